@@ -0,0 +1,103 @@
+-- ベースラインのスキーマ。mysql/init/init.sql に mysql/migration/0_sample.sql を適用した状態と同じ。
+-- schema_migrations がなく users テーブルが存在する DB では、実行せずに適用済みとして記録する
+
+CREATE TABLE areas (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    name VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE users (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    username VARCHAR(255) NOT NULL,
+    password VARCHAR(255) NOT NULL DEFAULT '$argon2id$v=19$m=19456,t=2,p=1$XATPp8QqqTtg3VrdJ/QPfw$r3o9L6zWQc/Zq70GbP33Gl9N50jGUSMMvYcl7M05ukw',
+    profile_image VARCHAR(255) NOT NULL DEFAULT 'default.png',
+    role VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE sessions (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    user_id INT NOT NULL,
+    session_token VARCHAR(255) NOT NULL,
+    is_valid BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+CREATE TABLE dispatchers (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    user_id INT NOT NULL,
+    area_id INT NOT NULL
+);
+
+CREATE TABLE tow_trucks (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    driver_id INT NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'available',
+    area_id INT NOT NULL
+);
+
+CREATE TABLE nodes (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    area_id INT NOT NULL,
+    x INT NOT NULL,
+    y INT NOT NULL
+);
+
+CREATE TABLE edges (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    node_a_id INT NOT NULL,
+    node_b_id INT NOT NULL,
+    weight INT NOT NULL,
+    UNIQUE (node_a_id, node_b_id)
+);
+
+CREATE TABLE locations (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    tow_truck_id INT NOT NULL,
+    node_id INT NOT NULL,
+    timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE orders (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    client_id INT NOT NULL,
+    dispatcher_id INT,
+    tow_truck_id INT,
+    status VARCHAR(50) NOT NULL DEFAULT 'pending',
+    node_id INT NOT NULL,
+    car_value DOUBLE NOT NULL,
+    order_time DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    completed_time DATETIME,
+    FOREIGN KEY (client_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (dispatcher_id) REFERENCES dispatchers(id) ON DELETE CASCADE,
+    FOREIGN KEY (tow_truck_id) REFERENCES tow_trucks(id) ON DELETE CASCADE,
+    FOREIGN KEY (node_id) REFERENCES nodes(id) ON DELETE CASCADE
+);
+
+CREATE TABLE completed_orders (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    order_id INT NOT NULL UNIQUE,
+    tow_truck_id INT NOT NULL UNIQUE,
+    completed_time DATETIME NOT NULL,
+    FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE,
+    FOREIGN KEY (tow_truck_id) REFERENCES tow_trucks(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_users_username ON users (username);
+CREATE INDEX idx_users_password ON users (password);
+CREATE INDEX idx_tow_trucks_id_driver_id ON tow_trucks (id, driver_id);
+CREATE INDEX idx_locations_tow_truck_id_timestamp ON locations (tow_truck_id, timestamp);
+CREATE INDEX idx_tow_trucks_status_id ON tow_trucks (status, id);
+CREATE INDEX idx_tow_trucks_status_area_id_id ON tow_trucks (status, area_id, id);
+CREATE INDEX idx_orders_status_order_time ON orders (status, order_time);
+CREATE INDEX idx_nodes_area_id ON nodes (area_id);
+CREATE INDEX idx_tow_trucks_id ON tow_trucks (id);
+CREATE INDEX idx_sessions_session_token ON sessions (session_token);
+CREATE INDEX idx_orders_node_id ON orders (node_id);
+CREATE INDEX idx_orders_client_id ON orders (client_id);
+CREATE INDEX idx_orders_dispatcher_id ON orders (dispatcher_id);
+CREATE INDEX idx_orders_tow_truck_id ON orders (tow_truck_id);
+CREATE INDEX idx_dispatchers_id ON dispatchers (id);
+CREATE INDEX idx_dispatchers_user_id ON dispatchers (user_id);
+CREATE INDEX idx_orders_area_id ON orders (node_id);
+CREATE INDEX idx_orders_status ON orders (status);
+CREATE INDEX idx_orders_order_time ON orders (order_time);
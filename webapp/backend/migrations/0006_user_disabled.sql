@@ -0,0 +1,2 @@
+-- 管理者によるアカウントの無効化
+ALTER TABLE users ADD COLUMN is_disabled BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,148 @@
+-- ローカル開発・テスト用の SQLite のスキーマ。migrations/ の MySQL のスキーマと同じ構成にする
+-- データは DATABASE_SEED_DIR の CSV から読み込む
+
+CREATE TABLE IF NOT EXISTS areas (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username VARCHAR(255) NOT NULL,
+    password VARCHAR(255) NOT NULL DEFAULT '$argon2id$v=19$m=19456,t=2,p=1$XATPp8QqqTtg3VrdJ/QPfw$r3o9L6zWQc/Zq70GbP33Gl9N50jGUSMMvYcl7M05ukw',
+    profile_image VARCHAR(255) NOT NULL DEFAULT 'default.png',
+    role VARCHAR(255) NOT NULL,
+    is_disabled BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INT NOT NULL,
+    session_token VARCHAR(255) NOT NULL,
+    is_valid BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_seen_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_agent VARCHAR(512),
+    ip_address VARCHAR(45)
+);
+
+CREATE TABLE IF NOT EXISTS dispatchers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INT NOT NULL,
+    area_id INT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS tow_trucks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    driver_id INT NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'available',
+    area_id INT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS nodes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(255) NOT NULL,
+    area_id INT NOT NULL,
+    x INT NOT NULL,
+    y INT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS edges (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    node_a_id INT NOT NULL,
+    node_b_id INT NOT NULL,
+    weight INT NOT NULL,
+    UNIQUE (node_a_id, node_b_id)
+);
+
+CREATE TABLE IF NOT EXISTS locations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    tow_truck_id INT NOT NULL,
+    node_id INT NOT NULL,
+    timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS orders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_id INT NOT NULL,
+    dispatcher_id INT,
+    tow_truck_id INT,
+    status VARCHAR(50) NOT NULL DEFAULT 'pending',
+    node_id INT NOT NULL,
+    car_value DOUBLE NOT NULL,
+    order_time DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    completed_time DATETIME,
+    FOREIGN KEY (client_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (dispatcher_id) REFERENCES dispatchers(id) ON DELETE CASCADE,
+    FOREIGN KEY (tow_truck_id) REFERENCES tow_trucks(id) ON DELETE CASCADE,
+    FOREIGN KEY (node_id) REFERENCES nodes(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS completed_orders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    order_id INT NOT NULL UNIQUE,
+    tow_truck_id INT NOT NULL UNIQUE,
+    completed_time DATETIME NOT NULL,
+    FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE,
+    FOREIGN KEY (tow_truck_id) REFERENCES tow_trucks(id) ON DELETE CASCADE
+);
+
+-- ドライバーへの依頼オファー（承諾・辞退・タイムアウトの履歴）
+CREATE TABLE IF NOT EXISTS order_offers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    order_id INT NOT NULL,
+    tow_truck_id INT NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'offered',
+    offered_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    responded_at DATETIME,
+    FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE,
+    FOREIGN KEY (tow_truck_id) REFERENCES tow_trucks(id) ON DELETE CASCADE
+);
+
+-- 管理者が発行するパスワードリセット用のワンタイムトークン（ハッシュ化して保存）
+CREATE TABLE IF NOT EXISTS password_reset_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INT NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    expires_at DATETIME NOT NULL,
+    used_at DATETIME,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- 外部システム連携用の API キー（ハッシュ化して保存）。scopes はカンマ区切り
+CREATE TABLE IF NOT EXISTS api_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(255) NOT NULL,
+    key_hash VARCHAR(64) NOT NULL UNIQUE,
+    scopes VARCHAR(255) NOT NULL,
+    created_by INT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    revoked_at DATETIME,
+    FOREIGN KEY (created_by) REFERENCES users(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_users_username ON users (username);
+CREATE INDEX IF NOT EXISTS idx_users_password ON users (password);
+CREATE INDEX IF NOT EXISTS idx_tow_trucks_id_driver_id ON tow_trucks (id, driver_id);
+CREATE INDEX IF NOT EXISTS idx_locations_tow_truck_id_timestamp ON locations (tow_truck_id, timestamp);
+CREATE INDEX IF NOT EXISTS idx_tow_trucks_status_id ON tow_trucks (status, id);
+CREATE INDEX IF NOT EXISTS idx_tow_trucks_status_area_id_id ON tow_trucks (status, area_id, id);
+CREATE INDEX IF NOT EXISTS idx_orders_status_order_time ON orders (status, order_time);
+CREATE INDEX IF NOT EXISTS idx_nodes_area_id ON nodes (area_id);
+CREATE INDEX IF NOT EXISTS idx_tow_trucks_id ON tow_trucks (id);
+CREATE INDEX IF NOT EXISTS idx_sessions_session_token ON sessions (session_token);
+CREATE INDEX IF NOT EXISTS idx_orders_node_id ON orders (node_id);
+CREATE INDEX IF NOT EXISTS idx_orders_client_id ON orders (client_id);
+CREATE INDEX IF NOT EXISTS idx_orders_dispatcher_id ON orders (dispatcher_id);
+CREATE INDEX IF NOT EXISTS idx_orders_tow_truck_id ON orders (tow_truck_id);
+CREATE INDEX IF NOT EXISTS idx_dispatchers_id ON dispatchers (id);
+CREATE INDEX IF NOT EXISTS idx_dispatchers_user_id ON dispatchers (user_id);
+CREATE INDEX IF NOT EXISTS idx_orders_area_id ON orders (node_id);
+CREATE INDEX IF NOT EXISTS idx_orders_status ON orders (status);
+CREATE INDEX IF NOT EXISTS idx_orders_order_time ON orders (order_time);
+CREATE INDEX IF NOT EXISTS idx_order_offers_order_id ON order_offers (order_id);
+CREATE INDEX IF NOT EXISTS idx_order_offers_status_offered_at ON order_offers (status, offered_at);
+CREATE INDEX IF NOT EXISTS idx_sessions_created_at ON sessions (created_at);
+CREATE INDEX IF NOT EXISTS idx_sessions_last_seen_at ON sessions (last_seen_at);
+CREATE INDEX IF NOT EXISTS idx_sessions_user_id ON sessions (user_id);
@@ -1,15 +1,36 @@
-use crate::domains::auth_service::AuthService;
-use crate::domains::dto::auth::{LoginRequestDto, LogoutRequestDto, RegisterRequestDto};
+use crate::domains::auth_service::{AuthService, MAX_PROFILE_IMAGE_BYTES};
+use crate::domains::dto::auth::{
+    ChangeAreaRequestDto, ChangePasswordRequestDto, ChangeRoleRequestDto, ClientInfoDto,
+    CreateApiKeyRequestDto, LoginRequestDto, LogoutRequestDto, RefreshTokenRequestDto,
+    RegisterRequestDto, ResetPasswordRequestDto,
+};
 use crate::errors::AppError;
+use crate::infrastructure::image_resizer::{ImageOutputFormat, DEFAULT_PROFILE_IMAGE_SIZE};
+use crate::infrastructure::trusted_proxies::TrustedProxies;
+use crate::models::user::AuthenticatedUser;
 use crate::repositories::auth_repository::AuthRepositoryImpl;
-use actix_web::{web, HttpResponse};
+use actix_multipart::Multipart;
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use std::net::IpAddr;
+use tracing::instrument;
 
+#[instrument(skip_all)]
 pub async fn register_handler(
     service: web::Data<AuthService<AuthRepositoryImpl>>,
+    trusted_proxies: web::Data<TrustedProxies>,
+    http_req: HttpRequest,
     req: web::Json<RegisterRequestDto>,
 ) -> Result<HttpResponse, AppError> {
     match service
-        .register_user(&req.username, &req.password, &req.role, req.area_id)
+        .register_user(
+            &req.username,
+            &req.password,
+            &req.role,
+            req.area_id,
+            &client_info(&http_req, &trusted_proxies),
+        )
         .await
     {
         Ok(response) => Ok(HttpResponse::Created().json(response)),
@@ -17,16 +38,51 @@ pub async fn register_handler(
     }
 }
 
+// 信頼するプロキシ（nginx）からの接続では X-Real-IP を使い、それ以外は接続元アドレスを使う。
+// X-Real-IP・X-Forwarded-For はクライアントが任意の値を入れられるため、直接接続では信頼しない。
+// X-Real-IP が IP アドレスとして解釈できない場合は、カラム長を超えないよう接続元アドレスを使う
+fn client_info(http_req: &HttpRequest, trusted_proxies: &TrustedProxies) -> ClientInfoDto {
+    let peer_ip = http_req.peer_addr().map(|addr| addr.ip());
+    let ip_address = peer_ip
+        .filter(|ip| trusted_proxies.contains(*ip))
+        .and_then(|_| http_req.headers().get("X-Real-IP"))
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.trim().parse::<IpAddr>().ok())
+        .or(peer_ip)
+        .map(|ip| ip.to_string());
+    let user_agent = http_req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.chars().take(512).collect());
+
+    ClientInfoDto {
+        ip_address,
+        user_agent,
+    }
+}
+
+#[instrument(skip_all)]
 pub async fn login_handler(
     service: web::Data<AuthService<AuthRepositoryImpl>>,
+    trusted_proxies: web::Data<TrustedProxies>,
+    http_req: HttpRequest,
     req: web::Json<LoginRequestDto>,
 ) -> Result<HttpResponse, AppError> {
-    match service.login_user(&req.username, &req.password).await {
+    match service
+        .login_user(
+            &req.username,
+            &req.password,
+            &client_info(&http_req, &trusted_proxies),
+        )
+        .await
+    {
         Ok(response) => Ok(HttpResponse::Ok().json(response)),
         Err(err) => Err(err),
     }
 }
 
+#[instrument(skip_all)]
 pub async fn logout_handler(
     service: web::Data<AuthService<AuthRepositoryImpl>>,
     req: web::Json<LogoutRequestDto>,
@@ -37,13 +93,362 @@ pub async fn logout_handler(
     }
 }
 
+#[instrument(skip_all)]
+pub async fn refresh_token_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    req: web::Json<RefreshTokenRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    match service.refresh_access_token(&req.refresh_token).await {
+        Ok(response) => Ok(HttpResponse::Ok().json(response)),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn get_my_sessions_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    match service.get_sessions(user.user_id).await {
+        Ok(sessions) => Ok(HttpResponse::Ok().json(sessions)),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn revoke_my_session_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    match service
+        .revoke_session(user.user_id, path.into_inner())
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn revoke_my_sessions_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    match service.invalidate_user_sessions(user.user_id).await {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn get_user_sessions_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    match service.get_sessions(path.into_inner()).await {
+        Ok(sessions) => Ok(HttpResponse::Ok().json(sessions)),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn revoke_user_session_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, AppError> {
+    let (user_id, session_id) = path.into_inner();
+    match service.revoke_session(user_id, session_id).await {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn revoke_user_sessions_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    match service.invalidate_user_sessions(path.into_inner()).await {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn change_password_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    user: AuthenticatedUser,
+    req: web::Json<ChangePasswordRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    match service
+        .change_password(user.user_id, &req.old_password, &req.new_password)
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn issue_password_reset_token_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    match service.issue_password_reset_token(path.into_inner()).await {
+        Ok(response) => Ok(HttpResponse::Created().json(response)),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn unlock_user_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    match service.unlock_user(path.into_inner()).await {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PaginatedUserQuery {
+    page: Option<i32>,
+    page_size: Option<i32>,
+    role: Option<String>,
+}
+
+#[instrument(skip_all)]
+pub async fn get_users_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    query: web::Query<PaginatedUserQuery>,
+) -> Result<HttpResponse, AppError> {
+    match service
+        .get_users(
+            query.page.unwrap_or(0),
+            query.page_size.unwrap_or(20),
+            query.role.clone(),
+        )
+        .await
+    {
+        Ok(users) => Ok(HttpResponse::Ok().json(users)),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn disable_user_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    match service
+        .set_user_disabled(user.user_id, path.into_inner(), true)
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn enable_user_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    match service
+        .set_user_disabled(user.user_id, path.into_inner(), false)
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn change_user_role_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    req: web::Json<ChangeRoleRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    match service
+        .change_user_role(
+            user.user_id,
+            path.into_inner(),
+            &req.role,
+            req.area_id,
+            req.node_id,
+        )
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn move_dispatcher_area_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    path: web::Path<i32>,
+    req: web::Json<ChangeAreaRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    match service
+        .move_dispatcher_area(path.into_inner(), req.area_id)
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn create_api_key_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    user: AuthenticatedUser,
+    req: web::Json<CreateApiKeyRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    match service
+        .create_api_key(user.user_id, &req.name, &req.scopes)
+        .await
+    {
+        Ok(response) => Ok(HttpResponse::Created().json(response)),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn get_api_keys_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+) -> Result<HttpResponse, AppError> {
+    match service.get_api_keys().await {
+        Ok(api_keys) => Ok(HttpResponse::Ok().json(api_keys)),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn revoke_api_key_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    match service.revoke_api_key(path.into_inner()).await {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn reset_password_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    req: web::Json<ResetPasswordRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    match service
+        .reset_password(&req.reset_token, &req.new_password)
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+// multipart/form-data の image フィールドを上限サイズまで読み込む
+#[instrument(skip_all)]
+pub async fn upload_profile_image_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    user: AuthenticatedUser,
+    mut payload: Multipart,
+) -> Result<HttpResponse, AppError> {
+    let mut image: Option<Vec<u8>> = None;
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|_| AppError::BadRequest)?;
+        if field.name() != "image" {
+            continue;
+        }
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|_| AppError::BadRequest)?;
+            if bytes.len() + chunk.len() > MAX_PROFILE_IMAGE_BYTES {
+                return Err(AppError::PayloadTooLarge);
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        image = Some(bytes);
+    }
+
+    let image = image.ok_or(AppError::BadRequest)?;
+    match service
+        .update_profile_image(user.user_id, web::Bytes::from(image))
+        .await
+    {
+        Ok(response) => Ok(HttpResponse::Ok().json(response)),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn image_cache_stats_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl>>,
+) -> Result<HttpResponse, AppError> {
+    Ok(HttpResponse::Ok().json(service.image_cache_stats()))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ProfileImageQuery {
+    size: Option<u32>,
+}
+
+#[instrument(skip_all)]
 pub async fn user_profile_image_handler(
     service: web::Data<AuthService<AuthRepositoryImpl>>,
+    http_req: HttpRequest,
     path: web::Path<i32>,
+    query: web::Query<ProfileImageQuery>,
 ) -> Result<HttpResponse, AppError> {
     let user_id = path.into_inner();
-    let profile_image_byte = service.get_resized_profile_image_byte(user_id).await?;
-    Ok(HttpResponse::Ok()
-        .content_type("image/png")
-        .body(profile_image_byte))
+    let accept = http_req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|h| h.to_str().ok());
+    let format = ImageOutputFormat::from_accept(accept);
+
+    let profile_image = service
+        .get_resized_profile_image(
+            user_id,
+            query.size.unwrap_or(DEFAULT_PROFILE_IMAGE_SIZE),
+            format,
+        )
+        .await?;
+
+    // 画像は差し替えられる可能性があるため、短時間だけキャッシュさせて以降は ETag で再検証させる
+    let is_not_modified = http_req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|if_none_match| {
+            if_none_match
+                .split(',')
+                .any(|etag| etag.trim() == profile_image.etag || etag.trim() == "*")
+        });
+
+    let mut response = if is_not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header((header::ETAG, profile_image.etag.clone()))
+        .insert_header((header::CACHE_CONTROL, "public, max-age=60"))
+        .insert_header((header::VARY, "Accept"));
+
+    if is_not_modified {
+        Ok(response.finish())
+    } else {
+        Ok(response
+            .content_type(profile_image.content_type)
+            .body(profile_image.bytes))
+    }
 }
@@ -0,0 +1,46 @@
+use crate::domains::tow_truck_service::TowTruckService;
+use crate::errors::AppError;
+use crate::models::user::AuthenticatedUser;
+use crate::repositories::map_repository::MapRepositoryImpl;
+use crate::repositories::order_repository::OrderRepositoryImpl;
+use crate::repositories::tow_truck_repository::TowTruckRepositoryImpl;
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use tracing::instrument;
+
+#[instrument(skip_all)]
+pub async fn get_my_job_handler(
+    service: web::Data<
+        TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>,
+    >,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let job = service.get_driver_job(user.user_id).await?;
+
+    Ok(HttpResponse::Ok().json(job))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct JobHistoryQuery {
+    page: Option<i32>,
+    page_size: Option<i32>,
+}
+
+#[instrument(skip_all)]
+pub async fn get_my_job_history_handler(
+    service: web::Data<
+        TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>,
+    >,
+    user: AuthenticatedUser,
+    query: web::Query<JobHistoryQuery>,
+) -> Result<HttpResponse, AppError> {
+    let orders = service
+        .get_driver_job_history(
+            user.user_id,
+            query.page.unwrap_or(0),
+            query.page_size.unwrap_or(10),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(orders))
+}
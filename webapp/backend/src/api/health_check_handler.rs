@@ -1,14 +1,29 @@
+use crate::domains::health_service::HealthService;
 use crate::errors::AppError;
-use actix_web::HttpResponse;
+use crate::repositories::health_repository::HealthRepositoryImpl;
+use actix_web::{web, HttpResponse};
 use serde::Serialize;
+use tracing::instrument;
 
 #[derive(Serialize)]
 struct HealthCheckResponse {
     status: String,
 }
 
+// プロセスが応答できるかだけを返し、DB などの依存先は確認しない
+#[instrument(skip_all)]
 pub async fn health_check_handler() -> Result<HttpResponse, AppError> {
     Ok(HttpResponse::Ok().json(HealthCheckResponse {
         status: "OK".to_string(),
     }))
 }
+
+#[instrument(skip_all)]
+pub async fn readiness_handler(
+    service: web::Data<HealthService<HealthRepositoryImpl>>,
+) -> Result<HttpResponse, AppError> {
+    match service.check_readiness().await {
+        (true, readiness) => Ok(HttpResponse::Ok().json(readiness)),
+        (false, readiness) => Ok(HttpResponse::ServiceUnavailable().json(readiness)),
+    }
+}
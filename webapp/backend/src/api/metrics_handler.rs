@@ -0,0 +1,19 @@
+use crate::domains::auth_service::AuthService;
+use crate::domains::metrics_service::MetricsService;
+use crate::errors::AppError;
+use crate::infrastructure::metrics::TEXT_FORMAT;
+use crate::repositories::auth_repository::AuthRepositoryImpl;
+use crate::repositories::metrics_repository::MetricsRepositoryImpl;
+use actix_web::{web, HttpResponse};
+use tracing::instrument;
+
+#[instrument(skip_all)]
+pub async fn metrics_handler(
+    service: web::Data<MetricsService<MetricsRepositoryImpl>>,
+    auth_service: web::Data<AuthService<AuthRepositoryImpl>>,
+) -> Result<HttpResponse, AppError> {
+    match service.render(auth_service.image_cache_stats()).await {
+        Ok(body) => Ok(HttpResponse::Ok().content_type(TEXT_FORMAT).body(body)),
+        Err(err) => Err(err),
+    }
+}
@@ -1,6 +1,8 @@
 pub mod auth_handler;
+pub mod driver_handler;
 pub mod health_check_handler;
 pub mod map_handler;
+pub mod metrics_handler;
 pub mod order_handler;
 pub mod result_handler;
 pub mod tow_truck_handler;
@@ -1,15 +1,37 @@
+use crate::api::tow_truck_handler::authorize_tow_truck;
 use crate::domains::dto::order::{
-    ClientOrderRequestDto, DispatcherOrderRequestDto, UpdateOrderStatusRequestDto,
+    ClientOrderRequestDto, DispatcherOrderRequestDto, OrderDto, OrderOfferRequestDto,
+    UpdateOrderStatusRequestDto,
 };
 use crate::domains::order_service::OrderService;
+use crate::domains::tow_truck_service::TowTruckService;
 use crate::errors::AppError;
+use crate::models::user::AuthenticatedUser;
 use crate::repositories::auth_repository::AuthRepositoryImpl;
 use crate::repositories::map_repository::MapRepositoryImpl;
 use crate::repositories::order_repository::OrderRepositoryImpl;
 use crate::repositories::tow_truck_repository::TowTruckRepositoryImpl;
 use actix_web::{web, HttpResponse};
 use serde::Deserialize;
+use tracing::instrument;
 
+pub fn authorize_order(user: &AuthenticatedUser, order: &OrderDto) -> Result<(), AppError> {
+    let is_allowed = match user.role.as_str() {
+        "admin" | "api_key" => true,
+        "dispatcher" => user.can_access_area(order.area_id),
+        "driver" => order.driver_user_id == Some(user.user_id),
+        "client" => order.client_id == user.user_id,
+        _ => false,
+    };
+
+    if is_allowed {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden)
+    }
+}
+
+#[instrument(skip_all)]
 pub async fn update_order_status_handler(
     service: web::Data<
         OrderService<
@@ -19,14 +41,19 @@ pub async fn update_order_status_handler(
             MapRepositoryImpl,
         >,
     >,
+    user: AuthenticatedUser,
     req: web::Json<UpdateOrderStatusRequestDto>,
 ) -> Result<HttpResponse, AppError> {
+    let order = service.get_order_by_id(req.order_id).await?;
+    authorize_order(&user, &order)?;
+
     match service.update_order_status(req.order_id, &req.status).await {
         Ok(_) => Ok(HttpResponse::Ok().finish()),
         Err(err) => Err(err),
     }
 }
 
+#[instrument(skip_all)]
 pub async fn get_order_handler(
     service: web::Data<
         OrderService<
@@ -36,10 +63,14 @@ pub async fn get_order_handler(
             MapRepositoryImpl,
         >,
     >,
+    user: AuthenticatedUser,
     path: web::Path<i32>,
 ) -> Result<HttpResponse, AppError> {
     match service.get_order_by_id(path.into_inner()).await {
-        Ok(order) => Ok(HttpResponse::Ok().json(order)),
+        Ok(order) => {
+            authorize_order(&user, &order)?;
+            Ok(HttpResponse::Ok().json(order))
+        }
         Err(err) => Err(err),
     }
 }
@@ -54,6 +85,7 @@ pub struct PaginatedOrderQuery {
     area: Option<i32>,
 }
 
+#[instrument(skip_all)]
 pub async fn get_paginated_orders_handler(
     service: web::Data<
         OrderService<
@@ -63,8 +95,10 @@ pub async fn get_paginated_orders_handler(
             MapRepositoryImpl,
         >,
     >,
+    user: AuthenticatedUser,
     query: web::Query<PaginatedOrderQuery>,
 ) -> Result<HttpResponse, AppError> {
+    let area = user.restrict_area(query.area)?;
     match service
         .get_paginated_orders(
             query.page.unwrap_or(0),
@@ -72,7 +106,7 @@ pub async fn get_paginated_orders_handler(
             query.sort_by.clone(),
             query.sort_order.clone(),
             query.status.clone(),
-            query.area,
+            area,
         )
         .await
     {
@@ -81,6 +115,7 @@ pub async fn get_paginated_orders_handler(
     }
 }
 
+#[instrument(skip_all)]
 pub async fn create_client_order_handler(
     service: web::Data<
         OrderService<
@@ -90,10 +125,13 @@ pub async fn create_client_order_handler(
             MapRepositoryImpl,
         >,
     >,
+    user: AuthenticatedUser,
     req: web::Json<ClientOrderRequestDto>,
 ) -> Result<HttpResponse, AppError> {
+    let client_id = user.resolve_actor_id(Some(user.user_id), req.client_id)?;
+
     match service
-        .create_client_order(req.client_id, req.node_id, req.car_value)
+        .create_client_order(client_id, req.node_id, req.car_value)
         .await
     {
         Ok(_) => Ok(HttpResponse::Created().finish()),
@@ -101,6 +139,7 @@ pub async fn create_client_order_handler(
     }
 }
 
+#[instrument(skip_all)]
 pub async fn create_dispatcher_order_handler(
     service: web::Data<
         OrderService<
@@ -110,15 +149,85 @@ pub async fn create_dispatcher_order_handler(
             MapRepositoryImpl,
         >,
     >,
+    tow_truck_service: web::Data<
+        TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>,
+    >,
+    user: AuthenticatedUser,
     req: web::Json<DispatcherOrderRequestDto>,
 ) -> Result<HttpResponse, AppError> {
+    let dispatcher_id = user.resolve_actor_id(user.dispatcher_id, req.dispatcher_id)?;
+    let order = service.get_order_by_id(req.order_id).await?;
+    authorize_order(&user, &order)?;
+    let tow_truck = tow_truck_service
+        .get_tow_truck_by_id(req.tow_truck_id)
+        .await?
+        .ok_or(AppError::BadRequest)?;
+    authorize_tow_truck(&user, &tow_truck)?;
+
     match service
-        .create_dispatcher_order(
-            req.order_id,
-            req.dispatcher_id,
-            req.tow_truck_id,
-            req.order_time,
-        )
+        .create_dispatcher_order(req.order_id, dispatcher_id, req.tow_truck_id)
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn accept_order_offer_handler(
+    service: web::Data<
+        OrderService<
+            OrderRepositoryImpl,
+            TowTruckRepositoryImpl,
+            AuthRepositoryImpl,
+            MapRepositoryImpl,
+        >,
+    >,
+    tow_truck_service: web::Data<
+        TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>,
+    >,
+    user: AuthenticatedUser,
+    req: web::Json<OrderOfferRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    let tow_truck = tow_truck_service
+        .get_tow_truck_by_id(req.tow_truck_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    authorize_tow_truck(&user, &tow_truck)?;
+
+    match service
+        .accept_order_offer(req.order_id, req.tow_truck_id)
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => Err(err),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn decline_order_offer_handler(
+    service: web::Data<
+        OrderService<
+            OrderRepositoryImpl,
+            TowTruckRepositoryImpl,
+            AuthRepositoryImpl,
+            MapRepositoryImpl,
+        >,
+    >,
+    tow_truck_service: web::Data<
+        TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>,
+    >,
+    user: AuthenticatedUser,
+    req: web::Json<OrderOfferRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    let tow_truck = tow_truck_service
+        .get_tow_truck_by_id(req.tow_truck_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    authorize_tow_truck(&user, &tow_truck)?;
+
+    match service
+        .decline_order_offer(req.order_id, req.tow_truck_id)
         .await
     {
         Ok(_) => Ok(HttpResponse::Ok().finish()),
@@ -7,7 +7,9 @@ use crate::{
     },
 };
 use actix_web::{web, HttpResponse};
+use tracing::instrument;
 
+#[instrument(skip_all)]
 pub async fn result_handler(
     service: web::Data<
         OrderService<
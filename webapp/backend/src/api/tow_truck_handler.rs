@@ -1,5 +1,10 @@
+use crate::api::order_handler::authorize_order;
+use crate::domains::dto::tow_truck::TowTruckDto;
+use crate::domains::order_service::OrderService;
 use crate::domains::tow_truck_service::TowTruckService;
 use crate::errors::AppError;
+use crate::models::user::AuthenticatedUser;
+use crate::repositories::auth_repository::AuthRepositoryImpl;
 use crate::repositories::order_repository::OrderRepositoryImpl;
 use crate::repositories::tow_truck_repository::TowTruckRepositoryImpl;
 use crate::{
@@ -8,6 +13,25 @@ use crate::{
 };
 use actix_web::{web, HttpResponse};
 use serde::Deserialize;
+use tracing::instrument;
+
+pub fn authorize_tow_truck(
+    user: &AuthenticatedUser,
+    tow_truck: &TowTruckDto,
+) -> Result<(), AppError> {
+    let is_allowed = match user.role.as_str() {
+        "admin" | "api_key" => true,
+        "dispatcher" => user.can_access_area(tow_truck.area_id),
+        "driver" => tow_truck.driver_user_id == user.user_id,
+        _ => false,
+    };
+
+    if is_allowed {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden)
+    }
+}
 
 #[derive(Deserialize, Debug)]
 pub struct PaginatedTowTruckQuery {
@@ -17,44 +41,60 @@ pub struct PaginatedTowTruckQuery {
     area: Option<i32>,
 }
 
+#[instrument(skip_all)]
 pub async fn get_paginated_tow_trucks_handler(
     service: web::Data<
         TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>,
     >,
+    user: AuthenticatedUser,
     query: web::Query<PaginatedTowTruckQuery>,
 ) -> Result<HttpResponse, AppError> {
+    let area = user.restrict_area(query.area)?;
     let tow_trucks = service
         .get_all_tow_trucks(
             query.page.unwrap_or(0),
             query.page_size.unwrap_or(-1),
             query.status.clone(),
-            query.area,
+            area,
         )
         .await?;
 
     Ok(HttpResponse::Ok().json(tow_trucks))
 }
 
+#[instrument(skip_all)]
 pub async fn get_tow_truck_handler(
     service: web::Data<
         TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>,
     >,
+    user: AuthenticatedUser,
     path: web::Path<i32>,
 ) -> Result<HttpResponse, AppError> {
     let id = path.into_inner();
     match service.get_tow_truck_by_id(id).await {
-        Ok(Some(tow_truck)) => Ok(HttpResponse::Ok().json(tow_truck)),
+        Ok(Some(tow_truck)) => {
+            authorize_tow_truck(&user, &tow_truck)?;
+            Ok(HttpResponse::Ok().json(tow_truck))
+        }
         Ok(None) => Ok(HttpResponse::NotFound().finish()),
         Err(err) => Err(err),
     }
 }
 
+#[instrument(skip_all)]
 pub async fn update_location_handler(
     service: web::Data<
         TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>,
     >,
+    user: AuthenticatedUser,
     req: web::Json<UpdateLocationRequestDto>,
 ) -> Result<HttpResponse, AppError> {
+    let tow_truck = service
+        .get_tow_truck_by_id(req.tow_truck_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    authorize_tow_truck(&user, &tow_truck)?;
+
     service
         .update_location(req.tow_truck_id, req.node_id)
         .await?;
@@ -66,12 +106,26 @@ pub struct TowTruckQuery {
     order_id: i32,
 }
 
+#[instrument(skip_all)]
 pub async fn get_nearest_available_tow_trucks_handler(
     service: web::Data<
         TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>,
     >,
+    order_service: web::Data<
+        OrderService<
+            OrderRepositoryImpl,
+            TowTruckRepositoryImpl,
+            AuthRepositoryImpl,
+            MapRepositoryImpl,
+        >,
+    >,
+    user: AuthenticatedUser,
     query: web::Query<TowTruckQuery>,
 ) -> Result<HttpResponse, AppError> {
+    // 他エリアの依頼で探索しないよう、最寄りのレッカー車を探す前に依頼のエリアを確認する
+    let order = order_service.get_order_by_id(query.order_id).await?;
+    authorize_order(&user, &order)?;
+
     match service
         .get_nearest_available_tow_trucks(query.order_id)
         .await
@@ -0,0 +1,367 @@
+use std::sync::Arc;
+
+use actix_cors::Cors;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::{web, App};
+
+use crate::api::{
+    auth_handler, driver_handler, health_check_handler, map_handler, metrics_handler,
+    order_handler, result_handler, tow_truck_handler,
+};
+use crate::config::{CorsSettings, Settings};
+use crate::domains::health_service::HealthService;
+use crate::domains::map_service::MapService;
+use crate::domains::metrics_service::MetricsService;
+use crate::domains::{
+    auth_service::AuthService, order_service::OrderService, tow_truck_service::TowTruckService,
+};
+use crate::infrastructure::db::DbPool;
+use crate::infrastructure::trusted_proxies::TrustedProxies;
+use crate::middlewares::auth_middleware::AuthMiddleware;
+use crate::middlewares::request_id::RequestId;
+use crate::middlewares::request_metrics::RequestMetrics;
+use crate::middlewares::role_guard::RoleGuard;
+use crate::repositories::auth_repository::AuthRepositoryImpl;
+use crate::repositories::health_repository::HealthRepositoryImpl;
+use crate::repositories::map_repository::MapRepositoryImpl;
+use crate::repositories::metrics_repository::MetricsRepositoryImpl;
+use crate::repositories::order_repository::OrderRepositoryImpl;
+use crate::repositories::tow_truck_repository::TowTruckRepositoryImpl;
+
+#[cfg(test)]
+mod tests;
+
+// 全ワーカーで共有するサービス。トークンキャッシュ・ログイン試行回数・画像キャッシュはサービスが保持する
+#[derive(Clone)]
+pub struct AppState {
+    pub auth_service: Arc<AuthService<AuthRepositoryImpl>>,
+    pub tow_truck_service:
+        web::Data<TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>>,
+    pub order_service: web::Data<
+        OrderService<
+            OrderRepositoryImpl,
+            TowTruckRepositoryImpl,
+            AuthRepositoryImpl,
+            MapRepositoryImpl,
+        >,
+    >,
+    pub map_service: web::Data<MapService<MapRepositoryImpl>>,
+    pub health_service: web::Data<HealthService<HealthRepositoryImpl>>,
+    pub metrics_service: web::Data<MetricsService<MetricsRepositoryImpl>>,
+    pub trusted_proxies: web::Data<TrustedProxies>,
+}
+
+impl AppState {
+    pub fn new(pool: &DbPool, settings: &Settings) -> Self {
+        AppState {
+            auth_service: Arc::new(AuthService::new(
+                AuthRepositoryImpl::new(pool.clone()),
+                settings,
+            )),
+            tow_truck_service: web::Data::new(TowTruckService::new(
+                TowTruckRepositoryImpl::new(pool.clone()),
+                OrderRepositoryImpl::new(pool.clone()),
+                MapRepositoryImpl::new(pool.clone()),
+                settings,
+            )),
+            order_service: web::Data::new(OrderService::new(
+                OrderRepositoryImpl::new(pool.clone()),
+                TowTruckRepositoryImpl::new(pool.clone()),
+                AuthRepositoryImpl::new(pool.clone()),
+                MapRepositoryImpl::new(pool.clone()),
+                settings,
+            )),
+            map_service: web::Data::new(MapService::new(MapRepositoryImpl::new(pool.clone()))),
+            health_service: web::Data::new(HealthService::new(
+                HealthRepositoryImpl::new(pool.clone()),
+                settings,
+            )),
+            metrics_service: web::Data::new(MetricsService::new(
+                MetricsRepositoryImpl::new(pool.clone()),
+                settings,
+            )),
+            // 起動時に検証済みのため、ここで失敗することはない
+            trusted_proxies: web::Data::new(settings.server.trusted_proxies().unwrap_or_default()),
+        }
+    }
+}
+
+fn build_cors(cors_settings: &CorsSettings) -> Cors {
+    let mut cors = Cors::default();
+    for origin in &cors_settings.allowed_origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+
+    cors = cors
+        .allowed_methods(cors_settings.allowed_methods.iter().map(String::as_str))
+        .allowed_headers(vec![
+            actix_web::http::header::AUTHORIZATION,
+            actix_web::http::header::ACCEPT,
+        ])
+        .allowed_header(actix_web::http::header::CONTENT_TYPE)
+        .supports_credentials()
+        .max_age(cors_settings.max_age_secs);
+
+    cors
+}
+
+// HttpServer のワーカーごとに呼ばれる。テストでも同じルーティングを使う
+pub fn create_app(
+    state: &AppState,
+    cors_settings: &CorsSettings,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .app_data(state.tow_truck_service.clone())
+        .app_data(web::Data::from(state.auth_service.clone()))
+        .app_data(state.order_service.clone())
+        .app_data(state.map_service.clone())
+        .app_data(state.health_service.clone())
+        .app_data(state.metrics_service.clone())
+        .app_data(state.trusted_proxies.clone())
+        .wrap(build_cors(cors_settings))
+        .wrap(RequestMetrics)
+        .wrap(RequestId)
+        // Prometheus からのスクレイプ用。admin のほか metrics:read スコープの API キーで参照できる
+        .service(
+            web::resource("/metrics")
+                .wrap(RoleGuard::new(&["admin"]).scope("metrics:read"))
+                .wrap(AuthMiddleware::new(state.auth_service.clone()))
+                .route(web::get().to(metrics_handler::metrics_handler)),
+        )
+        .service(
+            web::scope("/api")
+                .service(
+                    web::resource("/health_check")
+                        .route(web::get().to(health_check_handler::health_check_handler)),
+                )
+                .service(
+                    web::resource("/health/live")
+                        .route(web::get().to(health_check_handler::health_check_handler)),
+                )
+                .service(
+                    web::resource("/health/ready")
+                        .route(web::get().to(health_check_handler::readiness_handler)),
+                )
+                .service(
+                    web::resource("/result").route(web::get().to(result_handler::result_handler)),
+                )
+                .service(
+                    web::resource("/register")
+                        .route(web::post().to(auth_handler::register_handler)),
+                )
+                .service(web::resource("/login").route(web::post().to(auth_handler::login_handler)))
+                .service(
+                    web::resource("/logout").route(web::post().to(auth_handler::logout_handler)),
+                )
+                .service(
+                    web::resource("/token/refresh")
+                        .route(web::post().to(auth_handler::refresh_token_handler)),
+                )
+                .service(
+                    web::resource("/password_reset")
+                        .route(web::post().to(auth_handler::reset_password_handler)),
+                )
+                .service(
+                    web::resource("/user_image/{user_id}")
+                        .route(web::get().to(auth_handler::user_profile_image_handler)),
+                )
+                .service(
+                    web::scope("/account")
+                        .wrap(RoleGuard::new(&["client", "dispatcher", "driver", "admin"]))
+                        .wrap(AuthMiddleware::new(state.auth_service.clone()))
+                        .service(
+                            web::resource("/password")
+                                .route(web::put().to(auth_handler::change_password_handler)),
+                        )
+                        .service(
+                            web::resource("/sessions")
+                                .route(web::get().to(auth_handler::get_my_sessions_handler))
+                                .route(web::delete().to(auth_handler::revoke_my_sessions_handler)),
+                        )
+                        .service(
+                            web::resource("/sessions/{session_id}")
+                                .route(web::delete().to(auth_handler::revoke_my_session_handler)),
+                        )
+                        .service(
+                            web::resource("/profile_image")
+                                .route(web::post().to(auth_handler::upload_profile_image_handler)),
+                        ),
+                )
+                .service(
+                    web::scope("/admin")
+                        .wrap(RoleGuard::new(&["admin"]))
+                        .wrap(AuthMiddleware::new(state.auth_service.clone()))
+                        .service(
+                            web::resource("/users")
+                                .route(web::get().to(auth_handler::get_users_handler)),
+                        )
+                        .service(
+                            web::resource("/users/{id}/disable")
+                                .route(web::post().to(auth_handler::disable_user_handler)),
+                        )
+                        .service(
+                            web::resource("/users/{id}/enable")
+                                .route(web::post().to(auth_handler::enable_user_handler)),
+                        )
+                        .service(
+                            web::resource("/users/{id}/role")
+                                .route(web::put().to(auth_handler::change_user_role_handler)),
+                        )
+                        .service(
+                            web::resource("/users/{id}/area")
+                                .route(web::put().to(auth_handler::move_dispatcher_area_handler)),
+                        )
+                        .service(
+                            web::resource("/users/{id}/sessions")
+                                .route(web::get().to(auth_handler::get_user_sessions_handler))
+                                .route(
+                                    web::delete().to(auth_handler::revoke_user_sessions_handler),
+                                ),
+                        )
+                        .service(
+                            web::resource("/users/{id}/sessions/{session_id}")
+                                .route(web::delete().to(auth_handler::revoke_user_session_handler)),
+                        )
+                        .service(web::resource("/users/{id}/password_reset").route(
+                            web::post().to(auth_handler::issue_password_reset_token_handler),
+                        ))
+                        .service(
+                            web::resource("/users/{id}/unlock")
+                                .route(web::post().to(auth_handler::unlock_user_handler)),
+                        )
+                        .service(
+                            web::resource("/image_cache/stats")
+                                .route(web::get().to(auth_handler::image_cache_stats_handler)),
+                        )
+                        .service(
+                            web::resource("/api_keys")
+                                .route(web::get().to(auth_handler::get_api_keys_handler))
+                                .route(web::post().to(auth_handler::create_api_key_handler)),
+                        )
+                        .service(
+                            web::resource("/api_keys/{id}")
+                                .route(web::delete().to(auth_handler::revoke_api_key_handler)),
+                        ),
+                )
+                .service(
+                    web::scope("/tow_truck")
+                        .wrap(AuthMiddleware::new(state.auth_service.clone()))
+                        .service(
+                            web::resource("/list")
+                                .wrap(
+                                    RoleGuard::new(&["dispatcher", "admin"])
+                                        .scope("tow_trucks:read"),
+                                )
+                                .route(
+                                    web::get()
+                                        .to(tow_truck_handler::get_paginated_tow_trucks_handler),
+                                ),
+                        )
+                        .service(
+                            web::resource("/location")
+                                .wrap(RoleGuard::new(&["driver", "admin"]).scope("location:write"))
+                                .route(web::post().to(tow_truck_handler::update_location_handler)),
+                        )
+                        .service(
+                            web::resource("/nearest")
+                                .wrap(
+                                    RoleGuard::new(&["dispatcher", "admin"])
+                                        .scope("tow_trucks:read"),
+                                )
+                                .route(web::get().to(
+                                    tow_truck_handler::get_nearest_available_tow_trucks_handler,
+                                )),
+                        )
+                        .service(
+                            web::resource("/{id}")
+                                .wrap(
+                                    RoleGuard::new(&["dispatcher", "driver", "admin"])
+                                        .scope("tow_trucks:read"),
+                                )
+                                .route(web::get().to(tow_truck_handler::get_tow_truck_handler)),
+                        ),
+                )
+                .service(
+                    web::scope("/order")
+                        .wrap(AuthMiddleware::new(state.auth_service.clone()))
+                        .service(
+                            web::resource("/list")
+                                .wrap(RoleGuard::new(&["dispatcher", "admin"]).scope("orders:read"))
+                                .route(web::get().to(order_handler::get_paginated_orders_handler)),
+                        )
+                        .service(
+                            web::resource("/status")
+                                .wrap(
+                                    RoleGuard::new(&["dispatcher", "driver", "admin"])
+                                        .scope("orders:write"),
+                                )
+                                .route(web::post().to(order_handler::update_order_status_handler)),
+                        )
+                        .service(
+                            web::resource("/client")
+                                .wrap(RoleGuard::new(&["client", "admin"]))
+                                .route(web::post().to(order_handler::create_client_order_handler)),
+                        )
+                        .service(
+                            web::resource("/dispatcher")
+                                .wrap(RoleGuard::new(&["dispatcher", "admin"]))
+                                .route(
+                                    web::post().to(order_handler::create_dispatcher_order_handler),
+                                ),
+                        )
+                        .service(
+                            web::resource("/offer/accept")
+                                .wrap(RoleGuard::new(&["driver", "admin"]))
+                                .route(web::post().to(order_handler::accept_order_offer_handler)),
+                        )
+                        .service(
+                            web::resource("/offer/decline")
+                                .wrap(RoleGuard::new(&["driver", "admin"]))
+                                .route(web::post().to(order_handler::decline_order_offer_handler)),
+                        )
+                        .service(
+                            web::resource("/{id}")
+                                .wrap(
+                                    RoleGuard::new(&["client", "dispatcher", "driver", "admin"])
+                                        .scope("orders:read"),
+                                )
+                                .route(web::get().to(order_handler::get_order_handler)),
+                        ),
+                )
+                .service(
+                    web::scope("/driver")
+                        .wrap(RoleGuard::new(&["driver"]))
+                        .wrap(AuthMiddleware::new(state.auth_service.clone()))
+                        .service(
+                            web::resource("/me/job")
+                                .route(web::get().to(driver_handler::get_my_job_handler)),
+                        )
+                        .service(
+                            web::resource("/me/history")
+                                .route(web::get().to(driver_handler::get_my_job_history_handler)),
+                        ),
+                )
+                .service(
+                    web::scope("/map")
+                        .wrap(AuthMiddleware::new(state.auth_service.clone()))
+                        .service(
+                            web::resource("/update_edge")
+                                .wrap(RoleGuard::new(&["admin"]))
+                                .route(web::put().to(map_handler::update_edge_handler)),
+                        ),
+                ),
+        )
+}
@@ -0,0 +1,1475 @@
+use std::path::Path;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::{header, StatusCode};
+use actix_web::test::{self, TestRequest};
+use actix_web::web::Bytes;
+use serde_json::{json, Value};
+use sqlx::sqlite::SqlitePool;
+
+use super::{create_app, AppState};
+use crate::config::{CorsSettings, Settings, TokenMode};
+use crate::infrastructure::db::{create_pool, DbPool};
+use crate::infrastructure::sqlite;
+use crate::middlewares::request_id::REQUEST_ID_HEADER;
+
+// tests/fixtures/csv のユーザー ID
+const ADMIN: i32 = 1;
+const DISPATCHER1: i32 = 2;
+const DISPATCHER2: i32 = 3;
+const DRIVER1: i32 = 4;
+const DRIVER2: i32 = 5;
+const DRIVER3: i32 = 6;
+const CLIENT1: i32 = 7;
+const CLIENT2: i32 = 8;
+
+// インメモリの SQLite にフィクスチャを読み込み、本番と同じ構成のサービスを組み立てる
+async fn setup() -> (AppState, SqlitePool) {
+    setup_with(|_| {}).await
+}
+
+async fn setup_with(configure: impl FnOnce(&mut Settings)) -> (AppState, SqlitePool) {
+    let mut settings = Settings::default();
+    settings.database.url = "sqlite::memory:".to_string();
+    // テストではハッシュ計算のコストを最小にする
+    settings.auth.password_hash.memory_kib = 8;
+    settings.auth.password_hash.iterations = 1;
+    settings.auth.password_hash.parallelism = 1;
+    configure(&mut settings);
+
+    let pool = create_pool(&settings.database).await;
+    let sqlite_pool = match &pool {
+        DbPool::Sqlite(sqlite_pool) => sqlite_pool.clone(),
+        DbPool::MySql(_) => unreachable!(),
+    };
+    let fixture_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/csv");
+    sqlite::initialize(&sqlite_pool, Some(&fixture_dir))
+        .await
+        .unwrap();
+
+    (AppState::new(&pool, &settings), sqlite_pool)
+}
+
+// フィクスチャのユーザーはパスワードが分からないため、セッションを直接作成する
+async fn login_as(pool: &SqlitePool, user_id: i32) -> String {
+    let session_token = format!("test-session-{}", user_id);
+    sqlx::query("INSERT INTO sessions (user_id, session_token) VALUES (?, ?)")
+        .bind(user_id)
+        .bind(&session_token)
+        .execute(pool)
+        .await
+        .unwrap();
+    session_token
+}
+
+fn get(uri: &str, token: Option<&str>) -> TestRequest {
+    with_token(TestRequest::get().uri(uri), token)
+}
+
+fn post(uri: &str, token: Option<&str>) -> TestRequest {
+    with_token(TestRequest::post().uri(uri), token)
+}
+
+fn put(uri: &str, token: Option<&str>) -> TestRequest {
+    with_token(TestRequest::put().uri(uri), token)
+}
+
+fn delete(uri: &str, token: Option<&str>) -> TestRequest {
+    with_token(TestRequest::delete().uri(uri), token)
+}
+
+fn with_token(req: TestRequest, token: Option<&str>) -> TestRequest {
+    match token {
+        Some(token) => req.insert_header((header::AUTHORIZATION, token)),
+        None => req,
+    }
+}
+
+// ミドルウェアが返すエラー（401・403）もレスポンスと同じように扱う
+async fn send<S, R, B>(app: &S, req: R) -> (StatusCode, Bytes)
+where
+    S: Service<R, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    match test::try_call_service(app, req).await {
+        Ok(res) => {
+            let status = res.status();
+            (status, test::read_body(res).await)
+        }
+        Err(err) => (err.as_response_error().status_code(), Bytes::new()),
+    }
+}
+
+fn to_json(body: &Bytes) -> Value {
+    serde_json::from_slice(body).unwrap()
+}
+
+fn ids(body: &Bytes) -> Vec<i64> {
+    to_json(body)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["id"].as_i64().unwrap())
+        .collect()
+}
+
+fn multipart(name: &str, content_type: &str, content: &[u8]) -> (String, Vec<u8>) {
+    let boundary = "test-boundary";
+    let mut body = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"file\"\r\nContent-Type: {}\r\n\r\n",
+        boundary, name, content_type
+    )
+    .into_bytes();
+    body.extend_from_slice(content);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+    (format!("multipart/form-data; boundary={}", boundary), body)
+}
+
+#[actix_rt::test]
+async fn health_endpoints_respond() {
+    let (state, _) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+
+    let (status, _) = send(&app, get("/api/health/live", None).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(&app, get("/api/health/ready", None).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(to_json(&body)["database"]["status"], "ok");
+    assert_eq!(to_json(&body)["migrations"]["status"], "ok");
+}
+
+#[actix_rt::test]
+async fn seeded_database_has_no_sessions() {
+    let (_, pool) = setup().await;
+
+    let session_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sessions")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(session_count, 0);
+}
+
+#[actix_rt::test]
+async fn register_login_and_logout() {
+    let (state, _) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+
+    let credentials = json!({"username": "newclient", "password": "secret", "role": "client"});
+    let (status, body) = send(
+        &app,
+        post("/api/register", None)
+            .set_json(&credentials)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(to_json(&body)["role"], "client");
+
+    let (status, _) = send(
+        &app,
+        post("/api/register", None)
+            .set_json(&credentials)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _) = send(
+        &app,
+        post("/api/register", None)
+            .set_json(json!({"username": "newadmin", "password": "secret", "role": "admin"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = send(
+        &app,
+        post("/api/login", None)
+            .set_json(json!({"username": "newclient", "password": "wrong"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = send(
+        &app,
+        post("/api/login", None)
+            .set_json(json!({"username": "newclient", "password": "secret"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let session_token = to_json(&body)["session_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (status, _) = send(
+        &app,
+        get("/api/account/sessions", Some(&session_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        &app,
+        post("/api/logout", None)
+            .set_json(json!({"session_token": session_token}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        &app,
+        get("/api/account/sessions", Some(&session_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn token_refresh_is_not_available_in_session_mode() {
+    let (state, _) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+
+    let (status, _) = send(
+        &app,
+        post("/api/token/refresh", None)
+            .set_json(json!({"refresh_token": "unknown"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn token_refresh_rotates_refresh_token() {
+    let (state, pool) = setup_with(|settings| {
+        settings.auth.token_mode = TokenMode::Stateless;
+        settings.auth.access_token_keys = Some("k1:test-secret".to_string());
+    })
+    .await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let refresh_token = login_as(&pool, CLIENT1).await;
+
+    let (status, body) = send(
+        &app,
+        post("/api/token/refresh", None)
+            .set_json(json!({ "refresh_token": refresh_token }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let rotated_token = to_json(&body)["refresh_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_ne!(rotated_token, refresh_token);
+
+    // 使用済みのリフレッシュトークンは使えない
+    let (status, _) = send(
+        &app,
+        post("/api/token/refresh", None)
+            .set_json(json!({ "refresh_token": refresh_token }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // 無効化されたユーザーには再発行しない
+    sqlx::query("UPDATE users SET is_disabled = TRUE WHERE id = ?")
+        .bind(CLIENT1)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let (status, _) = send(
+        &app,
+        post("/api/token/refresh", None)
+            .set_json(json!({ "refresh_token": rotated_token }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn user_image_is_generated_for_default_profile() {
+    let (state, _) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+
+    let (status, body) = send(
+        &app,
+        get(&format!("/api/user_image/{}", CLIENT1), None).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!body.is_empty());
+
+    let (status, _) = send(&app, get("/api/user_image/999", None).to_request()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(
+        &app,
+        get(&format!("/api/user_image/{}?size=7", CLIENT1), None).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn account_routes_require_authentication() {
+    let (state, _) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+
+    for req in [
+        get("/api/account/sessions", None),
+        delete("/api/account/sessions", None),
+        delete("/api/account/sessions/1", None),
+        put("/api/account/password", None),
+        post("/api/account/profile_image", None),
+        get("/api/account/sessions", Some("invalid-token")),
+    ] {
+        let (status, _) = send(&app, req.to_request()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[actix_rt::test]
+async fn repeated_login_failures_return_too_many_requests() {
+    let (state, _) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let (status, _) = send(
+        &app,
+        post("/api/register", None)
+            .set_json(json!({"username": "newclient", "password": "secret", "role": "client"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // 5 回までは失敗してもロックせず、6 回目の失敗でロックする
+    for _ in 0..6 {
+        let (status, _) = send(
+            &app,
+            post("/api/login", None)
+                .set_json(json!({"username": "newclient", "password": "wrong"}))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    // ロック中は正しいパスワードでも拒否する
+    let res = test::call_service(
+        &app,
+        post("/api/login", None)
+            .set_json(json!({"username": "newclient", "password": "secret"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(res.headers().get(header::RETRY_AFTER).unwrap(), "1");
+}
+
+#[actix_rt::test]
+async fn real_ip_header_is_trusted_only_from_configured_proxy() {
+    let (state, _) = setup_with(|settings| {
+        settings.server.trusted_proxies = vec!["10.0.0.0/24".to_string()];
+    })
+    .await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+
+    for (username, peer_addr, expected_ip) in [
+        ("viaproxy", "10.0.0.2:40000", "203.0.113.5"),
+        ("direct", "192.0.2.9:40000", "192.0.2.9"),
+    ] {
+        let (status, body) = send(
+            &app,
+            post("/api/register", None)
+                .peer_addr(peer_addr.parse().unwrap())
+                .insert_header(("X-Real-IP", "203.0.113.5"))
+                .set_json(json!({"username": username, "password": "secret", "role": "client"}))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let session_token = to_json(&body)["session_token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let (status, body) = send(
+            &app,
+            get("/api/account/sessions", Some(&session_token)).to_request(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(to_json(&body)[0]["ip_address"], expected_ip);
+    }
+}
+
+#[actix_rt::test]
+async fn account_password_and_sessions() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+
+    let (_, body) = send(
+        &app,
+        post("/api/register", None)
+            .set_json(json!({"username": "newdriver", "password": "secret", "role": "client"}))
+            .to_request(),
+    )
+    .await;
+    let session_token = to_json(&body)["session_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (status, _) = send(
+        &app,
+        put("/api/account/password", Some(&session_token))
+            .set_json(json!({"old_password": "wrong", "new_password": "changed"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = send(
+        &app,
+        put("/api/account/password", Some(&session_token))
+            .set_json(json!({"old_password": "secret", "new_password": "changed"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        &app,
+        post("/api/login", None)
+            .set_json(json!({"username": "newdriver", "password": "changed"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let client_token = login_as(&pool, CLIENT1).await;
+    let (status, body) = send(
+        &app,
+        get("/api/account/sessions", Some(&client_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let session_ids = ids(&body);
+    assert_eq!(session_ids.len(), 1);
+
+    // 他のユーザーのセッションは削除できない
+    let other_token = login_as(&pool, CLIENT2).await;
+    let (status, _) = send(
+        &app,
+        delete(
+            &format!("/api/account/sessions/{}", session_ids[0]),
+            Some(&other_token),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(
+        &app,
+        delete(
+            &format!("/api/account/sessions/{}", session_ids[0]),
+            Some(&client_token),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        &app,
+        delete("/api/account/sessions", Some(&other_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn account_profile_image_rejects_invalid_uploads() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let token = login_as(&pool, CLIENT1).await;
+
+    let (content_type, body) = multipart("other", "text/plain", b"hello");
+    let (status, _) = send(
+        &app,
+        post("/api/account/profile_image", Some(&token))
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .set_payload(body)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (content_type, body) = multipart("image", "text/plain", b"not an image");
+    let (status, _) = send(
+        &app,
+        post("/api/account/profile_image", Some(&token))
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .set_payload(body)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    // 先頭のマジックナンバーだけ正しく、デコードできない画像
+    let (content_type, body) = multipart("image", "image/png", b"\x89PNG\r\n\x1a\nbroken");
+    let (status, _) = send(
+        &app,
+        post("/api/account/profile_image", Some(&token))
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .set_payload(body)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn admin_routes_require_admin_role() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let dispatcher_token = login_as(&pool, DISPATCHER1).await;
+
+    let (status, _) = send(&app, get("/api/admin/users", None).to_request()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    for req in [
+        get("/api/admin/users", Some(&dispatcher_token)),
+        post("/api/admin/users/8/disable", Some(&dispatcher_token)),
+        post("/api/admin/users/8/enable", Some(&dispatcher_token)),
+        put("/api/admin/users/8/role", Some(&dispatcher_token)),
+        put("/api/admin/users/3/area", Some(&dispatcher_token)),
+        get("/api/admin/users/8/sessions", Some(&dispatcher_token)),
+        delete("/api/admin/users/8/sessions", Some(&dispatcher_token)),
+        delete("/api/admin/users/8/sessions/1", Some(&dispatcher_token)),
+        post("/api/admin/users/8/password_reset", Some(&dispatcher_token)),
+        post("/api/admin/users/8/unlock", Some(&dispatcher_token)),
+        get("/api/admin/image_cache/stats", Some(&dispatcher_token)),
+        get("/api/admin/api_keys", Some(&dispatcher_token)),
+        post("/api/admin/api_keys", Some(&dispatcher_token)),
+        delete("/api/admin/api_keys/1", Some(&dispatcher_token)),
+    ] {
+        let (status, _) = send(&app, req.to_request()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+}
+
+#[actix_rt::test]
+async fn admin_manages_users() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let admin_token = login_as(&pool, ADMIN).await;
+
+    let (status, body) = send(
+        &app,
+        get("/api/admin/users", Some(&admin_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&body).len(), 8);
+
+    let (status, body) = send(
+        &app,
+        get("/api/admin/users?role=driver", Some(&admin_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&body), vec![4, 5, 6]);
+
+    let (status, body) = send(
+        &app,
+        get("/api/admin/users?page=1&page_size=3", Some(&admin_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&body), vec![4, 5, 6]);
+
+    for query in ["page_size=-1", "page_size=-2", "page=-1"] {
+        let (status, _) = send(
+            &app,
+            get(&format!("/api/admin/users?{}", query), Some(&admin_token)).to_request(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    let client_token = login_as(&pool, CLIENT2).await;
+    let (status, _) = send(
+        &app,
+        post(
+            &format!("/api/admin/users/{}/disable", CLIENT2),
+            Some(&admin_token),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        get("/api/account/sessions", Some(&client_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = send(
+        &app,
+        post(
+            &format!("/api/admin/users/{}/enable", CLIENT2),
+            Some(&admin_token),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // 自分自身のロールは変更できない
+    let (status, _) = send(
+        &app,
+        put(
+            &format!("/api/admin/users/{}/role", ADMIN),
+            Some(&admin_token),
+        )
+        .set_json(json!({"role": "client"}))
+        .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = send(
+        &app,
+        put(
+            &format!("/api/admin/users/{}/role", CLIENT2),
+            Some(&admin_token),
+        )
+        .set_json(json!({"role": "dispatcher", "area_id": 1}))
+        .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        &app,
+        put(
+            &format!("/api/admin/users/{}/area", DISPATCHER2),
+            Some(&admin_token),
+        )
+        .set_json(json!({"area_id": 1}))
+        .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(
+        &app,
+        get("/api/admin/users?role=dispatcher", Some(&admin_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let dispatchers = to_json(&body);
+    assert!(dispatchers
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|user| user["area_id"] == 1));
+
+    let (status, _) = send(
+        &app,
+        post("/api/admin/users/999/unlock", Some(&admin_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(
+        &app,
+        post(
+            &format!("/api/admin/users/{}/unlock", CLIENT1),
+            Some(&admin_token),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(
+        &app,
+        get("/api/admin/image_cache/stats", Some(&admin_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(to_json(&body).is_object());
+}
+
+#[actix_rt::test]
+async fn admin_manages_user_sessions() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let admin_token = login_as(&pool, ADMIN).await;
+    let client_token = login_as(&pool, CLIENT1).await;
+
+    let sessions_uri = format!("/api/admin/users/{}/sessions", CLIENT1);
+    let (status, body) = send(&app, get(&sessions_uri, Some(&admin_token)).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    let session_ids = ids(&body);
+    assert_eq!(session_ids.len(), 1);
+
+    let (status, _) = send(
+        &app,
+        delete(
+            &format!("/api/admin/users/{}/sessions/{}", CLIENT2, session_ids[0]),
+            Some(&admin_token),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(
+        &app,
+        delete(
+            &format!("{}/{}", sessions_uri, session_ids[0]),
+            Some(&admin_token),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        get("/api/account/sessions", Some(&client_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let driver_token = login_as(&pool, DRIVER1).await;
+    let (status, _) = send(
+        &app,
+        delete(
+            &format!("/api/admin/users/{}/sessions", DRIVER1),
+            Some(&admin_token),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        get("/api/driver/me/job", Some(&driver_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn admin_issues_password_reset_token() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let admin_token = login_as(&pool, ADMIN).await;
+
+    let (status, body) = send(
+        &app,
+        post(
+            &format!("/api/admin/users/{}/password_reset", CLIENT1),
+            Some(&admin_token),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let reset_token = to_json(&body)["reset_token"].as_str().unwrap().to_string();
+
+    let reset = json!({"reset_token": reset_token, "new_password": "reset-password"});
+    let (status, _) = send(
+        &app,
+        post("/api/password_reset", None)
+            .set_json(&reset)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // リセットトークンは一度しか使えない
+    let (status, _) = send(
+        &app,
+        post("/api/password_reset", None)
+            .set_json(&reset)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = send(
+        &app,
+        post("/api/login", None)
+            .set_json(json!({"username": "client1", "password": "reset-password"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn admin_manages_api_keys() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let admin_token = login_as(&pool, ADMIN).await;
+
+    let (status, _) = send(
+        &app,
+        post("/api/admin/api_keys", Some(&admin_token))
+            .set_json(json!({"name": "invalid", "scopes": ["users:write"]}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = send(
+        &app,
+        post("/api/admin/api_keys", Some(&admin_token))
+            .set_json(json!({"name": "partner", "scopes": ["tow_trucks:read"]}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let created = to_json(&body);
+    let key = created["key"].as_str().unwrap().to_string();
+    let key_id = created["id"].as_i64().unwrap();
+
+    let (status, body) = send(
+        &app,
+        get("/api/admin/api_keys", Some(&admin_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&body), vec![key_id]);
+
+    // API キーはスコープに含まれる API のみ呼び出せる
+    let (status, _) = send(
+        &app,
+        get("/api/tow_truck/list", None)
+            .insert_header(("X-API-Key", key.as_str()))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        get("/api/order/list", None)
+            .insert_header(("X-API-Key", key.as_str()))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(
+        &app,
+        get("/api/admin/users", None)
+            .insert_header(("X-API-Key", key.as_str()))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &app,
+        delete(
+            &format!("/api/admin/api_keys/{}", key_id),
+            Some(&admin_token),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        get("/api/tow_truck/list", None)
+            .insert_header(("X-API-Key", key.as_str()))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn tow_truck_list_is_restricted_to_dispatcher_area() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let dispatcher_token = login_as(&pool, DISPATCHER1).await;
+    let client_token = login_as(&pool, CLIENT1).await;
+
+    let (status, _) = send(&app, get("/api/tow_truck/list", None).to_request()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = send(
+        &app,
+        get("/api/tow_truck/list", Some(&client_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = send(
+        &app,
+        get("/api/tow_truck/list", Some(&dispatcher_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&body), vec![1, 2]);
+
+    let (status, _) = send(
+        &app,
+        get("/api/tow_truck/list?area=2", Some(&dispatcher_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[actix_rt::test]
+async fn tow_truck_location_is_updated_by_its_driver() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let driver_token = login_as(&pool, DRIVER1).await;
+    let dispatcher_token = login_as(&pool, DISPATCHER1).await;
+
+    let (status, _) = send(
+        &app,
+        post("/api/tow_truck/location", Some(&driver_token))
+            .set_json(json!({"tow_truck_id": 2, "node_id": 5}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &app,
+        post("/api/tow_truck/location", Some(&dispatcher_token))
+            .set_json(json!({"tow_truck_id": 1, "node_id": 5}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &app,
+        post("/api/tow_truck/location", Some(&driver_token))
+            .set_json(json!({"tow_truck_id": 1, "node_id": 5}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(
+        &app,
+        get("/api/tow_truck/1", Some(&driver_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(to_json(&body)["node_id"], 5);
+}
+
+#[actix_rt::test]
+async fn nearest_tow_truck_is_found_within_area() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let dispatcher_token = login_as(&pool, DISPATCHER1).await;
+    let driver_token = login_as(&pool, DRIVER1).await;
+
+    let (status, body) = send(
+        &app,
+        get("/api/tow_truck/nearest?order_id=1", Some(&dispatcher_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(to_json(&body)["id"], 1);
+
+    // 別エリアの依頼の最寄りのレッカー車は参照できない
+    let (status, _) = send(
+        &app,
+        get("/api/tow_truck/nearest?order_id=2", Some(&dispatcher_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // 別エリアに待機中のレッカー車がなくても、404 ではなく 403 を返す
+    sqlx::query("UPDATE tow_trucks SET status = 'busy' WHERE area_id = 2")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let (status, _) = send(
+        &app,
+        get("/api/tow_truck/nearest?order_id=2", Some(&dispatcher_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &app,
+        get("/api/tow_truck/nearest?order_id=1", Some(&driver_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[actix_rt::test]
+async fn orders_are_visible_only_to_related_users() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let client_token = login_as(&pool, CLIENT1).await;
+    let other_client_token = login_as(&pool, CLIENT2).await;
+    let dispatcher_token = login_as(&pool, DISPATCHER2).await;
+
+    let (status, _) = send(&app, get("/api/order/1", None).to_request()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = send(&app, get("/api/order/1", Some(&client_token)).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(to_json(&body)["client_username"], "client1");
+
+    let (status, _) = send(
+        &app,
+        get("/api/order/1", Some(&other_client_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &app,
+        get("/api/order/1", Some(&dispatcher_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = send(
+        &app,
+        get("/api/order/list?status=pending", Some(&dispatcher_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&body), vec![2]);
+
+    let (status, _) = send(
+        &app,
+        get("/api/order/list", Some(&client_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[actix_rt::test]
+async fn client_creates_own_order() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let client_token = login_as(&pool, CLIENT1).await;
+    let driver_token = login_as(&pool, DRIVER1).await;
+
+    let (status, _) = send(
+        &app,
+        post("/api/order/client", Some(&client_token))
+            .set_json(json!({"client_id": CLIENT2, "node_id": 5, "car_value": 500.0}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &app,
+        post("/api/order/client", Some(&driver_token))
+            .set_json(json!({"node_id": 5, "car_value": 500.0}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &app,
+        post("/api/order/client", Some(&client_token))
+            .set_json(json!({"node_id": 5, "car_value": 500.0}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, body) = send(&app, get("/api/order/4", Some(&client_token)).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(to_json(&body)["status"], "pending");
+}
+
+#[actix_rt::test]
+async fn dispatched_order_is_accepted_and_completed_by_driver() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let dispatcher_token = login_as(&pool, DISPATCHER1).await;
+    let driver_token = login_as(&pool, DRIVER2).await;
+    let other_driver_token = login_as(&pool, DRIVER1).await;
+
+    // 別エリアのレッカー車には配車できない
+    let (status, _) = send(
+        &app,
+        post("/api/order/dispatcher", Some(&dispatcher_token))
+            .set_json(
+                json!({"order_id": 1, "tow_truck_id": 3, "order_time": "2024-07-25T01:00:00Z"}),
+            )
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &app,
+        post("/api/order/dispatcher", Some(&dispatcher_token))
+            .set_json(
+                json!({"order_id": 1, "tow_truck_id": 2, "order_time": "2024-07-25T01:00:00Z"}),
+            )
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let offer = json!({"order_id": 1, "tow_truck_id": 2});
+    let (status, _) = send(
+        &app,
+        post("/api/order/offer/accept", Some(&other_driver_token))
+            .set_json(&offer)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &app,
+        post("/api/order/offer/accept", Some(&driver_token))
+            .set_json(&offer)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(
+        &app,
+        get("/api/driver/me/job", Some(&driver_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let job = to_json(&body);
+    assert_eq!(job["order"]["id"], 1);
+    assert_eq!(job["order"]["status"], "dispatched");
+
+    let (status, _) = send(
+        &app,
+        post("/api/order/status", Some(&other_driver_token))
+            .set_json(json!({"order_id": 1, "status": "completed"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &app,
+        post("/api/order/status", Some(&driver_token))
+            .set_json(json!({"order_id": 1, "status": "completed"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(
+        &app,
+        get("/api/order/1", Some(&dispatcher_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let order = to_json(&body);
+    assert_eq!(order["status"], "completed");
+    assert!(!order["completed_time"].is_null());
+
+    // 完了済みの依頼は再度完了にできない
+    let (status, _) = send(
+        &app,
+        post("/api/order/status", Some(&driver_token))
+            .set_json(json!({"order_id": 1, "status": "completed"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn completion_by_tow_truck_with_completed_order_conflicts() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let dispatcher_token = login_as(&pool, DISPATCHER1).await;
+    let driver_token = login_as(&pool, DRIVER1).await;
+
+    let (status, _) = send(
+        &app,
+        post("/api/order/dispatcher", Some(&dispatcher_token))
+            .set_json(
+                json!({"order_id": 1, "tow_truck_id": 1, "order_time": "2024-07-25T01:00:00Z"}),
+            )
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        &app,
+        post("/api/order/offer/accept", Some(&driver_token))
+            .set_json(json!({"order_id": 1, "tow_truck_id": 1}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // レッカー車 1 はフィクスチャで完了記録があり、completed_orders.tow_truck_id は UNIQUE
+    let (status, _) = send(
+        &app,
+        post("/api/order/status", Some(&driver_token))
+            .set_json(json!({"order_id": 1, "status": "completed"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, body) = send(
+        &app,
+        get("/api/order/1", Some(&dispatcher_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let order = to_json(&body);
+    assert_eq!(order["status"], "dispatched");
+    assert!(order["completed_time"].is_null());
+}
+
+#[actix_rt::test]
+async fn declined_offer_returns_order_to_pending() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let dispatcher_token = login_as(&pool, DISPATCHER2).await;
+    let driver_token = login_as(&pool, DRIVER3).await;
+
+    let (status, _) = send(
+        &app,
+        post("/api/order/dispatcher", Some(&dispatcher_token))
+            .set_json(
+                json!({"order_id": 2, "tow_truck_id": 3, "order_time": "2024-07-25T02:00:00Z"}),
+            )
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let offer = json!({"order_id": 2, "tow_truck_id": 3});
+    let (status, _) = send(
+        &app,
+        post("/api/order/offer/decline", Some(&dispatcher_token))
+            .set_json(&offer)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &app,
+        post("/api/order/offer/decline", Some(&driver_token))
+            .set_json(&offer)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // エリア 2 には他に空いているレッカー車がない
+    let (status, body) = send(
+        &app,
+        get("/api/order/2", Some(&dispatcher_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(to_json(&body)["status"], "pending");
+
+    let (status, _) = send(
+        &app,
+        post("/api/order/offer/accept", Some(&driver_token))
+            .set_json(&offer)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn driver_routes_are_only_for_drivers() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let driver_token = login_as(&pool, DRIVER1).await;
+    let admin_token = login_as(&pool, ADMIN).await;
+
+    let (status, _) = send(&app, get("/api/driver/me/job", None).to_request()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = send(
+        &app,
+        get("/api/driver/me/job", Some(&admin_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = send(
+        &app,
+        get("/api/driver/me/job", Some(&driver_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let job = to_json(&body);
+    assert_eq!(job["tow_truck"]["id"], 1);
+    assert!(job["order"].is_null());
+
+    let (status, body) = send(
+        &app,
+        get("/api/driver/me/history", Some(&driver_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&body), vec![3]);
+}
+
+#[actix_rt::test]
+async fn map_edge_is_updated_by_admin() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let admin_token = login_as(&pool, ADMIN).await;
+    let dispatcher_token = login_as(&pool, DISPATCHER1).await;
+
+    let edge = json!({"node_a_id": 1, "node_b_id": 2, "weight": 100});
+    let (status, _) = send(
+        &app,
+        put("/api/map/update_edge", Some(&dispatcher_token))
+            .set_json(&edge)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(
+        &app,
+        put("/api/map/update_edge", Some(&admin_token))
+            .set_json(&edge)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn metrics_are_exposed_in_prometheus_format() {
+    let (state, pool) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+    let dispatcher_token = login_as(&pool, DISPATCHER1).await;
+
+    send(&app, get("/api/order/1", None).to_request()).await;
+    let (status, _) = send(
+        &app,
+        get("/api/tow_truck/nearest?order_id=1", Some(&dispatcher_token)).to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // admin 以外のロールと、スコープを持たない API キーは参照できない
+    let (status, _) = send(&app, get("/metrics", None).to_request()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, get("/metrics", Some(&dispatcher_token)).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let admin_token = login_as(&pool, ADMIN).await;
+    let mut api_keys = Vec::new();
+    for scope in ["tow_trucks:read", "metrics:read"] {
+        let (status, body) = send(
+            &app,
+            post("/api/admin/api_keys", Some(&admin_token))
+                .set_json(json!({ "name": scope, "scopes": [scope] }))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        api_keys.push(to_json(&body)["key"].as_str().unwrap().to_string());
+    }
+    let (status, _) = send(
+        &app,
+        get("/metrics", None)
+            .insert_header(("X-API-Key", api_keys[0].as_str()))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&app, get("/metrics", Some(&admin_token)).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(
+        &app,
+        get("/metrics", None)
+            .insert_header(("X-API-Key", api_keys[1].as_str()))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    for expected in [
+        r#"http_requests_total{method="GET",route="/api/order/{id}",status="401"}"#,
+        r#"http_request_duration_seconds_bucket{method="GET",route="/api/tow_truck/nearest""#,
+        "dijkstra_runs_total",
+        "dijkstra_duration_seconds_count",
+        r#"db_pool_connections{state="in_use"}"#,
+        "image_cache_bytes",
+        r#"pending_orders{area_id="1"}"#,
+        r#"available_tow_trucks{area_id="2"}"#,
+    ] {
+        assert!(body.contains(expected), "{} が出力されていません", expected);
+    }
+}
+
+#[actix_rt::test]
+async fn request_id_is_generated_or_propagated() {
+    let (state, _) = setup().await;
+    let app = test::init_service(create_app(&state, &CorsSettings::default())).await;
+
+    let res = test::call_service(&app, get("/api/health/live", None).to_request()).await;
+    let generated = res.headers().get(REQUEST_ID_HEADER).unwrap();
+    assert!(uuid::Uuid::parse_str(generated.to_str().unwrap()).is_ok());
+
+    // 認証エラーのレスポンスにも、受け取った ID をそのまま返す
+    let req = get("/api/order/1", None)
+        .insert_header((REQUEST_ID_HEADER, "client-request-1"))
+        .to_request();
+    let res = match test::try_call_service(&app, req).await {
+        Ok(_) => panic!("認証エラーになっていません"),
+        Err(err) => err.error_response(),
+    };
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        res.headers().get(REQUEST_ID_HEADER).unwrap(),
+        "client-request-1"
+    );
+
+    // 不正な ID は使わずに生成し直す
+    let res = test::call_service(
+        &app,
+        get("/api/health/live", None)
+            .insert_header((REQUEST_ID_HEADER, "a".repeat(200)))
+            .to_request(),
+    )
+    .await;
+    let regenerated = res.headers().get(REQUEST_ID_HEADER).unwrap();
+    assert!(uuid::Uuid::parse_str(regenerated.to_str().unwrap()).is_ok());
+}
@@ -1,17 +1,35 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::path::Path;
 
 use actix_web::web::Bytes;
+use chrono::{DateTime, Duration, Utc};
+use image::ImageFormat;
 use log::error;
 
+use crate::config::Settings;
 use crate::errors::AppError;
-use crate::models::user::{Dispatcher, Session, User};
-use crate::utils::{generate_session_token, hash_password, verify_password};
+use crate::infrastructure::access_token::AccessTokenSigner;
+use crate::infrastructure::image_cache::{ImageCache, ImageCacheStats};
+use crate::infrastructure::image_resizer::{
+    decode_image, generate_identicon, resize_image, ImageOutputFormat, ResizedImage,
+    PROFILE_IMAGE_SIZES,
+};
+use crate::infrastructure::login_throttle::LoginThrottle;
+use crate::infrastructure::token_cache::TokenCache;
+use crate::models::user::{
+    ApiKey, AuthenticatedUser, Dispatcher, PasswordResetToken, Session, User, API_KEY_SCOPES,
+};
+use crate::utils::{
+    generate_session_token, hash_bytes, hash_password, hash_token, needs_rehash, verify_password,
+    PasswordHashParams,
+};
 
-use super::dto::auth::LoginResponseDto;
+use super::dto::auth::{
+    AccessTokenResponseDto, ApiKeyDto, ClientInfoDto, CreateApiKeyResponseDto, LoginResponseDto,
+    PasswordResetTokenResponseDto, ProfileImageResponseDto, SessionDto, UserDto,
+};
+use tracing::instrument;
 
 pub trait AuthRepository {
     async fn create_user(&self, username: &str, password: &str, role: &str)
@@ -24,38 +42,168 @@ pub trait AuthRepository {
         &self,
         user_id: i32,
     ) -> Result<Option<Dispatcher>, AppError>;
+    async fn find_driver_area_id_by_user_id(&self, user_id: i32) -> Result<Option<i32>, AppError>;
+    async fn update_user_password(&self, user_id: i32, password: &str) -> Result<(), AppError>;
+    async fn invalidate_sessions_by_user_id(&self, user_id: i32) -> Result<(), AppError>;
+    async fn create_password_reset_token(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AppError>;
+    async fn find_valid_password_reset_token(
+        &self,
+        token_hash: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<PasswordResetToken>, AppError>;
+    async fn mark_password_reset_token_used(
+        &self,
+        id: i32,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, AppError>;
     async fn find_profile_image_name_by_user_id(
         &self,
         user_id: i32,
     ) -> Result<Option<String>, AppError>;
-    async fn authenticate_user(&self, username: &str, password: &str) -> Result<User, AppError>;
-    async fn create_session(&self, user_id: i32, session_token: &str) -> Result<(), AppError>;
+    async fn create_session(
+        &self,
+        user_id: i32,
+        session_token: &str,
+        client: &ClientInfoDto,
+    ) -> Result<(), AppError>;
     async fn delete_session(&self, session_token: &str) -> Result<(), AppError>;
+    async fn touch_session(
+        &self,
+        session_id: i32,
+        last_seen_at: DateTime<Utc>,
+    ) -> Result<(), AppError>;
+    // 有効なセッションのトークンを差し替え、差し替えられたかどうかを返す
+    async fn rotate_session_token(
+        &self,
+        session_token: &str,
+        new_session_token: &str,
+    ) -> Result<bool, AppError>;
+    async fn delete_expired_sessions(
+        &self,
+        created_before: DateTime<Utc>,
+        last_seen_before: DateTime<Utc>,
+    ) -> Result<u64, AppError>;
     async fn find_session_by_session_token(&self, session_token: &str)
         -> Result<Session, AppError>;
+    async fn create_api_key(
+        &self,
+        name: &str,
+        key_hash: &str,
+        scopes: &str,
+        created_by: i32,
+    ) -> Result<i32, AppError>;
+    async fn find_api_key_by_id(&self, id: i32) -> Result<Option<ApiKey>, AppError>;
+    async fn find_active_api_key_by_hash(&self, key_hash: &str)
+        -> Result<Option<ApiKey>, AppError>;
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, AppError>;
+    async fn revoke_api_key(&self, id: i32, revoked_at: DateTime<Utc>) -> Result<(), AppError>;
+    async fn get_paginated_users(
+        &self,
+        page: i32,
+        page_size: i32,
+        role: Option<String>,
+    ) -> Result<Vec<UserDto>, AppError>;
+    async fn update_user_disabled(&self, user_id: i32, is_disabled: bool) -> Result<(), AppError>;
+    async fn find_node_area_id(&self, node_id: i32) -> Result<Option<i32>, AppError>;
+    async fn change_user_role(
+        &self,
+        user_id: i32,
+        current_role: &str,
+        new_role: &str,
+        area_id: Option<i32>,
+        node_id: Option<i32>,
+    ) -> Result<(), AppError>;
+    async fn update_dispatcher_area(&self, user_id: i32, area_id: i32) -> Result<(), AppError>;
+    async fn find_session_by_id(&self, id: i32) -> Result<Option<Session>, AppError>;
+    async fn get_valid_sessions_by_user_id(
+        &self,
+        user_id: i32,
+        created_after: DateTime<Utc>,
+        last_seen_after: DateTime<Utc>,
+    ) -> Result<Vec<Session>, AppError>;
+    async fn invalidate_session(&self, id: i32) -> Result<(), AppError>;
+    async fn update_profile_image_name(
+        &self,
+        user_id: i32,
+        profile_image_name: &str,
+    ) -> Result<(), AppError>;
+}
+
+const PASSWORD_RESET_TOKEN_TTL_SECS: i64 = 60 * 60;
+pub const MAX_PROFILE_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+const PROFILE_IMAGE_DIR: &str = "images/user_profile";
+// users.profile_image の初期値
+const DEFAULT_PROFILE_IMAGE_NAME: &str = "default.png";
+const MAX_USER_PAGE_SIZE: i32 = 100;
+
+// 拡張子やContent-Typeは信用せず、先頭のマジックナンバーから画像形式を判定する
+fn detect_image_format(image: &[u8]) -> Option<(ImageFormat, &'static str)> {
+    if image.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some((ImageFormat::Png, "png"))
+    } else if image.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some((ImageFormat::Jpeg, "jpg"))
+    } else if image.len() >= 12 && &image[0..4] == b"RIFF" && &image[8..12] == b"WEBP" {
+        Some((ImageFormat::WebP, "webp"))
+    } else {
+        None
+    }
+}
+
+// セッショントークンと衝突しないよう、API キーはハッシュに接頭辞を付けてキャッシュする
+fn api_key_cache_key(key_hash: &str) -> String {
+    format!("api_key:{}", key_hash)
 }
 
 #[derive(Debug)]
 pub struct AuthService<T: AuthRepository + std::fmt::Debug> {
     repository: T,
-    image_cache: Arc<Mutex<HashMap<String, Bytes>>>,
+    image_cache: ImageCache,
+    session_absolute_ttl: Duration,
+    session_idle_ttl: Duration,
+    token_cache: TokenCache,
+    access_token_signer: Option<AccessTokenSigner>,
+    password_hash_params: PasswordHashParams,
+    login_throttle: LoginThrottle,
 }
 
 impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
-    pub fn new(repository: T) -> Self {
+    pub fn new(repository: T, settings: &Settings) -> Self {
+        let auth = &settings.auth;
         AuthService {
             repository,
-            image_cache: Arc::new(Mutex::new(HashMap::new())),
+            image_cache: ImageCache::new(
+                settings.image_cache.max_bytes,
+                settings.image_cache.dir.clone(),
+                settings.image_cache.disk_max_bytes,
+            ),
+            session_absolute_ttl: Duration::seconds(auth.session_absolute_ttl_secs),
+            session_idle_ttl: Duration::seconds(auth.session_idle_ttl_secs),
+            token_cache: TokenCache::new(auth.token_cache_capacity, auth.token_cache_ttl()),
+            access_token_signer: settings.access_token_signer(),
+            // 起動時に検証済みのため、ここで失敗することはない
+            password_hash_params: auth.password_hash_params().unwrap_or_default(),
+            login_throttle: LoginThrottle::new(auth.login_throttle_capacity),
         }
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn register_user(
         &self,
         username: &str,
         password: &str,
         role: &str,
         area: Option<i32>,
+        client: &ClientInfoDto,
     ) -> Result<LoginResponseDto, AppError> {
+        if !["client", "dispatcher", "driver"].contains(&role) {
+            return Err(AppError::BadRequest);
+        }
+
         if role == "dispatcher" && area.is_none() {
             return Err(AppError::BadRequest);
         }
@@ -64,7 +212,7 @@ impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
             return Err(AppError::Conflict);
         }
 
-        let hashed_password = hash_password(password).unwrap();
+        let hashed_password = hash_password(password, &self.password_hash_params)?;
 
         self.repository
             .create_user(username, &hashed_password, role)
@@ -72,21 +220,21 @@ impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
 
         let session_token = generate_session_token();
 
-        match self.repository.find_user_by_username(username).await? {
+        let response = match self.repository.find_user_by_username(username).await? {
             Some(user) => {
                 self.repository
-                    .create_session(user.id, &session_token)
+                    .create_session(user.id, &session_token, client)
                     .await?;
                 match user.role.as_str() {
                     "dispatcher" => {
                         self.repository
-                            .create_dispatcher(user.id, area.unwrap())
+                            .create_dispatcher(user.id, area.ok_or(AppError::BadRequest)?)
                             .await?;
                         let dispatcher = self
                             .repository
                             .find_dispatcher_by_user_id(user.id)
                             .await?
-                            .unwrap();
+                            .ok_or(AppError::InternalServerError)?;
                         Ok(LoginResponseDto {
                             user_id: user.id,
                             username: user.username,
@@ -94,6 +242,8 @@ impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
                             role: user.role,
                             dispatcher_id: Some(dispatcher.id),
                             area_id: Some(dispatcher.area_id),
+                            access_token: None,
+                            access_token_expires_in: None,
                         })
                     }
                     _ => Ok(LoginResponseDto {
@@ -103,28 +253,48 @@ impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
                         role: user.role,
                         dispatcher_id: None,
                         area_id: None,
+                        access_token: None,
+                        access_token_expires_in: None,
                     }),
                 }
             }
             None => Err(AppError::InternalServerError),
-        }
+        }?;
+
+        self.attach_access_token(response).await
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn login_user(
         &self,
         username: &str,
         password: &str,
+        client: &ClientInfoDto,
     ) -> Result<LoginResponseDto, AppError> {
-        match self.repository.find_user_by_username(username).await? {
+        let client_ip = client.ip_address.as_deref();
+        if let Some(retry_after) = self.login_throttle.check(username, client_ip) {
+            return Err(AppError::TooManyRequests {
+                retry_after_secs: retry_after.as_secs() + 1,
+            });
+        }
+
+        let response = match self.repository.find_user_by_username(username).await? {
             Some(user) => {
-                let is_password_valid = verify_password(&user.password, password).unwrap();
+                let is_password_valid = verify_password(&user.password, password)?;
                 if !is_password_valid {
+                    self.login_throttle.record_failure(username, client_ip);
                     return Err(AppError::Unauthorized);
                 }
+                if user.is_disabled {
+                    return Err(AppError::Forbidden);
+                }
+                self.login_throttle.unlock(username);
+                self.rehash_password_if_needed(user.id, &user.password, password)
+                    .await;
 
                 let session_token = generate_session_token();
                 self.repository
-                    .create_session(user.id, &session_token)
+                    .create_session(user.id, &session_token, client)
                     .await?;
 
                 match user.role.as_str() {
@@ -137,6 +307,8 @@ impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
                                 role: user.role.clone(),
                                 dispatcher_id: Some(dispatcher.id),
                                 area_id: Some(dispatcher.area_id),
+                                access_token: None,
+                                access_token_expires_in: None,
                             }),
                             None => Err(AppError::InternalServerError),
                         }
@@ -148,19 +320,516 @@ impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
                         role: user.role.clone(),
                         dispatcher_id: None,
                         area_id: None,
+                        access_token: None,
+                        access_token_expires_in: None,
                     }),
                 }
             }
-            None => Err(AppError::Unauthorized),
+            None => {
+                // 存在しないユーザー名への試行も同様に数える
+                self.login_throttle.record_failure(username, client_ip);
+                Err(AppError::Unauthorized)
+            }
+        }?;
+
+        self.attach_access_token(response).await
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn unlock_user(&self, user_id: i32) -> Result<(), AppError> {
+        let user = self
+            .repository
+            .find_user_by_id(user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        self.login_throttle.unlock(&user.username);
+        Ok(())
+    }
+
+    // ステートレスモードではログイン時のセッショントークンをリフレッシュトークンとして扱い、
+    // API 呼び出しには署名付きのアクセストークンを使う
+    async fn attach_access_token(
+        &self,
+        mut response: LoginResponseDto,
+    ) -> Result<LoginResponseDto, AppError> {
+        if let Some(signer) = &self.access_token_signer {
+            let user = self
+                .repository
+                .find_user_by_id(response.user_id)
+                .await?
+                .ok_or(AppError::InternalServerError)?;
+            let authenticated_user = self.build_authenticated_user(user).await?;
+            response.access_token = Some(
+                signer
+                    .issue(&authenticated_user)
+                    .ok_or(AppError::InternalServerError)?,
+            );
+            response.access_token_expires_in = Some(signer.ttl_secs());
+        }
+
+        Ok(response)
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn refresh_access_token(
+        &self,
+        refresh_token: &str,
+    ) -> Result<AccessTokenResponseDto, AppError> {
+        let signer = self
+            .access_token_signer
+            .as_ref()
+            .ok_or(AppError::NotFound)?;
+        // セッションの有効性とユーザーの無効化はここで確認する
+        let (authenticated_user, _) = self.resolve_authenticated_user(refresh_token).await?;
+
+        // 漏洩したリフレッシュトークンを使い続けられないよう、使うたびに差し替える。
+        // 同じトークンで同時に再発行された場合は、先に差し替えた方だけが成功する
+        let new_refresh_token = generate_session_token();
+        if !self
+            .repository
+            .rotate_session_token(refresh_token, &new_refresh_token)
+            .await?
+        {
+            return Err(AppError::Unauthorized);
+        }
+
+        Ok(AccessTokenResponseDto {
+            access_token: signer
+                .issue(&authenticated_user)
+                .ok_or(AppError::InternalServerError)?,
+            access_token_expires_in: signer.ttl_secs(),
+            refresh_token: new_refresh_token,
+        })
+    }
+
+    // 古いパラメータでハッシュ化されたパスワードを、ログイン成功時に現在の設定で再ハッシュする
+    async fn rehash_password_if_needed(&self, user_id: i32, hashed_password: &str, password: &str) {
+        if !needs_rehash(hashed_password, &self.password_hash_params) {
+            return;
+        }
+
+        let result = match hash_password(password, &self.password_hash_params) {
+            Ok(rehashed_password) => {
+                self.repository
+                    .update_user_password(user_id, &rehashed_password)
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            error!("パスワードの再ハッシュに失敗しました: {:?}", err);
         }
     }
 
+    #[instrument(level = "debug", skip_all)]
+    pub async fn change_password(
+        &self,
+        user_id: i32,
+        old_password: &str,
+        new_password: &str,
+    ) -> Result<(), AppError> {
+        if new_password.is_empty() {
+            return Err(AppError::BadRequest);
+        }
+
+        let user = self
+            .repository
+            .find_user_by_id(user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        if !verify_password(&user.password, old_password)? {
+            return Err(AppError::Unauthorized);
+        }
+
+        self.repository
+            .update_user_password(
+                user_id,
+                &hash_password(new_password, &self.password_hash_params)?,
+            )
+            .await?;
+        self.invalidate_user_sessions(user_id).await
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn issue_password_reset_token(
+        &self,
+        user_id: i32,
+    ) -> Result<PasswordResetTokenResponseDto, AppError> {
+        if self.repository.find_user_by_id(user_id).await?.is_none() {
+            return Err(AppError::NotFound);
+        }
+
+        let reset_token = generate_session_token();
+        let expires_at = Utc::now() + Duration::seconds(PASSWORD_RESET_TOKEN_TTL_SECS);
+        self.repository
+            .create_password_reset_token(user_id, &hash_token(&reset_token), expires_at)
+            .await?;
+        self.invalidate_user_sessions(user_id).await?;
+
+        Ok(PasswordResetTokenResponseDto {
+            user_id,
+            reset_token,
+            expires_at,
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn reset_password(
+        &self,
+        reset_token: &str,
+        new_password: &str,
+    ) -> Result<(), AppError> {
+        if new_password.is_empty() {
+            return Err(AppError::BadRequest);
+        }
+
+        let now = Utc::now();
+        let token = self
+            .repository
+            .find_valid_password_reset_token(&hash_token(reset_token), now)
+            .await?
+            .ok_or(AppError::Unauthorized)?;
+
+        // 同じトークンの同時使用を防ぐため、使用済みにできた場合のみ更新する
+        if !self
+            .repository
+            .mark_password_reset_token_used(token.id, now)
+            .await?
+        {
+            return Err(AppError::Unauthorized);
+        }
+
+        self.repository
+            .update_user_password(
+                token.user_id,
+                &hash_password(new_password, &self.password_hash_params)?,
+            )
+            .await?;
+        self.invalidate_user_sessions(token.user_id).await
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn invalidate_user_sessions(&self, user_id: i32) -> Result<(), AppError> {
+        self.repository
+            .invalidate_sessions_by_user_id(user_id)
+            .await?;
+        self.token_cache.evict_user(user_id);
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_sessions(&self, user_id: i32) -> Result<Vec<SessionDto>, AppError> {
+        let now = Utc::now();
+        let sessions = self
+            .repository
+            .get_valid_sessions_by_user_id(
+                user_id,
+                now - self.session_absolute_ttl,
+                now - self.session_idle_ttl,
+            )
+            .await?;
+
+        Ok(sessions.into_iter().map(SessionDto::from_entity).collect())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn revoke_session(&self, user_id: i32, session_id: i32) -> Result<(), AppError> {
+        // 他のユーザーのセッションは存在しないものとして扱う
+        let session = self
+            .repository
+            .find_session_by_id(session_id)
+            .await?
+            .filter(|session| session.user_id == user_id)
+            .ok_or(AppError::NotFound)?;
+
+        self.repository.invalidate_session(session.id).await?;
+        self.token_cache.evict(&session.session_token);
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip_all)]
     pub async fn logout_user(&self, session_token: &str) -> Result<(), AppError> {
         self.repository.delete_session(session_token).await?;
+        self.token_cache.evict(session_token);
         Ok(())
     }
 
-    pub async fn get_resized_profile_image_byte(&self, user_id: i32) -> Result<Bytes, AppError> {
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_users(
+        &self,
+        page: i32,
+        page_size: i32,
+        role: Option<String>,
+    ) -> Result<Vec<UserDto>, AppError> {
+        if page < 0 || page_size <= 0 {
+            return Err(AppError::BadRequest);
+        }
+        let page_size = page_size.min(MAX_USER_PAGE_SIZE);
+        if page.checked_mul(page_size).is_none() {
+            return Err(AppError::BadRequest);
+        }
+
+        self.repository
+            .get_paginated_users(page, page_size, role)
+            .await
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn set_user_disabled(
+        &self,
+        operator_id: i32,
+        user_id: i32,
+        is_disabled: bool,
+    ) -> Result<(), AppError> {
+        // 管理者が自分自身を締め出さないようにする
+        if operator_id == user_id {
+            return Err(AppError::BadRequest);
+        }
+        if self.repository.find_user_by_id(user_id).await?.is_none() {
+            return Err(AppError::NotFound);
+        }
+
+        self.repository
+            .update_user_disabled(user_id, is_disabled)
+            .await?;
+        if is_disabled {
+            self.invalidate_user_sessions(user_id).await?;
+        }
+        // このユーザーが作成した API キーも作成者の user_id でキャッシュしているため破棄する
+        self.token_cache.evict_user(user_id);
+        Ok(())
+    }
+
+    // dispatchers・tow_trucks の行はロールに合わせて作成・削除する。
+    // dispatcher には area_id、driver には area_id と初期位置の node_id が必要
+    #[instrument(level = "debug", skip_all)]
+    pub async fn change_user_role(
+        &self,
+        operator_id: i32,
+        user_id: i32,
+        role: &str,
+        area_id: Option<i32>,
+        node_id: Option<i32>,
+    ) -> Result<(), AppError> {
+        if operator_id == user_id {
+            return Err(AppError::BadRequest);
+        }
+        if !["client", "dispatcher", "driver", "admin"].contains(&role) {
+            return Err(AppError::BadRequest);
+        }
+
+        let user = self
+            .repository
+            .find_user_by_id(user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        if user.role == role {
+            return Ok(());
+        }
+
+        match role {
+            "dispatcher" if area_id.is_none() => return Err(AppError::BadRequest),
+            "driver" => {
+                let node_id = node_id.ok_or(AppError::BadRequest)?;
+                let node_area_id = self.repository.find_node_area_id(node_id).await?;
+                if area_id.is_none() || node_area_id != area_id {
+                    return Err(AppError::BadRequest);
+                }
+            }
+            _ => {}
+        }
+
+        self.repository
+            .change_user_role(user_id, &user.role, role, area_id, node_id)
+            .await?;
+        self.invalidate_user_sessions(user_id).await
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn move_dispatcher_area(&self, user_id: i32, area_id: i32) -> Result<(), AppError> {
+        let user = self
+            .repository
+            .find_user_by_id(user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        if user.role != "dispatcher" {
+            return Err(AppError::BadRequest);
+        }
+
+        self.repository
+            .update_dispatcher_area(user_id, area_id)
+            .await?;
+        // セッションは維持し、キャッシュ済みのエリアのみ更新させる
+        self.token_cache.evict_user(user_id);
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn create_api_key(
+        &self,
+        created_by: i32,
+        name: &str,
+        scopes: &[String],
+    ) -> Result<CreateApiKeyResponseDto, AppError> {
+        if name.is_empty()
+            || scopes.is_empty()
+            || scopes
+                .iter()
+                .any(|scope| !API_KEY_SCOPES.contains(&scope.as_str()))
+        {
+            return Err(AppError::BadRequest);
+        }
+
+        let key = generate_session_token();
+        let id = self
+            .repository
+            .create_api_key(name, &hash_token(&key), &scopes.join(","), created_by)
+            .await?;
+        let api_key = self
+            .repository
+            .find_api_key_by_id(id)
+            .await?
+            .ok_or(AppError::InternalServerError)?;
+
+        Ok(CreateApiKeyResponseDto {
+            api_key: ApiKeyDto::from_entity(api_key),
+            key,
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_api_keys(&self) -> Result<Vec<ApiKeyDto>, AppError> {
+        let api_keys = self.repository.get_api_keys().await?;
+        Ok(api_keys.into_iter().map(ApiKeyDto::from_entity).collect())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn revoke_api_key(&self, id: i32) -> Result<(), AppError> {
+        let api_key = self
+            .repository
+            .find_api_key_by_id(id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+        self.repository.revoke_api_key(id, Utc::now()).await?;
+        self.token_cache
+            .evict(&api_key_cache_key(&api_key.key_hash));
+        Ok(())
+    }
+
+    // API キーはステートレスモードでも DB で検証し、失効を即座に反映できるようにする
+    #[instrument(level = "debug", skip_all)]
+    pub async fn authenticate_api_key(&self, key: &str) -> Option<AuthenticatedUser> {
+        let cache_key = api_key_cache_key(&hash_token(key));
+        if let Some(cached) = self.token_cache.get(&cache_key) {
+            return cached.authenticated_user;
+        }
+
+        match self
+            .repository
+            .find_active_api_key_by_hash(&hash_token(key))
+            .await
+        {
+            Ok(Some(api_key)) => {
+                // 作成した管理者が無効化されている間はキーも使えない。
+                // 再度有効化された場合にすぐ使えるよう、この結果はキャッシュしない
+                match self.repository.find_user_by_id(api_key.created_by).await {
+                    Ok(Some(user)) if !user.is_disabled => {}
+                    _ => return None,
+                }
+
+                let authenticated_user = AuthenticatedUser {
+                    user_id: api_key.created_by,
+                    role: "api_key".to_string(),
+                    dispatcher_id: None,
+                    area_id: None,
+                    scopes: Some(api_key.scope_list()),
+                };
+                self.token_cache
+                    .put(cache_key, Some(authenticated_user.clone()), None);
+                Some(authenticated_user)
+            }
+            Ok(None) => {
+                self.token_cache.put(cache_key, None, None);
+                None
+            }
+            Err(_) => None,
+        }
+    }
+
+    // 同じ内容の画像は同じファイル名になるよう、内容のハッシュをファイル名にして保存する
+    #[instrument(level = "debug", skip_all)]
+    pub async fn update_profile_image(
+        &self,
+        user_id: i32,
+        image: Bytes,
+    ) -> Result<ProfileImageResponseDto, AppError> {
+        if image.len() > MAX_PROFILE_IMAGE_BYTES {
+            return Err(AppError::PayloadTooLarge);
+        }
+        let (format, extension) =
+            detect_image_format(&image).ok_or(AppError::UnsupportedMediaType)?;
+        // ヘッダーだけ正しい壊れた画像や巨大な画像を保存しないよう、一度最後までデコードする
+        let source = image.clone();
+        match actix_web::web::block(move || decode_image(&source, Some(format))).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(_)) => return Err(AppError::BadRequest),
+            Err(e) => {
+                error!("プロフィール画像のデコードに失敗しました: {:?}", e);
+                return Err(AppError::InternalServerError);
+            }
+        }
+
+        let old_profile_image_name = self
+            .repository
+            .find_profile_image_name_by_user_id(user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+        let profile_image_name = format!("{}.{}", hash_bytes(&image), extension);
+        let path = Path::new(PROFILE_IMAGE_DIR).join(&profile_image_name);
+        if tokio::fs::metadata(&path).await.is_err() {
+            // 書き込み途中のファイルが読まれないよう、一時ファイルに書いてからリネームする
+            let tmp_path = Path::new(PROFILE_IMAGE_DIR).join(format!(
+                ".{}.{}",
+                profile_image_name,
+                generate_session_token()
+            ));
+            let result = match tokio::fs::write(&tmp_path, &image).await {
+                Ok(_) => tokio::fs::rename(&tmp_path, &path).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!("プロフィール画像の保存に失敗しました: {:?}", e);
+                let _ = tokio::fs::remove_file(&tmp_path).await;
+                return Err(AppError::InternalServerError);
+            }
+        }
+
+        self.repository
+            .update_profile_image_name(user_id, &profile_image_name)
+            .await?;
+
+        // サイズ・形式ごとのキャッシュをまとめて破棄する
+        self.image_cache
+            .remove_prefix(&format!("profile_image_{}_", old_profile_image_name));
+
+        Ok(ProfileImageResponseDto {
+            profile_image: profile_image_name,
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_resized_profile_image(
+        &self,
+        user_id: i32,
+        size: u32,
+        format: ImageOutputFormat,
+    ) -> Result<ResizedImage, AppError> {
+        if !PROFILE_IMAGE_SIZES.contains(&size) {
+            return Err(AppError::BadRequest);
+        }
+
         let profile_image_name = match self
             .repository
             .find_profile_image_name_by_user_id(user_id)
@@ -171,52 +840,364 @@ impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
             Err(_) => return Err(AppError::NotFound),
         };
 
-        let cache_key = format!("profile_image_{}", profile_image_name);
-        {
-            let cache = self.image_cache.lock().unwrap();
-            if let Some(cached_image) = cache.get(&cache_key) {
-                return Ok(cached_image.clone());
-            }
-        }
+        // アップロードされた画像がないユーザーには、ユーザーIDから生成したアバターを返す
+        let is_default_image = profile_image_name == DEFAULT_PROFILE_IMAGE_NAME;
+        let cache_key = if is_default_image {
+            format!("identicon_{}_{}.{}", user_id, size, format.extension())
+        } else {
+            format!(
+                "profile_image_{}_{}.{}",
+                profile_image_name,
+                size,
+                format.extension()
+            )
+        };
+        self.image_cache
+            .get_or_try_insert_with(&cache_key, format, || async {
+                // リサイズは CPU を占有するため、ワーカースレッドをブロックしないよう別スレッドで行う
+                let resized = if is_default_image {
+                    let seed = user_id.to_string();
+                    actix_web::web::block(move || generate_identicon(&seed, size, format)).await
+                } else {
+                    let path = Path::new(PROFILE_IMAGE_DIR).join(&profile_image_name);
+                    let source = tokio::fs::read(&path).await.map_err(|e| {
+                        error!("プロフィール画像の読み込みに失敗しました: {:?}", e);
+                        AppError::InternalServerError
+                    })?;
+                    actix_web::web::block(move || resize_image(&source, size, format)).await
+                }
+                .map_err(|e| {
+                    error!("画像リサイズの実行に失敗しました: {:?}", e);
+                    AppError::InternalServerError
+                })?
+                .map_err(|e| {
+                    error!("画像リサイズに失敗しました: {:?}", e);
+                    AppError::InternalServerError
+                })?;
 
-        let path: PathBuf =
-            Path::new(&format!("images/user_profile/{}", profile_image_name)).to_path_buf();
+                Ok(ResizedImage {
+                    etag: format!("\"{}\"", hash_bytes(&resized)),
+                    bytes: Bytes::from(resized),
+                    content_type: format.content_type(),
+                })
+            })
+            .await
+    }
 
-        let output = Command::new("magick")
-            .arg(&path)
-            .arg("-resize")
-            .arg("500x500")
-            .arg("png:-")
-            .output()
-            .map_err(|e| {
-                error!("画像リサイズのコマンド実行に失敗しました: {:?}", e);
-                AppError::InternalServerError
-            })?;
+    pub fn image_cache_stats(&self) -> ImageCacheStats {
+        self.image_cache.stats()
+    }
 
-        let resized_image = if output.status.success() {
-            Bytes::from(output.stdout)
-        } else {
-            error!(
-                "画像リサイズのコマンド実行に失敗しました: {:?}",
-                String::from_utf8_lossy(&output.stderr)
-            );
-            return Err(AppError::InternalServerError);
-        };
+    #[instrument(level = "debug", skip_all)]
+    pub async fn purge_expired_sessions(&self) -> Result<u64, AppError> {
+        let now = Utc::now();
+        self.repository
+            .delete_expired_sessions(now - self.session_absolute_ttl, now - self.session_idle_ttl)
+            .await
+    }
 
-        {
-            let mut cache = self.image_cache.lock().unwrap();
-            cache.insert(cache_key, resized_image.clone());
+    #[instrument(level = "debug", skip_all)]
+    pub async fn authenticate(&self, session_token: &str) -> Option<AuthenticatedUser> {
+        if let Some(signer) = &self.access_token_signer {
+            let access_token = session_token
+                .strip_prefix("Bearer ")
+                .unwrap_or(session_token);
+            return signer.verify(access_token);
+        }
+
+        if let Some(cached) = self.token_cache.get(session_token) {
+            return cached.authenticated_user;
         }
 
-        Ok(resized_image)
+        match self.resolve_authenticated_user(session_token).await {
+            Ok((authenticated_user, expires_at)) => {
+                let expires_until = (expires_at - Utc::now())
+                    .to_std()
+                    .map(|remaining| Instant::now() + remaining)
+                    .unwrap_or_else(|_| Instant::now());
+                self.token_cache.put(
+                    session_token.to_string(),
+                    Some(authenticated_user.clone()),
+                    Some(expires_until),
+                );
+                Some(authenticated_user)
+            }
+            Err(AppError::Unauthorized) => {
+                self.token_cache.put(session_token.to_string(), None, None);
+                None
+            }
+            Err(_) => None,
+        }
     }
 
-    pub async fn validate_session(&self, session_token: &str) -> Result<bool, AppError> {
+    // 認証済みユーザーと、セッションがこのまま使われなかった場合の失効日時を返す
+    async fn resolve_authenticated_user(
+        &self,
+        session_token: &str,
+    ) -> Result<(AuthenticatedUser, DateTime<Utc>), AppError> {
         let session = self
             .repository
             .find_session_by_session_token(session_token)
-            .await?;
+            .await
+            .map_err(|_| AppError::Unauthorized)?;
+
+        let now = Utc::now();
+        if !session.is_valid
+            || session.created_at + self.session_absolute_ttl <= now
+            || session.last_seen_at + self.session_idle_ttl <= now
+        {
+            return Err(AppError::Unauthorized);
+        }
+
+        self.repository.touch_session(session.id, now).await?;
+        let expires_at = std::cmp::min(
+            session.created_at + self.session_absolute_ttl,
+            now + self.session_idle_ttl,
+        );
+
+        let user = self
+            .repository
+            .find_user_by_id(session.user_id)
+            .await?
+            .ok_or(AppError::Unauthorized)?;
+        if user.is_disabled {
+            return Err(AppError::Unauthorized);
+        }
+
+        Ok((self.build_authenticated_user(user).await?, expires_at))
+    }
+
+    async fn build_authenticated_user(&self, user: User) -> Result<AuthenticatedUser, AppError> {
+        let (dispatcher_id, area_id) = match user.role.as_str() {
+            "dispatcher" => match self.repository.find_dispatcher_by_user_id(user.id).await? {
+                Some(dispatcher) => (Some(dispatcher.id), Some(dispatcher.area_id)),
+                None => return Err(AppError::Unauthorized),
+            },
+            "driver" => (
+                None,
+                self.repository
+                    .find_driver_area_id_by_user_id(user.id)
+                    .await?,
+            ),
+            _ => (None, None),
+        };
+
+        Ok(AuthenticatedUser {
+            user_id: user.id,
+            role: user.role,
+            dispatcher_id,
+            area_id,
+            scopes: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::memory::auth_repository::MemoryAuthRepository;
+    use crate::repositories::memory::MemoryStore;
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    fn service(store: &MemoryStore) -> AuthService<MemoryAuthRepository> {
+        let mut settings = Settings::default();
+        // テストではハッシュ計算のコストを最小にする
+        settings.auth.password_hash.memory_kib = 8;
+        settings.auth.password_hash.iterations = 1;
+        settings.auth.password_hash.parallelism = 1;
+        AuthService::new(MemoryAuthRepository::new(store.clone()), &settings)
+    }
+
+    fn client() -> ClientInfoDto {
+        ClientInfoDto {
+            ip_address: Some("192.0.2.1".to_string()),
+            user_agent: Some("test".to_string()),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn register_client_creates_user_and_session() {
+        let store = MemoryStore::new();
+        let service = service(&store);
+
+        let response = service
+            .register_user("client1", PASSWORD, "client", None, &client())
+            .await
+            .unwrap();
+
+        assert_eq!(response.username, "client1");
+        assert_eq!(response.role, "client");
+        assert_eq!(response.dispatcher_id, None);
+        assert_eq!(response.area_id, None);
+
+        let tables = store.lock();
+        let user = tables.find_user(response.user_id).unwrap();
+        assert_ne!(user.password, PASSWORD);
+        assert!(verify_password(&user.password, PASSWORD).unwrap());
+        let session = &tables.sessions[0];
+        assert_eq!(session.user_id, response.user_id);
+        assert_eq!(session.session_token, response.session_token);
+        assert_eq!(session.ip_address.as_deref(), Some("192.0.2.1"));
+    }
+
+    #[actix_rt::test]
+    async fn register_dispatcher_creates_dispatcher_in_area() {
+        let store = MemoryStore::new();
+        let service = service(&store);
+
+        let response = service
+            .register_user("dispatcher1", PASSWORD, "dispatcher", Some(2), &client())
+            .await
+            .unwrap();
+
+        let dispatcher = store.lock().dispatchers[0].clone();
+        assert_eq!(dispatcher.user_id, response.user_id);
+        assert_eq!(dispatcher.area_id, 2);
+        assert_eq!(response.dispatcher_id, Some(dispatcher.id));
+        assert_eq!(response.area_id, Some(2));
+    }
+
+    #[actix_rt::test]
+    async fn register_rejects_invalid_requests() {
+        let store = MemoryStore::new();
+        let service = service(&store);
+
+        let result = service
+            .register_user("admin1", PASSWORD, "admin", None, &client())
+            .await;
+        assert!(matches!(result, Err(AppError::BadRequest)));
+
+        let result = service
+            .register_user("dispatcher1", PASSWORD, "dispatcher", None, &client())
+            .await;
+        assert!(matches!(result, Err(AppError::BadRequest)));
+
+        assert!(store.lock().users.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn register_rejects_duplicate_username() {
+        let store = MemoryStore::new();
+        let service = service(&store);
+
+        service
+            .register_user("client1", PASSWORD, "client", None, &client())
+            .await
+            .unwrap();
+        let result = service
+            .register_user("client1", "another password", "driver", None, &client())
+            .await;
+
+        assert!(matches!(result, Err(AppError::Conflict)));
+        assert_eq!(store.lock().users.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn login_issues_new_session() {
+        let store = MemoryStore::new();
+        let service = service(&store);
+        let registered = service
+            .register_user("dispatcher1", PASSWORD, "dispatcher", Some(1), &client())
+            .await
+            .unwrap();
+
+        let response = service
+            .login_user("dispatcher1", PASSWORD, &client())
+            .await
+            .unwrap();
+
+        assert_eq!(response.user_id, registered.user_id);
+        assert_eq!(response.dispatcher_id, registered.dispatcher_id);
+        assert_eq!(response.area_id, Some(1));
+        assert_ne!(response.session_token, registered.session_token);
+        assert_eq!(store.lock().sessions.len(), 2);
+
+        let user = service.authenticate(&response.session_token).await.unwrap();
+        assert_eq!(user.user_id, registered.user_id);
+        assert_eq!(user.role, "dispatcher");
+    }
+
+    #[actix_rt::test]
+    async fn login_rejects_wrong_password_and_unknown_user() {
+        let store = MemoryStore::new();
+        let service = service(&store);
+        service
+            .register_user("client1", PASSWORD, "client", None, &client())
+            .await
+            .unwrap();
+
+        let result = service
+            .login_user("client1", "wrong password", &client())
+            .await;
+        assert!(matches!(result, Err(AppError::Unauthorized)));
+
+        let result = service.login_user("client2", PASSWORD, &client()).await;
+        assert!(matches!(result, Err(AppError::Unauthorized)));
+
+        // 登録時のセッションのみ
+        assert_eq!(store.lock().sessions.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn login_rejects_disabled_user() {
+        let store = MemoryStore::new();
+        let service = service(&store);
+        let registered = service
+            .register_user("client1", PASSWORD, "client", None, &client())
+            .await
+            .unwrap();
+        store.lock().users[0].is_disabled = true;
+
+        let result = service.login_user("client1", PASSWORD, &client()).await;
+
+        assert!(matches!(result, Err(AppError::Forbidden)));
+        assert_eq!(store.lock().users[0].id, registered.user_id);
+        assert_eq!(store.lock().sessions.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn api_key_is_rejected_while_creator_is_disabled() {
+        let store = MemoryStore::new();
+        let service = service(&store);
+        let admin_id = store.insert_user("admin1", "admin");
+        let operator_id = store.insert_user("admin2", "admin");
+        let created = service
+            .create_api_key(admin_id, "partner", &["orders:read".to_string()])
+            .await
+            .unwrap();
+        // 認証結果をキャッシュさせておく
+        assert!(service.authenticate_api_key(&created.key).await.is_some());
+
+        service
+            .set_user_disabled(operator_id, admin_id, true)
+            .await
+            .unwrap();
+        assert!(service.authenticate_api_key(&created.key).await.is_none());
+
+        service
+            .set_user_disabled(operator_id, admin_id, false)
+            .await
+            .unwrap();
+        assert!(service.authenticate_api_key(&created.key).await.is_some());
+    }
+
+    #[actix_rt::test]
+    async fn get_users_rejects_invalid_page_and_clamps_page_size() {
+        let store = MemoryStore::new();
+        let service = service(&store);
+        for i in 0..MAX_USER_PAGE_SIZE + 1 {
+            store.insert_user(&format!("client{}", i), "client");
+        }
+
+        for (page, page_size) in [(-1, 10), (0, 0), (0, -1), (0, -5), (i32::MAX, 20)] {
+            let result = service.get_users(page, page_size, None).await;
+            assert!(matches!(result, Err(AppError::BadRequest)));
+        }
 
-        Ok(session.is_valid)
+        let users = service.get_users(0, i32::MAX, None).await.unwrap();
+        assert_eq!(users.len(), MAX_USER_PAGE_SIZE as usize);
+        let users = service.get_users(1, 20, None).await.unwrap();
+        assert_eq!(users[0].username, "client20");
     }
 }
@@ -1,4 +1,8 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::models::user::{ApiKey, Session};
 
 // Input Data Structure
 
@@ -21,8 +25,82 @@ pub struct LogoutRequestDto {
     pub session_token: String,
 }
 
+#[derive(Deserialize)]
+pub struct RefreshTokenRequestDto {
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequestDto {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequestDto {
+    pub reset_token: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateApiKeyRequestDto {
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeRoleRequestDto {
+    pub role: String,
+    pub area_id: Option<i32>,
+    pub node_id: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeAreaRequestDto {
+    pub area_id: i32,
+}
+
+// セッションを作成したクライアントの情報（リクエストヘッダーから取得する）
+#[derive(Debug, Default)]
+pub struct ClientInfoDto {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
 // Output Data Structure
 
+#[derive(Serialize)]
+pub struct SessionDto {
+    pub id: i32,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl SessionDto {
+    pub fn from_entity(entity: Session) -> Self {
+        SessionDto {
+            id: entity.id,
+            created_at: entity.created_at,
+            last_seen_at: entity.last_seen_at,
+            user_agent: entity.user_agent,
+            ip_address: entity.ip_address,
+        }
+    }
+}
+
+#[derive(Serialize, FromRow)]
+pub struct UserDto {
+    pub id: i32,
+    pub username: String,
+    pub role: String,
+    pub is_disabled: bool,
+    pub dispatcher_id: Option<i32>,
+    pub tow_truck_id: Option<i32>,
+    pub area_id: Option<i32>,
+}
+
 #[derive(Serialize)]
 pub struct LoginResponseDto {
     pub user_id: i32,
@@ -31,4 +109,57 @@ pub struct LoginResponseDto {
     pub role: String,
     pub dispatcher_id: Option<i32>,
     pub area_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token_expires_in: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct AccessTokenResponseDto {
+    pub access_token: String,
+    pub access_token_expires_in: i64,
+    pub refresh_token: String,
+}
+
+#[derive(Serialize)]
+pub struct PasswordResetTokenResponseDto {
+    pub user_id: i32,
+    pub reset_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct ApiKeyDto {
+    pub id: i32,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_by: i32,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKeyDto {
+    pub fn from_entity(entity: ApiKey) -> Self {
+        ApiKeyDto {
+            id: entity.id,
+            scopes: entity.scope_list(),
+            name: entity.name,
+            created_by: entity.created_by,
+            created_at: entity.created_at,
+            revoked_at: entity.revoked_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CreateApiKeyResponseDto {
+    #[serde(flatten)]
+    pub api_key: ApiKeyDto,
+    pub key: String,
+}
+
+#[derive(Serialize)]
+pub struct ProfileImageResponseDto {
+    pub profile_image: String,
 }
@@ -0,0 +1,33 @@
+use serde::Serialize;
+
+// Output Data Structure
+
+#[derive(Serialize, Debug)]
+pub struct ReadinessResponseDto {
+    pub status: String,
+    pub database: DatabaseCheckDto,
+    pub migrations: MigrationsCheckDto,
+    pub pool: PoolCheckDto,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DatabaseCheckDto {
+    pub status: String,
+    pub latency_ms: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MigrationsCheckDto {
+    pub status: String,
+    pub pending: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PoolCheckDto {
+    pub size: u32,
+    pub idle: usize,
+    pub in_use: u32,
+    pub max_connections: u32,
+    pub saturation: f64,
+}
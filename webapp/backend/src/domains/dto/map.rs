@@ -1,6 +1,8 @@
-// Input Data Structure
+use serde::{Deserialize, Serialize};
+
+use crate::models::graph::Node;
 
-use serde::Deserialize;
+// Input Data Structure
 
 #[derive(Deserialize, Debug)]
 pub struct UpdateEdgeRequestDto {
@@ -8,3 +10,22 @@ pub struct UpdateEdgeRequestDto {
     pub node_b_id: i32,
     pub weight: i32,
 }
+
+// Output Data Structure
+
+#[derive(Serialize, Clone, Debug)]
+pub struct NodeDto {
+    pub id: i32,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl NodeDto {
+    pub fn from_entity(entity: &Node) -> Self {
+        NodeDto {
+            id: entity.id,
+            x: entity.x,
+            y: entity.y,
+        }
+    }
+}
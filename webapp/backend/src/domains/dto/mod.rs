@@ -1,4 +1,5 @@
 pub mod auth;
+pub mod health;
 pub mod map;
 pub mod order;
 pub mod tow_truck;
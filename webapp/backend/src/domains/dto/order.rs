@@ -1,15 +1,13 @@
+use crate::models::order::{CompletedOrder, Order};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow};
-use sqlx::mysql::MySqlRow;
-use sqlx::Row;
-use crate::models::order::CompletedOrder;
+use sqlx::FromRow;
 
 // Input Data Structure
 
 #[derive(Deserialize, Debug)]
 pub struct ClientOrderRequestDto {
-    pub client_id: i32,
+    pub client_id: Option<i32>,
     pub node_id: i32,
     pub car_value: f64,
 }
@@ -17,9 +15,14 @@ pub struct ClientOrderRequestDto {
 #[derive(Deserialize, Debug)]
 pub struct DispatcherOrderRequestDto {
     pub order_id: i32,
-    pub dispatcher_id: i32,
+    pub dispatcher_id: Option<i32>,
+    pub tow_truck_id: i32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OrderOfferRequestDto {
+    pub order_id: i32,
     pub tow_truck_id: i32,
-    pub order_time: DateTime<Utc>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -30,7 +33,7 @@ pub struct UpdateOrderStatusRequestDto {
 
 // Output Data Structure
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, FromRow)]
 pub struct OrderDto {
     pub id: i32,
     pub client_id: i32,
@@ -72,24 +75,29 @@ impl CompletedOrderDto {
     }
 }
 
-impl<'r> FromRow<'r, MySqlRow> for OrderDto {
-    fn from_row(row: &'r MySqlRow) -> Result<Self, sqlx::Error> {
-        Ok(OrderDto {
-            id: row.try_get("id")?,
-            client_id: row.try_get("client_id")?,
-            client_username: row.try_get("client_username")?,
-            dispatcher_id: row.try_get("dispatcher_id")?,
-            dispatcher_user_id: row.try_get("dispatcher_user_id")?,
-            dispatcher_username: row.try_get("dispatcher_username")?,
-            tow_truck_id: row.try_get("tow_truck_id")?,
-            driver_user_id: row.try_get("driver_user_id")?,
-            driver_username: row.try_get("driver_username")?,
-            status: row.try_get("status")?,
-            node_id: row.try_get("node_id")?,
-            area_id: row.try_get("area_id")?,
-            car_value: row.try_get("car_value")?,
-            order_time: row.try_get("order_time")?,
-            completed_time: row.try_get("completed_time")?,
-        })
+#[derive(Serialize, Debug)]
+pub struct DriverOrderDto {
+    pub id: i32,
+    pub client_id: i32,
+    pub dispatcher_id: Option<i32>,
+    pub status: String,
+    pub node_id: i32,
+    pub car_value: f64,
+    pub order_time: DateTime<Utc>,
+    pub completed_time: Option<DateTime<Utc>>,
+}
+
+impl DriverOrderDto {
+    pub fn from_entity(entity: Order) -> Self {
+        DriverOrderDto {
+            id: entity.id,
+            client_id: entity.client_id,
+            dispatcher_id: entity.dispatcher_id,
+            status: entity.status,
+            node_id: entity.node_id,
+            car_value: entity.car_value,
+            order_time: entity.order_time,
+            completed_time: entity.completed_time,
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use super::{map::NodeDto, order::DriverOrderDto};
+
 // Input Data Structure
 
 #[derive(Deserialize, Debug)]
@@ -32,3 +34,12 @@ impl TowTruckDto {
         }
     }
 }
+
+#[derive(Serialize)]
+pub struct DriverJobDto {
+    pub tow_truck: TowTruckDto,
+    pub order: Option<DriverOrderDto>,
+    pub pickup_node: Option<NodeDto>,
+    pub route: Vec<NodeDto>,
+    pub distance: Option<i32>,
+}
@@ -0,0 +1,174 @@
+use std::time::{Duration, Instant};
+
+use log::error;
+
+use super::dto::health::{
+    DatabaseCheckDto, MigrationsCheckDto, PoolCheckDto, ReadinessResponseDto,
+};
+use crate::config::Settings;
+use crate::errors::AppError;
+use tracing::instrument;
+
+// DB が応答しない場合にプローブ自体が詰まらないよう、各チェックはこの時間で打ち切る
+const READINESS_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug)]
+pub struct PoolStatus {
+    pub size: u32,
+    pub idle: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MigrationSummary {
+    pub pending: Vec<String>,
+    // 適用後にファイルの内容が変更されたもの。これがあると migrate up は適用を進めない
+    pub modified: Vec<String>,
+}
+
+pub trait HealthRepository {
+    async fn ping(&self) -> Result<(), AppError>;
+    fn pool_status(&self) -> PoolStatus;
+    // 適用履歴を確認できない場合は None を返す
+    async fn get_migration_summary(&self) -> Result<Option<MigrationSummary>, AppError>;
+}
+
+#[derive(Debug)]
+pub struct HealthService<T: HealthRepository + std::fmt::Debug> {
+    repository: T,
+    max_connections: u32,
+}
+
+impl<T: HealthRepository + std::fmt::Debug> HealthService<T> {
+    pub fn new(repository: T, settings: &Settings) -> Self {
+        HealthService {
+            repository,
+            max_connections: settings.database.max_connections,
+        }
+    }
+
+    // DB に接続でき、未適用・変更済みのマイグレーションがなければ ready とする。
+    // プールの使用状況は報告のみで、飽和していても ready のままとする
+    #[instrument(level = "debug", skip_all)]
+    pub async fn check_readiness(&self) -> (bool, ReadinessResponseDto) {
+        let started_at = Instant::now();
+        let database =
+            match tokio::time::timeout(READINESS_CHECK_TIMEOUT, self.repository.ping()).await {
+                Ok(Ok(())) => DatabaseCheckDto {
+                    status: "ok".to_string(),
+                    latency_ms: Some(started_at.elapsed().as_millis() as u64),
+                },
+                Ok(Err(e)) => {
+                    error!("DB への疎通確認に失敗しました: {:?}", e);
+                    DatabaseCheckDto {
+                        status: "error".to_string(),
+                        latency_ms: None,
+                    }
+                }
+                Err(_) => DatabaseCheckDto {
+                    status: "timeout".to_string(),
+                    latency_ms: None,
+                },
+            };
+
+        let (migrations_status, summary) = if database.status == "ok" {
+            match tokio::time::timeout(
+                READINESS_CHECK_TIMEOUT,
+                self.repository.get_migration_summary(),
+            )
+            .await
+            {
+                Ok(Ok(Some(summary))) if !summary.modified.is_empty() => ("modified", summary),
+                Ok(Ok(Some(summary))) if !summary.pending.is_empty() => ("pending", summary),
+                Ok(Ok(Some(summary))) => ("ok", summary),
+                Ok(Ok(None)) => ("untracked", MigrationSummary::default()),
+                Ok(Err(e)) => {
+                    error!("マイグレーションの確認に失敗しました: {:?}", e);
+                    ("error", MigrationSummary::default())
+                }
+                Err(_) => ("timeout", MigrationSummary::default()),
+            }
+        } else {
+            ("unknown", MigrationSummary::default())
+        };
+        let migrations = MigrationsCheckDto {
+            status: migrations_status.to_string(),
+            pending: summary.pending,
+            modified: summary.modified,
+        };
+
+        let pool_status = self.repository.pool_status();
+        let in_use = pool_status.size.saturating_sub(pool_status.idle as u32);
+        let pool = PoolCheckDto {
+            size: pool_status.size,
+            idle: pool_status.idle,
+            in_use,
+            max_connections: self.max_connections,
+            saturation: in_use as f64 / self.max_connections as f64,
+        };
+
+        // 適用履歴を確認できない DB はスキーマが最新か分からないため、準備完了とはしない
+        let is_ready = database.status == "ok" && migrations.status == "ok";
+        (
+            is_ready,
+            ReadinessResponseDto {
+                status: if is_ready { "ready" } else { "not_ready" }.to_string(),
+                database,
+                migrations,
+                pool,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct StubHealthRepository {
+        migration_summary: Option<MigrationSummary>,
+    }
+
+    impl HealthRepository for StubHealthRepository {
+        async fn ping(&self) -> Result<(), AppError> {
+            Ok(())
+        }
+
+        fn pool_status(&self) -> PoolStatus {
+            PoolStatus { size: 1, idle: 1 }
+        }
+
+        async fn get_migration_summary(&self) -> Result<Option<MigrationSummary>, AppError> {
+            Ok(self.migration_summary.clone())
+        }
+    }
+
+    async fn check(migration_summary: Option<MigrationSummary>) -> (bool, String) {
+        let service = HealthService::new(
+            StubHealthRepository { migration_summary },
+            &Settings::default(),
+        );
+        let (is_ready, response) = service.check_readiness().await;
+        (is_ready, response.migrations.status)
+    }
+
+    #[actix_rt::test]
+    async fn ready_only_when_migrations_are_up_to_date() {
+        let pending = MigrationSummary {
+            pending: vec!["7_session_client_info".to_string()],
+            modified: Vec::new(),
+        };
+        let modified = MigrationSummary {
+            pending: vec!["7_session_client_info".to_string()],
+            modified: vec!["2_order_offers".to_string()],
+        };
+
+        assert_eq!(
+            check(Some(MigrationSummary::default())).await,
+            (true, "ok".to_string())
+        );
+        assert_eq!(check(Some(pending)).await, (false, "pending".to_string()));
+        assert_eq!(check(Some(modified)).await, (false, "modified".to_string()));
+        assert_eq!(check(None).await, (false, "untracked".to_string()));
+    }
+}
@@ -2,6 +2,7 @@ use crate::{
     errors::AppError,
     models::graph::{Edge, Node},
 };
+use tracing::instrument;
 
 pub trait MapRepository {
     async fn get_all_nodes(&self, area_id: Option<i32>) -> Result<Vec<Node>, sqlx::Error>;
@@ -25,6 +26,7 @@ impl<T: MapRepository + std::fmt::Debug> MapService<T> {
         MapService { repository }
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn update_edge(
         &self,
         node_a_id: i32,
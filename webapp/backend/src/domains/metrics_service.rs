@@ -0,0 +1,79 @@
+use super::health_service::PoolStatus;
+use crate::config::Settings;
+use crate::errors::AppError;
+use crate::infrastructure::image_cache::ImageCacheStats;
+use crate::infrastructure::metrics::metrics;
+use tracing::instrument;
+
+pub trait MetricsRepository {
+    fn pool_status(&self) -> PoolStatus;
+    // (エリア ID, 件数)
+    async fn count_pending_orders_by_area(&self) -> Result<Vec<(i32, i64)>, AppError>;
+    async fn count_available_tow_trucks_by_area(&self) -> Result<Vec<(i32, i64)>, AppError>;
+}
+
+#[derive(Debug)]
+pub struct MetricsService<T: MetricsRepository + std::fmt::Debug> {
+    repository: T,
+    max_connections: u32,
+}
+
+impl<T: MetricsRepository + std::fmt::Debug> MetricsService<T> {
+    pub fn new(repository: T, settings: &Settings) -> Self {
+        MetricsService {
+            repository,
+            max_connections: settings.database.max_connections,
+        }
+    }
+
+    // リクエスト数や Dijkstra の実行回数は発生時に記録し、
+    // プール・キャッシュ・業務上の件数はスクレイプのたびに集計してから出力する
+    #[instrument(level = "debug", skip_all)]
+    pub async fn render(&self, image_cache_stats: ImageCacheStats) -> Result<String, AppError> {
+        let metrics = metrics();
+
+        let pool_status = self.repository.pool_status();
+        let in_use = pool_status.size.saturating_sub(pool_status.idle as u32);
+        metrics
+            .db_pool_connections
+            .with_label_values(&["idle"])
+            .set(pool_status.idle as i64);
+        metrics
+            .db_pool_connections
+            .with_label_values(&["in_use"])
+            .set(in_use as i64);
+        metrics
+            .db_pool_max_connections
+            .set(self.max_connections as i64);
+
+        metrics
+            .image_cache_bytes
+            .set(image_cache_stats.bytes as i64);
+        metrics
+            .image_cache_max_bytes
+            .set(image_cache_stats.max_bytes as i64);
+        metrics
+            .image_cache_entries
+            .set(image_cache_stats.entries as i64);
+
+        let pending_orders = self.repository.count_pending_orders_by_area().await?;
+        let available_tow_trucks = self.repository.count_available_tow_trucks_by_area().await?;
+        // 件数が 0 になったエリアの値を残さないよう、毎回作り直す
+        metrics.pending_orders.reset();
+        for (area_id, count) in pending_orders {
+            metrics
+                .pending_orders
+                .with_label_values(&[&area_id.to_string()])
+                .set(count);
+        }
+        metrics.available_tow_trucks.reset();
+        for (area_id, count) in available_tow_trucks {
+            metrics
+                .available_tow_trucks
+                .with_label_values(&[&area_id.to_string()])
+                .set(count);
+        }
+
+        Ok(metrics.encode())
+    }
+}
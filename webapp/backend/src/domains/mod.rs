@@ -1,5 +1,7 @@
 pub mod auth_service;
 pub mod dto;
+pub mod health_service;
 pub mod map_service;
+pub mod metrics_service;
 pub mod order_service;
 pub mod tow_truck_service;
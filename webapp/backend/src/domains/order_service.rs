@@ -1,15 +1,18 @@
 use chrono::{DateTime, Utc};
+use log::error;
 
 use super::{
     auth_service::AuthRepository,
     dto::order::{CompletedOrderDto, OrderDto},
     map_service::MapRepository,
-    tow_truck_service::TowTruckRepository,
+    tow_truck_service::{find_nearest_available_tow_truck, TowTruckRepository},
 };
 use crate::{
+    config::Settings,
     errors::AppError,
-    models::order::{CompletedOrder, Order},
+    models::order::{CompletedOrder, Order, OrderOffer},
 };
+use tracing::instrument;
 
 pub trait OrderRepository {
     async fn find_order_by_id(&self, id: i32) -> Result<Order, AppError>;
@@ -29,19 +32,47 @@ pub trait OrderRepository {
         node_id: i32,
         car_value: f64,
     ) -> Result<(), AppError>;
-    async fn update_order_dispatched(
+    async fn complete_order(
         &self,
-        id: i32,
-        dispatcher_id: i32,
+        order_id: i32,
         tow_truck_id: i32,
+        completed_time: DateTime<Utc>,
     ) -> Result<(), AppError>;
-    async fn create_completed_order(
+    async fn get_all_completed_orders(&self) -> Result<Vec<CompletedOrder>, AppError>;
+    async fn offer_order(
         &self,
         order_id: i32,
+        dispatcher_id: i32,
         tow_truck_id: i32,
-        completed_time: DateTime<Utc>,
     ) -> Result<(), AppError>;
-    async fn get_all_completed_orders(&self) -> Result<Vec<CompletedOrder>, AppError>;
+    async fn accept_order_offer(&self, offer: &OrderOffer) -> Result<bool, AppError>;
+    async fn close_order_offer(
+        &self,
+        offer: &OrderOffer,
+        status: &str,
+        next_tow_truck_id: Option<i32>,
+    ) -> Result<bool, AppError>;
+    async fn find_active_order_offer(
+        &self,
+        order_id: i32,
+        tow_truck_id: i32,
+        timeout_secs: i64,
+    ) -> Result<Option<OrderOffer>, AppError>;
+    async fn find_expired_order_offers(
+        &self,
+        timeout_secs: i64,
+    ) -> Result<Vec<OrderOffer>, AppError>;
+    async fn find_active_order_by_tow_truck_id(
+        &self,
+        tow_truck_id: i32,
+    ) -> Result<Option<Order>, AppError>;
+    async fn get_completed_orders_by_tow_truck_id(
+        &self,
+        tow_truck_id: i32,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<Order>, AppError>;
+    async fn find_offered_tow_truck_ids(&self, order_id: i32) -> Result<Vec<i32>, AppError>;
 }
 
 #[derive(Debug)]
@@ -55,6 +86,8 @@ pub struct OrderService<
     tow_truck_repository: U,
     auth_repository: V,
     map_repository: W,
+    offer_timeout_secs: i64,
+    nearest_tow_truck_max_distance: i32,
 }
 
 impl<
@@ -69,21 +102,40 @@ impl<
         tow_truck_repository: U,
         auth_repository: V,
         map_repository: W,
+        settings: &Settings,
     ) -> Self {
         OrderService {
             order_repository,
             tow_truck_repository,
             auth_repository,
             map_repository,
+            offer_timeout_secs: settings.dispatch.offer_timeout_secs,
+            nearest_tow_truck_max_distance: settings.dispatch.nearest_tow_truck_max_distance,
         }
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn update_order_status(&self, order_id: i32, status: &str) -> Result<(), AppError> {
+        if status != "completed" {
+            return self
+                .order_repository
+                .update_order_status(order_id, status)
+                .await;
+        }
+
+        // 完了記録はドライバーが作業を終えた時点で作る
+        let order = self.order_repository.find_order_by_id(order_id).await?;
+        let tow_truck_id = match (order.status.as_str(), order.tow_truck_id) {
+            ("dispatched", Some(tow_truck_id)) => tow_truck_id,
+            _ => return Err(AppError::BadRequest),
+        };
+
         self.order_repository
-            .update_order_status(order_id, status)
+            .complete_order(order_id, tow_truck_id, Utc::now())
             .await
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn get_order_by_id(&self, id: i32) -> Result<OrderDto, AppError> {
         let order = self.order_repository.find_order_by_id(id).await?;
 
@@ -168,6 +220,7 @@ impl<
         })
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn get_paginated_orders(
         &self,
         page: i32,
@@ -185,6 +238,7 @@ impl<
         Ok(orders)
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn create_client_order(
         &self,
         client_id: i32,
@@ -201,33 +255,120 @@ impl<
         }
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn create_dispatcher_order(
         &self,
         order_id: i32,
         dispatcher_id: i32,
         tow_truck_id: i32,
-        order_time: DateTime<Utc>,
     ) -> Result<(), AppError> {
-        if (self
-            .order_repository
-            .create_completed_order(order_id, tow_truck_id, order_time)
-            .await)
-            .is_err()
-        {
+        let order = self.order_repository.find_order_by_id(order_id).await?;
+        if order.status != "pending" {
             return Err(AppError::BadRequest);
         }
+        self.tow_truck_repository
+            .find_tow_truck_by_id(tow_truck_id)
+            .await?
+            .ok_or(AppError::BadRequest)?;
 
         self.order_repository
-            .update_order_dispatched(order_id, dispatcher_id, tow_truck_id)
-            .await?;
+            .offer_order(order_id, dispatcher_id, tow_truck_id)
+            .await
+    }
 
-        self.tow_truck_repository
-            .update_status(tow_truck_id, "busy")
+    #[instrument(level = "debug", skip_all)]
+    pub async fn accept_order_offer(
+        &self,
+        order_id: i32,
+        tow_truck_id: i32,
+    ) -> Result<(), AppError> {
+        let offer = self
+            .order_repository
+            .find_active_order_offer(order_id, tow_truck_id, self.offer_timeout_secs)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+        if !self.order_repository.accept_order_offer(&offer).await? {
+            return Err(AppError::Conflict);
+        }
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn decline_order_offer(
+        &self,
+        order_id: i32,
+        tow_truck_id: i32,
+    ) -> Result<(), AppError> {
+        let offer = self
+            .order_repository
+            .find_active_order_offer(order_id, tow_truck_id, self.offer_timeout_secs)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+        if !self.redispatch_order(&offer, "declined").await? {
+            return Err(AppError::Conflict);
+        }
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn expire_order_offers(&self) -> Result<(), AppError> {
+        let offers = self
+            .order_repository
+            .find_expired_order_offers(self.offer_timeout_secs)
             .await?;
 
+        // 1件の失敗で残りのオファーが処理されないよう、エラーはログに出して次へ進む
+        // 承諾・辞退と競合した場合は先に確定した方を優先する
+        for offer in offers {
+            if let Err(e) = self.redispatch_order(&offer, "expired").await {
+                error!(
+                    "オファー {} の期限切れ処理に失敗しました: {:?}",
+                    offer.id, e
+                );
+            }
+        }
+
         Ok(())
     }
 
+    // オファーを status で締め、次に近いレッカー車へ出し直す。
+    // オファーが既に応答済みの場合は何も変更せず false を返す
+    async fn redispatch_order(&self, offer: &OrderOffer, status: &str) -> Result<bool, AppError> {
+        let order = self
+            .order_repository
+            .find_order_by_id(offer.order_id)
+            .await?;
+        let mut excluded_tow_truck_ids = self
+            .order_repository
+            .find_offered_tow_truck_ids(order.id)
+            .await?;
+        excluded_tow_truck_ids.push(offer.tow_truck_id);
+
+        let next_tow_truck = match order.dispatcher_id {
+            Some(_) => {
+                find_nearest_available_tow_truck(
+                    &self.tow_truck_repository,
+                    &self.map_repository,
+                    order.node_id,
+                    &excluded_tow_truck_ids,
+                    self.nearest_tow_truck_max_distance,
+                )
+                .await?
+            }
+            None => None,
+        };
+
+        // 引き受け可能なレッカー車がいなければディスパッチャーの判断に戻す
+        self.order_repository
+            .close_order_offer(offer, status, next_tow_truck.map(|tow_truck| tow_truck.id))
+            .await
+    }
+
+    #[instrument(level = "debug", skip_all)]
     pub async fn get_completed_orders(&self) -> Result<Vec<CompletedOrderDto>, AppError> {
         let orders = self.order_repository.get_all_completed_orders().await?;
         let order_dtos = orders
@@ -238,3 +379,388 @@ impl<
         Ok(order_dtos)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::repositories::memory::auth_repository::MemoryAuthRepository;
+    use crate::repositories::memory::map_repository::MemoryMapRepository;
+    use crate::repositories::memory::order_repository::MemoryOrderRepository;
+    use crate::repositories::memory::tow_truck_repository::MemoryTowTruckRepository;
+    use crate::repositories::memory::MemoryStore;
+
+    type Service = OrderService<
+        MemoryOrderRepository,
+        MemoryTowTruckRepository,
+        MemoryAuthRepository,
+        MemoryMapRepository,
+    >;
+
+    fn service(store: &MemoryStore) -> Service {
+        OrderService::new(
+            MemoryOrderRepository::new(store.clone()),
+            MemoryTowTruckRepository::new(store.clone()),
+            MemoryAuthRepository::new(store.clone()),
+            MemoryMapRepository::new(store.clone()),
+            &Settings::default(),
+        )
+    }
+
+    struct Fixture {
+        store: MemoryStore,
+        client_id: i32,
+        dispatcher_id: i32,
+        order_id: i32,
+    }
+
+    // エリア 1 の 1 -(10)- 2 -(10)- 3 上に依頼 (ノード 1) が1件ある
+    fn fixture() -> Fixture {
+        let store = MemoryStore::new();
+        for node_id in 1..=3 {
+            store.insert_node(node_id, 1);
+        }
+        store.insert_edge(1, 2, 10);
+        store.insert_edge(2, 3, 10);
+
+        let client_id = store.insert_user("client1", "client");
+        let dispatcher_user_id = store.insert_user("dispatcher1", "dispatcher");
+        let dispatcher_id = store.insert_dispatcher(dispatcher_user_id, 1);
+        let order_id = store.insert_order(client_id, 1, 1000.0);
+
+        Fixture {
+            store,
+            client_id,
+            dispatcher_id,
+            order_id,
+        }
+    }
+
+    fn add_tow_truck(store: &MemoryStore, node_id: i32) -> i32 {
+        let driver_id = store.insert_user(&format!("driver{}", node_id), "driver");
+        store.insert_tow_truck(driver_id, 1, node_id, "available")
+    }
+
+    fn tow_truck_status(store: &MemoryStore, tow_truck_id: i32) -> String {
+        let tables = store.lock();
+        let row = tables.tow_trucks.iter().find(|row| row.id == tow_truck_id);
+        row.unwrap().status.clone()
+    }
+
+    #[actix_rt::test]
+    async fn get_order_by_id_returns_pending_order() {
+        let fixture = fixture();
+
+        let order = service(&fixture.store)
+            .get_order_by_id(fixture.order_id)
+            .await
+            .unwrap();
+
+        assert_eq!(order.id, fixture.order_id);
+        assert_eq!(order.client_id, fixture.client_id);
+        assert_eq!(order.client_username.as_deref(), Some("client1"));
+        assert_eq!(order.status, "pending");
+        assert_eq!(order.area_id, 1);
+        assert_eq!(order.dispatcher_id, None);
+        assert_eq!(order.dispatcher_username, None);
+        assert_eq!(order.tow_truck_id, None);
+        assert_eq!(order.driver_username, None);
+    }
+
+    #[actix_rt::test]
+    async fn get_order_by_id_resolves_dispatcher_and_driver() {
+        let fixture = fixture();
+        let tow_truck_id = add_tow_truck(&fixture.store, 2);
+        let service = service(&fixture.store);
+        service
+            .create_dispatcher_order(fixture.order_id, fixture.dispatcher_id, tow_truck_id)
+            .await
+            .unwrap();
+
+        let order = service.get_order_by_id(fixture.order_id).await.unwrap();
+
+        assert_eq!(order.status, "offered");
+        assert_eq!(order.dispatcher_id, Some(fixture.dispatcher_id));
+        assert_eq!(order.dispatcher_username.as_deref(), Some("dispatcher1"));
+        assert_eq!(order.tow_truck_id, Some(tow_truck_id));
+        assert_eq!(order.driver_username.as_deref(), Some("driver2"));
+    }
+
+    #[actix_rt::test]
+    async fn get_order_by_id_fails_for_unknown_order() {
+        let fixture = fixture();
+
+        let result = service(&fixture.store).get_order_by_id(999).await;
+
+        assert!(result.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn dispatch_offers_order_to_tow_truck() {
+        let fixture = fixture();
+        let tow_truck_id = add_tow_truck(&fixture.store, 2);
+
+        service(&fixture.store)
+            .create_dispatcher_order(fixture.order_id, fixture.dispatcher_id, tow_truck_id)
+            .await
+            .unwrap();
+
+        let tables = fixture.store.lock();
+        assert_eq!(tables.order_offers.len(), 1);
+        assert_eq!(tables.order_offers[0].tow_truck_id, tow_truck_id);
+        assert_eq!(tables.order_offers[0].status, "offered");
+        // 完了記録は引き受けられるまで作らない
+        assert!(tables.completed_orders.is_empty());
+        drop(tables);
+        assert_eq!(tow_truck_status(&fixture.store, tow_truck_id), "busy");
+    }
+
+    #[actix_rt::test]
+    async fn dispatch_fails_for_unknown_tow_truck() {
+        let fixture = fixture();
+
+        let result = service(&fixture.store)
+            .create_dispatcher_order(fixture.order_id, fixture.dispatcher_id, 999)
+            .await;
+
+        assert!(matches!(result, Err(AppError::BadRequest)));
+        assert!(fixture.store.lock().order_offers.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn accepted_offer_dispatches_order() {
+        let fixture = fixture();
+        let tow_truck_id = add_tow_truck(&fixture.store, 2);
+        let service = service(&fixture.store);
+        service
+            .create_dispatcher_order(fixture.order_id, fixture.dispatcher_id, tow_truck_id)
+            .await
+            .unwrap();
+
+        service
+            .accept_order_offer(fixture.order_id, tow_truck_id)
+            .await
+            .unwrap();
+
+        let order = service.get_order_by_id(fixture.order_id).await.unwrap();
+        assert_eq!(order.status, "dispatched");
+        assert_eq!(order.completed_time, None);
+        {
+            let tables = fixture.store.lock();
+            assert_eq!(tables.order_offers[0].status, "accepted");
+            // 完了記録は作業が終わるまで作らない
+            assert!(tables.completed_orders.is_empty());
+        }
+
+        // 応答済みのオファーには再度応答できない
+        let result = service
+            .decline_order_offer(fixture.order_id, tow_truck_id)
+            .await;
+        assert!(matches!(result, Err(AppError::NotFound)));
+    }
+
+    #[actix_rt::test]
+    async fn declined_offer_is_redispatched_to_next_nearest_tow_truck() {
+        let fixture = fixture();
+        let first_id = add_tow_truck(&fixture.store, 2);
+        let second_id = add_tow_truck(&fixture.store, 3);
+        let service = service(&fixture.store);
+        service
+            .create_dispatcher_order(fixture.order_id, fixture.dispatcher_id, first_id)
+            .await
+            .unwrap();
+
+        service
+            .decline_order_offer(fixture.order_id, first_id)
+            .await
+            .unwrap();
+
+        let order = service.get_order_by_id(fixture.order_id).await.unwrap();
+        assert_eq!(order.status, "offered");
+        assert_eq!(order.tow_truck_id, Some(second_id));
+        assert_eq!(tow_truck_status(&fixture.store, first_id), "available");
+        assert_eq!(tow_truck_status(&fixture.store, second_id), "busy");
+        assert!(fixture.store.lock().completed_orders.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn order_already_offered_cannot_be_dispatched_again() {
+        let fixture = fixture();
+        let first_id = add_tow_truck(&fixture.store, 2);
+        let second_id = add_tow_truck(&fixture.store, 3);
+        let service = service(&fixture.store);
+        service
+            .create_dispatcher_order(fixture.order_id, fixture.dispatcher_id, first_id)
+            .await
+            .unwrap();
+
+        let result = service
+            .create_dispatcher_order(fixture.order_id, fixture.dispatcher_id, second_id)
+            .await;
+
+        assert!(matches!(result, Err(AppError::BadRequest)));
+        assert_eq!(fixture.store.lock().order_offers.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn completed_order_is_recorded_at_completion() {
+        let fixture = fixture();
+        let tow_truck_id = add_tow_truck(&fixture.store, 2);
+        let service = service(&fixture.store);
+        service
+            .create_dispatcher_order(fixture.order_id, fixture.dispatcher_id, tow_truck_id)
+            .await
+            .unwrap();
+        service
+            .accept_order_offer(fixture.order_id, tow_truck_id)
+            .await
+            .unwrap();
+        let accepted_at = Utc::now();
+
+        service
+            .update_order_status(fixture.order_id, "completed")
+            .await
+            .unwrap();
+
+        let order = service.get_order_by_id(fixture.order_id).await.unwrap();
+        assert_eq!(order.status, "completed");
+        assert!(order.completed_time.unwrap() >= accepted_at);
+        let tables = fixture.store.lock();
+        assert_eq!(tables.completed_orders.len(), 1);
+        assert_eq!(tables.completed_orders[0].tow_truck_id, tow_truck_id);
+        assert_eq!(
+            Some(tables.completed_orders[0].completed_time),
+            order.completed_time
+        );
+    }
+
+    #[actix_rt::test]
+    async fn order_not_dispatched_cannot_be_completed() {
+        let fixture = fixture();
+        let tow_truck_id = add_tow_truck(&fixture.store, 2);
+        let service = service(&fixture.store);
+        service
+            .create_dispatcher_order(fixture.order_id, fixture.dispatcher_id, tow_truck_id)
+            .await
+            .unwrap();
+
+        let result = service
+            .update_order_status(fixture.order_id, "completed")
+            .await;
+
+        assert!(matches!(result, Err(AppError::BadRequest)));
+        let order = service.get_order_by_id(fixture.order_id).await.unwrap();
+        assert_eq!(order.status, "offered");
+        assert!(fixture.store.lock().completed_orders.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn second_completion_by_same_tow_truck_conflicts() {
+        let fixture = fixture();
+        let tow_truck_id = add_tow_truck(&fixture.store, 2);
+        let other_order_id = fixture.store.insert_order(fixture.client_id, 1, 500.0);
+        let service = service(&fixture.store);
+        for order_id in [other_order_id, fixture.order_id] {
+            service
+                .create_dispatcher_order(order_id, fixture.dispatcher_id, tow_truck_id)
+                .await
+                .unwrap();
+            service
+                .accept_order_offer(order_id, tow_truck_id)
+                .await
+                .unwrap();
+        }
+        service
+            .update_order_status(other_order_id, "completed")
+            .await
+            .unwrap();
+
+        // completed_orders.tow_truck_id は UNIQUE のため、同じレッカー車では記録できない
+        let result = service
+            .update_order_status(fixture.order_id, "completed")
+            .await;
+
+        assert!(matches!(result, Err(AppError::Conflict)));
+        let order = service.get_order_by_id(fixture.order_id).await.unwrap();
+        assert_eq!(order.status, "dispatched");
+        assert_eq!(order.completed_time, None);
+        assert_eq!(fixture.store.lock().completed_orders.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn declined_offer_without_other_tow_trucks_returns_order_to_pending() {
+        let fixture = fixture();
+        let tow_truck_id = add_tow_truck(&fixture.store, 2);
+        let service = service(&fixture.store);
+        service
+            .create_dispatcher_order(fixture.order_id, fixture.dispatcher_id, tow_truck_id)
+            .await
+            .unwrap();
+
+        service
+            .decline_order_offer(fixture.order_id, tow_truck_id)
+            .await
+            .unwrap();
+
+        let order = service.get_order_by_id(fixture.order_id).await.unwrap();
+        assert_eq!(order.status, "pending");
+        assert_eq!(order.tow_truck_id, None);
+        assert_eq!(tow_truck_status(&fixture.store, tow_truck_id), "available");
+        assert!(fixture.store.lock().completed_orders.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn expired_offer_is_redispatched() {
+        let fixture = fixture();
+        let first_id = add_tow_truck(&fixture.store, 2);
+        let second_id = add_tow_truck(&fixture.store, 3);
+        let service = service(&fixture.store);
+        service
+            .create_dispatcher_order(fixture.order_id, fixture.dispatcher_id, first_id)
+            .await
+            .unwrap();
+        fixture.store.lock().order_offers[0].offered_at -= Duration::seconds(3600);
+
+        service.expire_order_offers().await.unwrap();
+
+        let tables = fixture.store.lock();
+        assert_eq!(tables.order_offers[0].status, "expired");
+        assert_eq!(tables.order_offers[1].tow_truck_id, second_id);
+        assert_eq!(tables.order_offers[1].status, "offered");
+    }
+
+    #[actix_rt::test]
+    async fn failed_expiration_does_not_stop_other_offers() {
+        let fixture = fixture();
+        let other_order_id = fixture.store.insert_order(fixture.client_id, 1, 500.0);
+        let first_id = add_tow_truck(&fixture.store, 2);
+        let second_id = add_tow_truck(&fixture.store, 3);
+        let third_id = add_tow_truck(&fixture.store, 1);
+        let service = service(&fixture.store);
+        for (order_id, tow_truck_id) in [(fixture.order_id, first_id), (other_order_id, second_id)]
+        {
+            service
+                .create_dispatcher_order(order_id, fixture.dispatcher_id, tow_truck_id)
+                .await
+                .unwrap();
+        }
+        {
+            let mut tables = fixture.store.lock();
+            for offer in tables.order_offers.iter_mut() {
+                offer.offered_at -= Duration::seconds(3600);
+            }
+            // 1件目の依頼は再配車時に見つからずエラーになる
+            tables.orders.retain(|order| order.id != fixture.order_id);
+        }
+
+        service.expire_order_offers().await.unwrap();
+
+        let tables = fixture.store.lock();
+        let redispatched = tables
+            .order_offers
+            .iter()
+            .find(|offer| offer.order_id == other_order_id && offer.status == "offered");
+        assert_eq!(redispatched.unwrap().tow_truck_id, third_id);
+    }
+}
@@ -1,9 +1,16 @@
-use super::dto::tow_truck::TowTruckDto;
+use super::dto::map::NodeDto;
+use super::dto::order::DriverOrderDto;
+use super::dto::tow_truck::{DriverJobDto, TowTruckDto};
 use super::map_service::MapRepository;
 use super::order_service::OrderRepository;
+use crate::config::Settings;
 use crate::errors::AppError;
+use crate::infrastructure::metrics::metrics;
 use crate::models::graph::Graph;
 use crate::models::tow_truck::TowTruck;
+use tracing::instrument;
+
+const MAX_JOB_HISTORY_PAGE_SIZE: i32 = 100;
 
 pub trait TowTruckRepository {
     async fn get_paginated_tow_trucks(
@@ -14,8 +21,11 @@ pub trait TowTruckRepository {
         area_id: Option<i32>,
     ) -> Result<Vec<TowTruck>, AppError>;
     async fn update_location(&self, truck_id: i32, node_id: i32) -> Result<(), AppError>;
-    async fn update_status(&self, truck_id: i32, status: &str) -> Result<(), AppError>;
     async fn find_tow_truck_by_id(&self, id: i32) -> Result<Option<TowTruck>, AppError>;
+    async fn find_tow_truck_by_driver_id(
+        &self,
+        driver_id: i32,
+    ) -> Result<Option<TowTruck>, AppError>;
 }
 
 #[derive(Debug)]
@@ -27,6 +37,7 @@ pub struct TowTruckService<
     tow_truck_repository: T,
     order_repository: U,
     map_repository: V,
+    nearest_tow_truck_max_distance: i32,
 }
 
 impl<
@@ -35,19 +46,27 @@ impl<
         V: MapRepository + std::fmt::Debug,
     > TowTruckService<T, U, V>
 {
-    pub fn new(tow_truck_repository: T, order_repository: U, map_repository: V) -> Self {
+    pub fn new(
+        tow_truck_repository: T,
+        order_repository: U,
+        map_repository: V,
+        settings: &Settings,
+    ) -> Self {
         TowTruckService {
             tow_truck_repository,
             order_repository,
             map_repository,
+            nearest_tow_truck_max_distance: settings.dispatch.nearest_tow_truck_max_distance,
         }
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn get_tow_truck_by_id(&self, id: i32) -> Result<Option<TowTruckDto>, AppError> {
         let tow_truck = self.tow_truck_repository.find_tow_truck_by_id(id).await?;
         Ok(tow_truck.map(TowTruckDto::from_entity))
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn get_all_tow_trucks(
         &self,
         page: i32,
@@ -67,6 +86,7 @@ impl<
         Ok(tow_truck_dtos)
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn update_location(&self, truck_id: i32, node_id: i32) -> Result<(), AppError> {
         self.tow_truck_repository
             .update_location(truck_id, node_id)
@@ -75,58 +95,339 @@ impl<
         Ok(())
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn get_nearest_available_tow_trucks(
         &self,
         order_id: i32,
     ) -> Result<Option<TowTruckDto>, AppError> {
         let order = self.order_repository.find_order_by_id(order_id).await?;
-        let area_id = self
-            .map_repository
-            .get_area_id_by_node_id(order.node_id)
-            .await?;
-        let tow_trucks = self
+        let tow_truck = find_nearest_available_tow_truck(
+            &self.tow_truck_repository,
+            &self.map_repository,
+            order.node_id,
+            &[],
+            self.nearest_tow_truck_max_distance,
+        )
+        .await?;
+
+        Ok(tow_truck.map(TowTruckDto::from_entity))
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_driver_job(&self, driver_id: i32) -> Result<DriverJobDto, AppError> {
+        let tow_truck = self
             .tow_truck_repository
-            .get_paginated_tow_trucks(0, -1, Some("available".to_string()), Some(area_id))
+            .find_tow_truck_by_driver_id(driver_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+        let order = self
+            .order_repository
+            .find_active_order_by_tow_truck_id(tow_truck.id)
             .await?;
 
-        let nodes = self.map_repository.get_all_nodes(Some(area_id)).await?;
-        let edges = self.map_repository.get_all_edges(Some(area_id)).await?;
+        let (pickup_node, route, distance) = match &order {
+            Some(order) => {
+                let graph = build_area_graph(&self.map_repository, tow_truck.area_id).await?;
+                let pickup_node = graph.nodes.get(&order.node_id).map(NodeDto::from_entity);
+                match metrics()
+                    .observe_dijkstra(|| graph.shortest_route(tow_truck.node_id, order.node_id))
+                {
+                    Some((distance, route)) => (
+                        pickup_node,
+                        route
+                            .iter()
+                            .filter_map(|node_id| graph.nodes.get(node_id))
+                            .map(NodeDto::from_entity)
+                            .collect(),
+                        Some(distance),
+                    ),
+                    None => (pickup_node, Vec::new(), None),
+                }
+            }
+            None => (None, Vec::new(), None),
+        };
 
-        let mut graph = Graph::new();
-        for node in nodes {
-            graph.add_node(node);
-        }
-        for edge in edges {
-            graph.add_edge(edge);
+        Ok(DriverJobDto {
+            tow_truck: TowTruckDto::from_entity(tow_truck),
+            order: order.map(DriverOrderDto::from_entity),
+            pickup_node,
+            route,
+            distance,
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_driver_job_history(
+        &self,
+        driver_id: i32,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<DriverOrderDto>, AppError> {
+        if page < 0 || page_size <= 0 {
+            return Err(AppError::BadRequest);
         }
+        let page_size = page_size.min(MAX_JOB_HISTORY_PAGE_SIZE);
 
-        let sorted_tow_trucks_by_distance = {
-            let mut tow_trucks_with_distance: Vec<_> = tow_trucks
-                .into_iter()
-                .map(|truck| {
-                    let distance = calculate_distance(&graph, truck.node_id, order.node_id);
-                    (distance, truck)
-                })
-                .collect();
-
-            tow_trucks_with_distance.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-            tow_trucks_with_distance
-        };
+        let tow_truck = self
+            .tow_truck_repository
+            .find_tow_truck_by_driver_id(driver_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
 
-        if sorted_tow_trucks_by_distance.is_empty() || sorted_tow_trucks_by_distance[0].0 > 10000000
-        {
-            return Ok(None);
-        }
+        let orders = self
+            .order_repository
+            .get_completed_orders_by_tow_truck_id(tow_truck.id, page, page_size)
+            .await?;
 
-        let sorted_tow_truck_dtos: Vec<TowTruckDto> = sorted_tow_trucks_by_distance
+        Ok(orders
             .into_iter()
-            .map(|(_, truck)| TowTruckDto::from_entity(truck))
+            .map(DriverOrderDto::from_entity)
+            .collect())
+    }
+}
+
+#[instrument(level = "debug", skip_all)]
+async fn build_area_graph<V: MapRepository>(
+    map_repository: &V,
+    area_id: i32,
+) -> Result<Graph, AppError> {
+    let nodes = map_repository.get_all_nodes(Some(area_id)).await?;
+    let edges = map_repository.get_all_edges(Some(area_id)).await?;
+
+    let mut graph = Graph::new();
+    for node in nodes {
+        graph.add_node(node);
+    }
+    for edge in edges {
+        graph.add_edge(edge);
+    }
+
+    Ok(graph)
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn find_nearest_available_tow_truck<T: TowTruckRepository, V: MapRepository>(
+    tow_truck_repository: &T,
+    map_repository: &V,
+    node_id: i32,
+    excluded_tow_truck_ids: &[i32],
+    max_distance: i32,
+) -> Result<Option<TowTruck>, AppError> {
+    let area_id = map_repository.get_area_id_by_node_id(node_id).await?;
+    let tow_trucks = tow_truck_repository
+        .get_paginated_tow_trucks(0, -1, Some("available".to_string()), Some(area_id))
+        .await?;
+
+    let graph = build_area_graph(map_repository, area_id).await?;
+
+    let sorted_tow_trucks_by_distance = {
+        let mut tow_trucks_with_distance: Vec<_> = tow_trucks
+            .into_iter()
+            .filter(|truck| !excluded_tow_truck_ids.contains(&truck.id))
+            .map(|truck| {
+                let distance = calculate_distance(&graph, truck.node_id, node_id);
+                (distance, truck)
+            })
             .collect();
 
-        Ok(sorted_tow_truck_dtos.first().cloned())
+        tow_trucks_with_distance.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        tow_trucks_with_distance
+    };
+
+    if sorted_tow_trucks_by_distance.is_empty() || sorted_tow_trucks_by_distance[0].0 > max_distance
+    {
+        return Ok(None);
     }
+
+    Ok(sorted_tow_trucks_by_distance
+        .into_iter()
+        .next()
+        .map(|(_, truck)| truck))
 }
 
 fn calculate_distance(graph: &Graph, node_id_1: i32, node_id_2: i32) -> i32 {
-    graph.shortest_path(node_id_1, node_id_2)
+    metrics().observe_dijkstra(|| graph.shortest_path(node_id_1, node_id_2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::memory::map_repository::MemoryMapRepository;
+    use crate::repositories::memory::order_repository::MemoryOrderRepository;
+    use crate::repositories::memory::tow_truck_repository::MemoryTowTruckRepository;
+    use crate::repositories::memory::MemoryStore;
+
+    type Service =
+        TowTruckService<MemoryTowTruckRepository, MemoryOrderRepository, MemoryMapRepository>;
+
+    fn service(store: &MemoryStore) -> Service {
+        TowTruckService::new(
+            MemoryTowTruckRepository::new(store.clone()),
+            MemoryOrderRepository::new(store.clone()),
+            MemoryMapRepository::new(store.clone()),
+            &Settings::default(),
+        )
+    }
+
+    // エリア 1: 1 -(10)- 2 -(10)- 3 -(10)- 4 と、どこにも繋がっていない 5
+    // エリア 2: 10 -(1)- 11
+    fn build_map(store: &MemoryStore) {
+        for node_id in 1..=5 {
+            store.insert_node(node_id, 1);
+        }
+        store.insert_edge(1, 2, 10);
+        store.insert_edge(2, 3, 10);
+        store.insert_edge(3, 4, 10);
+        store.insert_node(10, 2);
+        store.insert_node(11, 2);
+        store.insert_edge(10, 11, 1);
+    }
+
+    fn add_tow_truck(store: &MemoryStore, area_id: i32, node_id: i32, status: &str) -> i32 {
+        let driver_id = store.insert_user(&format!("driver{}", node_id), "driver");
+        store.insert_tow_truck(driver_id, area_id, node_id, status)
+    }
+
+    #[actix_rt::test]
+    async fn nearest_tow_truck_is_chosen_by_route_distance() {
+        let store = MemoryStore::new();
+        build_map(&store);
+        let client_id = store.insert_user("client1", "client");
+        add_tow_truck(&store, 1, 4, "available");
+        let nearest_id = add_tow_truck(&store, 1, 2, "available");
+        let order_id = store.insert_order(client_id, 1, 1000.0);
+
+        let tow_truck = service(&store)
+            .get_nearest_available_tow_trucks(order_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(tow_truck.id, nearest_id);
+        assert_eq!(tow_truck.node_id, 2);
+        assert_eq!(tow_truck.driver_username.as_deref(), Some("driver2"));
+    }
+
+    #[actix_rt::test]
+    async fn busy_tow_trucks_are_ignored() {
+        let store = MemoryStore::new();
+        build_map(&store);
+        let client_id = store.insert_user("client1", "client");
+        add_tow_truck(&store, 1, 1, "busy");
+        let available_id = add_tow_truck(&store, 1, 3, "available");
+        let order_id = store.insert_order(client_id, 1, 1000.0);
+
+        let tow_truck = service(&store)
+            .get_nearest_available_tow_trucks(order_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(tow_truck.id, available_id);
+    }
+
+    #[actix_rt::test]
+    async fn unreachable_tow_truck_is_not_returned() {
+        let store = MemoryStore::new();
+        build_map(&store);
+        let client_id = store.insert_user("client1", "client");
+        add_tow_truck(&store, 1, 5, "available");
+        let order_id = store.insert_order(client_id, 1, 1000.0);
+
+        let tow_truck = service(&store)
+            .get_nearest_available_tow_trucks(order_id)
+            .await
+            .unwrap();
+
+        assert!(tow_truck.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn reachable_tow_truck_is_preferred_over_unreachable_one() {
+        let store = MemoryStore::new();
+        build_map(&store);
+        let client_id = store.insert_user("client1", "client");
+        add_tow_truck(&store, 1, 5, "available");
+        let reachable_id = add_tow_truck(&store, 1, 4, "available");
+        let order_id = store.insert_order(client_id, 1, 1000.0);
+
+        let tow_truck = service(&store)
+            .get_nearest_available_tow_trucks(order_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(tow_truck.id, reachable_id);
+    }
+
+    #[actix_rt::test]
+    async fn no_tow_truck_in_area_returns_none() {
+        let store = MemoryStore::new();
+        build_map(&store);
+        let client_id = store.insert_user("client1", "client");
+        // 別エリアのレッカー車は対象外
+        add_tow_truck(&store, 2, 10, "available");
+        let order_id = store.insert_order(client_id, 1, 1000.0);
+
+        let tow_truck = service(&store)
+            .get_nearest_available_tow_trucks(order_id)
+            .await
+            .unwrap();
+
+        assert!(tow_truck.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn tow_truck_beyond_max_distance_is_not_returned() {
+        let store = MemoryStore::new();
+        build_map(&store);
+        let client_id = store.insert_user("client1", "client");
+        add_tow_truck(&store, 1, 4, "available");
+        let order_id = store.insert_order(client_id, 1, 1000.0);
+        let mut settings = Settings::default();
+        settings.dispatch.nearest_tow_truck_max_distance = 29;
+        let service = TowTruckService::new(
+            MemoryTowTruckRepository::new(store.clone()),
+            MemoryOrderRepository::new(store.clone()),
+            MemoryMapRepository::new(store.clone()),
+            &settings,
+        );
+
+        let tow_truck = service
+            .get_nearest_available_tow_trucks(order_id)
+            .await
+            .unwrap();
+
+        assert!(tow_truck.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn unknown_order_is_an_error() {
+        let store = MemoryStore::new();
+        build_map(&store);
+
+        let result = service(&store).get_nearest_available_tow_trucks(1).await;
+
+        assert!(result.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn invalid_job_history_page_is_rejected() {
+        let store = MemoryStore::new();
+        build_map(&store);
+        let driver_id = store.insert_user("driver1", "driver");
+        store.insert_tow_truck(driver_id, 1, 1, "available");
+
+        for (page, page_size) in [(-1, 10), (0, 0), (0, -1)] {
+            let result = service(&store)
+                .get_driver_job_history(driver_id, page, page_size)
+                .await;
+            assert!(matches!(result, Err(AppError::BadRequest)));
+        }
+        assert!(service(&store)
+            .get_driver_job_history(driver_id, 0, 1000)
+            .await
+            .is_ok());
+    }
 }
@@ -8,10 +8,18 @@ pub enum AppError {
     BadRequest,
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("Forbidden")]
+    Forbidden,
     #[error("Not Found")]
     NotFound,
     #[error("Conflict")]
     Conflict,
+    #[error("Payload Too Large")]
+    PayloadTooLarge,
+    #[error("Unsupported Media Type")]
+    UnsupportedMediaType,
+    #[error("Too Many Requests")]
+    TooManyRequests { retry_after_secs: u64 },
     #[error("Internal Server Error")]
     InternalServerError,
     #[error(transparent)]
@@ -33,8 +41,16 @@ impl ResponseError for AppError {
         match *self {
             AppError::BadRequest => HttpResponse::BadRequest().json(error_response),
             AppError::Unauthorized => HttpResponse::Unauthorized().json(error_response),
+            AppError::Forbidden => HttpResponse::Forbidden().json(error_response),
             AppError::NotFound => HttpResponse::NotFound().json(error_response),
             AppError::Conflict => HttpResponse::Conflict().json(error_response),
+            AppError::PayloadTooLarge => HttpResponse::PayloadTooLarge().json(error_response),
+            AppError::UnsupportedMediaType => {
+                HttpResponse::UnsupportedMediaType().json(error_response)
+            }
+            AppError::TooManyRequests { retry_after_secs } => HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after_secs.to_string()))
+                .json(error_response),
             AppError::InternalServerError => {
                 HttpResponse::InternalServerError().json(error_response)
             }
@@ -0,0 +1,105 @@
+use chrono::Utc;
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::models::user::AuthenticatedUser;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AccessTokenClaims {
+    sub: i32,
+    role: String,
+    dispatcher_id: Option<i32>,
+    area_id: Option<i32>,
+    iat: i64,
+    exp: i64,
+}
+
+struct SigningKey {
+    kid: String,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+}
+
+// HS256 で署名した短命のアクセストークンを発行・検証する。
+// 先頭の鍵で署名し、検証は kid が一致する鍵で行うため、鍵を先頭に追加すればローテーションできる
+pub struct AccessTokenSigner {
+    keys: Vec<SigningKey>,
+    ttl_secs: i64,
+}
+
+impl std::fmt::Debug for AccessTokenSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessTokenSigner")
+            .field(
+                "kids",
+                &self.keys.iter().map(|key| &key.kid).collect::<Vec<_>>(),
+            )
+            .field("ttl_secs", &self.ttl_secs)
+            .finish()
+    }
+}
+
+impl AccessTokenSigner {
+    // keys は "kid:secret,kid:secret" 形式
+    pub fn from_key_list(keys: &str, ttl_secs: i64) -> Option<Self> {
+        let keys: Vec<SigningKey> = keys
+            .split(',')
+            .filter_map(|entry| entry.trim().split_once(':'))
+            .filter(|(kid, secret)| !kid.is_empty() && !secret.is_empty())
+            .map(|(kid, secret)| SigningKey {
+                kid: kid.to_string(),
+                encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+                decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            })
+            .collect();
+
+        if keys.is_empty() {
+            return None;
+        }
+
+        Some(AccessTokenSigner { keys, ttl_secs })
+    }
+
+    pub fn ttl_secs(&self) -> i64 {
+        self.ttl_secs
+    }
+
+    pub fn issue(&self, authenticated_user: &AuthenticatedUser) -> Option<String> {
+        let signing_key = &self.keys[0];
+        let now = Utc::now().timestamp();
+        let claims = AccessTokenClaims {
+            sub: authenticated_user.user_id,
+            role: authenticated_user.role.clone(),
+            dispatcher_id: authenticated_user.dispatcher_id,
+            area_id: authenticated_user.area_id,
+            iat: now,
+            exp: now + self.ttl_secs,
+        };
+
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(signing_key.kid.clone());
+
+        encode(&header, &claims, &signing_key.encoding_key).ok()
+    }
+
+    pub fn verify(&self, token: &str) -> Option<AuthenticatedUser> {
+        let kid = decode_header(token).ok()?.kid?;
+        let signing_key = self.keys.iter().find(|key| key.kid == kid)?;
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 0;
+        let claims = decode::<AccessTokenClaims>(token, &signing_key.decoding_key, &validation)
+            .ok()?
+            .claims;
+
+        Some(AuthenticatedUser {
+            user_id: claims.sub,
+            role: claims.role,
+            dispatcher_id: claims.dispatcher_id,
+            area_id: claims.area_id,
+            scopes: None,
+        })
+    }
+}
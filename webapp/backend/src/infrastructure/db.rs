@@ -1,9 +1,153 @@
-use sqlx::mysql::MySqlPool;
-use std::env;
+use std::str::FromStr;
+use std::time::Duration;
 
-pub async fn create_pool() -> MySqlPool {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    MySqlPool::connect(&database_url)
+use log::LevelFilter;
+use sqlx::mysql::{
+    MySqlConnectOptions, MySqlDatabaseError, MySqlPool, MySqlPoolOptions, MySqlQueryResult,
+};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteQueryResult};
+use sqlx::ConnectOptions;
+
+use crate::config::DatabaseSettings;
+
+// DATABASE_URL のスキームで MySQL と SQLite を切り替える
+#[derive(Clone, Debug)]
+pub enum DbPool {
+    MySql(MySqlPool),
+    Sqlite(SqlitePool),
+}
+
+// MySQL と SQLite で文法が異なるクエリ。それ以外は両方で同じ SQL を使う
+#[derive(Debug)]
+pub struct Dialect {
+    // バインドした秒数だけ現在より前の日時
+    pub seconds_ago: &'static str,
+    pub delete_locations_by_driver_id: &'static str,
+}
+
+pub const MYSQL_DIALECT: Dialect = Dialect {
+    seconds_ago: "NOW() - INTERVAL ? SECOND",
+    delete_locations_by_driver_id:
+        "DELETE l FROM locations l JOIN tow_trucks tt ON l.tow_truck_id = tt.id WHERE tt.driver_id = ?",
+};
+
+pub const SQLITE_DIALECT: Dialect = Dialect {
+    seconds_ago: "datetime('now', '-' || ? || ' seconds')",
+    delete_locations_by_driver_id:
+        "DELETE FROM locations WHERE tow_truck_id IN (SELECT id FROM tow_trucks WHERE driver_id = ?)",
+};
+
+// INSERT で採番された ID。MySQL は last_insert_id、SQLite は last_insert_rowid から取得する
+pub trait InsertedId {
+    fn inserted_id(&self) -> i64;
+}
+
+impl InsertedId for MySqlQueryResult {
+    fn inserted_id(&self) -> i64 {
+        self.last_insert_id() as i64
+    }
+}
+
+impl InsertedId for SqliteQueryResult {
+    fn inserted_id(&self) -> i64 {
+        self.last_insert_rowid()
+    }
+}
+
+// UNIQUE 制約違反かどうか。MySQL は ER_DUP_ENTRY、SQLite は SQLITE_CONSTRAINT_UNIQUE / PRIMARYKEY
+pub fn is_unique_violation(err: &sqlx::Error) -> bool {
+    let db_err = match err {
+        sqlx::Error::Database(db_err) => db_err,
+        _ => return false,
+    };
+
+    match db_err.try_downcast_ref::<MySqlDatabaseError>() {
+        Some(mysql_err) => mysql_err.number() == 1062,
+        None => matches!(db_err.code().as_deref(), Some("2067") | Some("1555")),
+    }
+}
+
+// 本体をプールの種類ごとに展開し、MySQL と SQLite のクエリを一度だけ書けるようにする。
+// 本体ではプールを $conn として、文法が異なるクエリを $dialect として参照できる
+macro_rules! with_pool {
+    ($pool:expr, |$conn:ident| $body:expr) => {
+        match $pool {
+            $crate::infrastructure::db::DbPool::MySql($conn) => $body,
+            $crate::infrastructure::db::DbPool::Sqlite($conn) => $body,
+        }
+    };
+    ($pool:expr, |$conn:ident, $dialect:ident| $body:expr) => {
+        match $pool {
+            $crate::infrastructure::db::DbPool::MySql($conn) => {
+                let $dialect = &$crate::infrastructure::db::MYSQL_DIALECT;
+                $body
+            }
+            $crate::infrastructure::db::DbPool::Sqlite($conn) => {
+                let $dialect = &$crate::infrastructure::db::SQLITE_DIALECT;
+                $body
+            }
+        }
+    };
+}
+pub(crate) use with_pool;
+
+pub async fn create_pool(settings: &DatabaseSettings) -> DbPool {
+    if settings.url.starts_with("sqlite:") {
+        return DbPool::Sqlite(create_sqlite_pool(settings).await);
+    }
+
+    let mut options = MySqlConnectOptions::from_str(&settings.url)
+        .expect("DATABASE_URL must be a valid MySQL URL");
+    configure_query_log(&mut options, settings);
+
+    DbPool::MySql(
+        MySqlPoolOptions::new()
+            .max_connections(settings.max_connections)
+            .min_connections(settings.min_connections)
+            .connect_timeout(Duration::from_secs(settings.acquire_timeout_secs))
+            .idle_timeout(settings.idle_timeout_secs.map(Duration::from_secs))
+            .connect_with(options)
+            .await
+            .expect("Failed to create pool"),
+    )
+}
+
+async fn create_sqlite_pool(settings: &DatabaseSettings) -> SqlitePool {
+    let mut options = SqliteConnectOptions::from_str(&settings.url)
+        .expect("DATABASE_URL must be a valid SQLite URL")
+        .create_if_missing(true);
+    configure_query_log(&mut options, settings);
+
+    // インメモリの DB はコネクションごとに別の DB になり、閉じると消えるため、
+    // コネクションを1本に限定して使い続ける
+    let pool_options = if settings.url.contains(":memory:") || settings.url.contains("mode=memory")
+    {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+    } else {
+        SqlitePoolOptions::new()
+            .max_connections(settings.max_connections)
+            .min_connections(settings.min_connections)
+            .idle_timeout(settings.idle_timeout_secs.map(Duration::from_secs))
+    };
+
+    pool_options
+        .connect_timeout(Duration::from_secs(settings.acquire_timeout_secs))
+        .connect_with(options)
         .await
         .expect("Failed to create pool")
 }
+
+// sqlx はデフォルトですべてのクエリを info で出力するため debug に下げ、
+// 閾値を超えたクエリのみ warn で出力する。ログは発行元のリクエストのスパン内で記録される
+fn configure_query_log<C: ConnectOptions>(options: &mut C, settings: &DatabaseSettings) {
+    options
+        .log_statements(LevelFilter::Debug)
+        .log_slow_statements(
+            LevelFilter::Warn,
+            Duration::from_millis(settings.slow_query_threshold_ms),
+        );
+}
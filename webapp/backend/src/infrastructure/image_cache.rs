@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use actix_web::web::Bytes;
+use log::error;
+use lru::LruCache;
+use serde::Serialize;
+
+use crate::errors::AppError;
+use crate::infrastructure::image_resizer::{ImageOutputFormat, ResizedImage};
+use crate::utils::hash_bytes;
+
+struct MemoryTier {
+    entries: LruCache<String, ResizedImage>,
+    total_bytes: usize,
+}
+
+// ディスク上のファイル名とサイズ。起動時にディレクトリを走査し、更新日時の古い順に並べる
+struct DiskTier {
+    files: LruCache<String, u64>,
+    total_bytes: u64,
+}
+
+impl DiskTier {
+    fn load(disk_dir: &Path) -> Self {
+        let mut files: Vec<(String, u64, std::time::SystemTime)> = Vec::new();
+        match std::fs::read_dir(disk_dir) {
+            Ok(entries) => {
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    let Ok(metadata) = entry.metadata() else {
+                        continue;
+                    };
+                    if !metadata.is_file() {
+                        continue;
+                    }
+                    // 前回のプロセスが書き込み途中で終了した一時ファイル
+                    if path.extension().is_some_and(|extension| extension == "tmp") {
+                        let _ = std::fs::remove_file(&path);
+                        continue;
+                    }
+                    let modified = metadata
+                        .modified()
+                        .unwrap_or(std::time::SystemTime::UNIX_EPOCH);
+                    files.push((
+                        entry.file_name().to_string_lossy().into_owned(),
+                        metadata.len(),
+                        modified,
+                    ));
+                }
+            }
+            Err(e) => error!(
+                "画像キャッシュのディレクトリの読み込みに失敗しました: {:?}",
+                e
+            ),
+        }
+        files.sort_by_key(|(_, _, modified)| *modified);
+
+        let mut disk = DiskTier {
+            files: LruCache::unbounded(),
+            total_bytes: 0,
+        };
+        for (name, size, _) in files {
+            disk.insert(name, size);
+        }
+        disk
+    }
+
+    fn insert(&mut self, name: String, size: u64) {
+        if let Some(old_size) = self.files.put(name, size) {
+            self.total_bytes -= old_size;
+        }
+        self.total_bytes += size;
+    }
+
+    // 上限を超えた分を古い順に索引から外し、削除するファイル名を返す
+    fn evict(&mut self, max_bytes: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.total_bytes > max_bytes {
+            match self.files.pop_lru() {
+                Some((name, size)) => {
+                    self.total_bytes -= size;
+                    evicted.push(name);
+                }
+                None => break,
+            }
+        }
+        evicted
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImageCacheStats {
+    pub memory_hits: u64,
+    pub disk_hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+    pub max_bytes: usize,
+    pub disk_entries: usize,
+    pub disk_bytes: u64,
+    pub disk_max_bytes: u64,
+}
+
+// リサイズ済み画像のキャッシュ。メモリ上は合計バイト数で上限を設けた LRU とし、
+// disk_dir を指定した場合は再起動後も使えるようディスクにも保存する。
+// ディスク上も disk_max_bytes を超えた分は古いファイルから削除する
+pub struct ImageCache {
+    memory: Mutex<MemoryTier>,
+    max_bytes: usize,
+    disk_dir: Option<PathBuf>,
+    disk: Mutex<DiskTier>,
+    disk_max_bytes: u64,
+    // 同じ画像のキャッシュミスが同時に起きた場合に、リサイズを1回に抑えるためのキーごとのロック
+    in_flight: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    memory_hits: AtomicU64,
+    disk_hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl std::fmt::Debug for ImageCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImageCache")
+            .field("max_bytes", &self.max_bytes)
+            .field("disk_dir", &self.disk_dir)
+            .field("disk_max_bytes", &self.disk_max_bytes)
+            .finish()
+    }
+}
+
+impl ImageCache {
+    pub fn new(max_bytes: usize, disk_dir: Option<PathBuf>, disk_max_bytes: u64) -> Self {
+        let mut disk = DiskTier {
+            files: LruCache::unbounded(),
+            total_bytes: 0,
+        };
+        if let Some(disk_dir) = &disk_dir {
+            if let Err(e) = std::fs::create_dir_all(disk_dir) {
+                error!("画像キャッシュのディレクトリの作成に失敗しました: {:?}", e);
+            }
+            // 上限を下げて再起動した場合も、起動時に上限まで削除する
+            disk = DiskTier::load(disk_dir);
+            for name in disk.evict(disk_max_bytes) {
+                let _ = std::fs::remove_file(disk_dir.join(name));
+            }
+        }
+
+        ImageCache {
+            memory: Mutex::new(MemoryTier {
+                entries: LruCache::unbounded(),
+                total_bytes: 0,
+            }),
+            max_bytes,
+            disk_dir,
+            disk: Mutex::new(disk),
+            disk_max_bytes,
+            in_flight: Mutex::new(HashMap::new()),
+            memory_hits: AtomicU64::new(0),
+            disk_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn get_or_try_insert_with<F, Fut>(
+        &self,
+        key: &str,
+        format: ImageOutputFormat,
+        resize: F,
+    ) -> Result<ResizedImage, AppError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<ResizedImage, AppError>>,
+    {
+        if let Some(image) = self.get_from_memory(key) {
+            self.memory_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(image);
+        }
+
+        let key_lock = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        let result = {
+            let _guard = key_lock.lock().await;
+            self.load_or_resize(key, format, resize).await
+        };
+
+        // 待機中のリクエストがいなければロックを片付ける
+        {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            if Arc::strong_count(&key_lock) <= 2 {
+                in_flight.remove(key);
+            }
+        }
+
+        result
+    }
+
+    async fn load_or_resize<F, Fut>(
+        &self,
+        key: &str,
+        format: ImageOutputFormat,
+        resize: F,
+    ) -> Result<ResizedImage, AppError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<ResizedImage, AppError>>,
+    {
+        // 先行したリクエストがリサイズを終えていればその結果を使う
+        if let Some(image) = self.get_from_memory(key) {
+            self.memory_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(image);
+        }
+
+        if let Some(image) = self.get_from_disk(key, format).await {
+            self.disk_hits.fetch_add(1, Ordering::Relaxed);
+            self.put_to_memory(key, image.clone());
+            return Ok(image);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let image = resize().await?;
+        self.put_to_disk(key, format, &image).await;
+        self.put_to_memory(key, image.clone());
+        Ok(image)
+    }
+
+    // メモリ上のエントリのみ破棄する。ディスク上のファイルはキーが変わらない限り再利用される
+    pub fn remove_prefix(&self, prefix: &str) {
+        let mut memory = self.memory.lock().unwrap();
+        let keys: Vec<String> = memory
+            .entries
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in keys {
+            if let Some(image) = memory.entries.pop(&key) {
+                memory.total_bytes -= image.bytes.len();
+            }
+        }
+    }
+
+    pub fn stats(&self) -> ImageCacheStats {
+        let memory = self.memory.lock().unwrap();
+        let disk = self.disk.lock().unwrap();
+        ImageCacheStats {
+            memory_hits: self.memory_hits.load(Ordering::Relaxed),
+            disk_hits: self.disk_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: memory.entries.len(),
+            bytes: memory.total_bytes,
+            max_bytes: self.max_bytes,
+            disk_entries: disk.files.len(),
+            disk_bytes: disk.total_bytes,
+            disk_max_bytes: self.disk_max_bytes,
+        }
+    }
+
+    fn get_from_memory(&self, key: &str) -> Option<ResizedImage> {
+        self.memory.lock().unwrap().entries.get(key).cloned()
+    }
+
+    fn put_to_memory(&self, key: &str, image: ResizedImage) {
+        let size = image.bytes.len();
+        if size > self.max_bytes {
+            return;
+        }
+
+        let mut memory = self.memory.lock().unwrap();
+        if let Some(old_image) = memory.entries.put(key.to_string(), image) {
+            memory.total_bytes -= old_image.bytes.len();
+        }
+        memory.total_bytes += size;
+
+        while memory.total_bytes > self.max_bytes {
+            match memory.entries.pop_lru() {
+                Some((_, evicted)) => memory.total_bytes -= evicted.bytes.len(),
+                None => break,
+            }
+        }
+    }
+
+    fn disk_file_name(key: &str, format: ImageOutputFormat) -> String {
+        format!("{}.{}", hash_bytes(key.as_bytes()), format.extension())
+    }
+
+    async fn get_from_disk(&self, key: &str, format: ImageOutputFormat) -> Option<ResizedImage> {
+        let disk_dir = self.disk_dir.as_ref()?;
+        let name = Self::disk_file_name(key, format);
+        let bytes = match tokio::fs::read(disk_dir.join(&name)).await {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                // 外部から削除された場合は索引からも外す
+                let mut disk = self.disk.lock().unwrap();
+                if let Some(size) = disk.files.pop(&name) {
+                    disk.total_bytes -= size;
+                }
+                return None;
+            }
+        };
+        // 最近使ったファイルとして記録する。他のプロセスが書き込んだファイルもここで索引に加える
+        self.record_disk_file(name, bytes.len() as u64).await;
+
+        Some(ResizedImage {
+            etag: format!("\"{}\"", hash_bytes(&bytes)),
+            bytes: Bytes::from(bytes),
+            content_type: format.content_type(),
+        })
+    }
+
+    async fn put_to_disk(&self, key: &str, format: ImageOutputFormat, image: &ResizedImage) {
+        let disk_dir = match &self.disk_dir {
+            Some(disk_dir) => disk_dir,
+            None => return,
+        };
+        let size = image.bytes.len() as u64;
+        if size > self.disk_max_bytes {
+            return;
+        }
+        let name = Self::disk_file_name(key, format);
+        let path = disk_dir.join(&name);
+
+        // 書き込み途中のファイルを読まないよう、一時ファイルに書いてからリネームする
+        let tmp_path = path.with_extension("tmp");
+        let result = match tokio::fs::write(&tmp_path, &image.bytes).await {
+            Ok(_) => tokio::fs::rename(&tmp_path, &path).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(_) => self.record_disk_file(name, size).await,
+            Err(e) => {
+                error!("画像キャッシュのディスクへの保存に失敗しました: {:?}", e);
+                let _ = tokio::fs::remove_file(&tmp_path).await;
+            }
+        }
+    }
+
+    async fn record_disk_file(&self, name: String, size: u64) {
+        let Some(disk_dir) = &self.disk_dir else {
+            return;
+        };
+        let evicted = {
+            let mut disk = self.disk.lock().unwrap();
+            disk.insert(name, size);
+            disk.evict(self.disk_max_bytes)
+        };
+        for name in evicted {
+            let _ = tokio::fs::remove_file(disk_dir.join(name)).await;
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::io::Cursor;
+
+use actix_web::web::Bytes;
+use image::{
+    imageops::FilterType, DynamicImage, ImageFormat, ImageReader, Limits, Rgba, RgbaImage,
+};
+use sha2::{Digest, Sha256};
+
+// プロフィール画像として返せるサイズ（縦横の最大ピクセル数）
+pub const PROFILE_IMAGE_SIZES: [u32; 3] = [64, 128, 500];
+pub const DEFAULT_PROFILE_IMAGE_SIZE: u32 = 500;
+// 展開後に巨大になる画像でメモリを使い果たさないよう、デコード時の上限を設ける
+const MAX_SOURCE_IMAGE_DIMENSION: u32 = 8192;
+const MAX_SOURCE_IMAGE_ALLOC_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(Clone, Debug)]
+pub struct ResizedImage {
+    pub bytes: Bytes,
+    pub etag: String,
+    pub content_type: &'static str,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ImageOutputFormat {
+    Png,
+    WebP,
+}
+
+impl ImageOutputFormat {
+    // Accept ヘッダーで image/webp が許可されていれば WebP、それ以外は PNG を返す
+    pub fn from_accept(accept: Option<&str>) -> Self {
+        let accepts_webp = accept.is_some_and(|accept| {
+            accept.split(',').any(|media_range| {
+                let mut params = media_range.split(';').map(|param| param.trim());
+                params.next() == Some("image/webp")
+                    && !params.any(|param| {
+                        param.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0)
+                    })
+            })
+        });
+
+        if accepts_webp {
+            ImageOutputFormat::WebP
+        } else {
+            ImageOutputFormat::Png
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ImageOutputFormat::Png => "image/png",
+            ImageOutputFormat::WebP => "image/webp",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageOutputFormat::Png => "png",
+            ImageOutputFormat::WebP => "webp",
+        }
+    }
+}
+
+// 縦横のピクセル数と確保するメモリ量に上限を設けてデコードする。
+// format を指定しない場合は先頭のバイト列から判定する
+pub fn decode_image(
+    source: &[u8],
+    format: Option<ImageFormat>,
+) -> Result<DynamicImage, image::ImageError> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_IMAGE_DIMENSION);
+    limits.max_alloc = Some(MAX_SOURCE_IMAGE_ALLOC_BYTES);
+
+    let mut reader = match format {
+        Some(format) => ImageReader::with_format(Cursor::new(source), format),
+        None => ImageReader::new(Cursor::new(source)).with_guessed_format()?,
+    };
+    reader.limits(limits);
+    reader.decode()
+}
+
+// アスペクト比を保ったまま size x size に収まるよう縮小・拡大する
+pub fn resize_image(
+    source: &[u8],
+    size: u32,
+    format: ImageOutputFormat,
+) -> Result<Vec<u8>, image::ImageError> {
+    let resized = decode_image(source, None)?.resize(size, size, FilterType::Lanczos3);
+    // WebP のエンコーダーは RGB8・RGBA8 のみ対応しているため揃えておく
+    encode_image(resized.to_rgba8(), format)
+}
+
+// seed から左右対称の 5x5 のパターンと色を決め、identicon 風のアバターを生成する。
+// 同じ seed からは常に同じ画像が生成される
+pub fn generate_identicon(
+    seed: &str,
+    size: u32,
+    format: ImageOutputFormat,
+) -> Result<Vec<u8>, image::ImageError> {
+    const GRID: u32 = 5;
+    // 余白をセルの半分とし、縦横を 1 + 5 * 2 + 1 = 12 単位に分割する
+    const UNITS: u32 = GRID * 2 + 2;
+
+    let hash = Sha256::digest(seed.as_bytes());
+    // 淡くなりすぎないよう、各成分を 48〜207 に収める
+    let foreground = Rgba([
+        48 + hash[0] % 160,
+        48 + hash[1] % 160,
+        48 + hash[2] % 160,
+        255,
+    ]);
+    let background = Rgba([240, 240, 240, 255]);
+
+    // 左3列分のセルを塗るかどうかを決め、右2列は左右反転して使う
+    let is_filled = |column: u32, row: u32| {
+        let column = column.min(GRID - 1 - column);
+        let bit = (column * GRID + row) as usize;
+        hash[3 + bit / 8] & (1 << (bit % 8)) != 0
+    };
+
+    let image = RgbaImage::from_fn(size, size, |x, y| {
+        let unit_x = x * UNITS / size;
+        let unit_y = y * UNITS / size;
+        if unit_x == 0 || unit_y == 0 || unit_x == UNITS - 1 || unit_y == UNITS - 1 {
+            return background;
+        }
+
+        if is_filled((unit_x - 1) / 2, (unit_y - 1) / 2) {
+            foreground
+        } else {
+            background
+        }
+    });
+
+    encode_image(image, format)
+}
+
+fn encode_image(image: RgbaImage, format: ImageOutputFormat) -> Result<Vec<u8>, image::ImageError> {
+    let mut output = Vec::new();
+    let image_format = match format {
+        ImageOutputFormat::Png => ImageFormat::Png,
+        ImageOutputFormat::WebP => ImageFormat::WebP,
+    };
+    DynamicImage::ImageRgba8(image).write_to(&mut Cursor::new(&mut output), image_format)?;
+
+    Ok(output)
+}
@@ -0,0 +1,23 @@
+use tracing_subscriber::EnvFilter;
+
+use crate::config::{LogFormat, LogSettings};
+
+// tracing のサブスクライバーを登録する。log クレートのマクロ（sqlx のクエリログを含む）も
+// tracing のイベントとして扱われ、発生時のスパン（リクエスト ID など）とともに出力される
+pub fn init(settings: &LogSettings) {
+    let filter = EnvFilter::try_new(&settings.filter).unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    let result = match settings.format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            // 内側のスパンにはリクエスト ID がないため、外側からすべてのスパンを出力する
+            .with_current_span(false)
+            .with_span_list(true)
+            .try_init(),
+    };
+    if let Err(e) = result {
+        eprintln!("ログの初期化に失敗しました: {}", e);
+    }
+}
@@ -0,0 +1,216 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+
+// ロックなしで許容する連続失敗回数。これを超えると失敗のたびにロック時間が倍になる
+const MAX_FREE_FAILURES_PER_USERNAME: u32 = 5;
+const MAX_FREE_FAILURES_PER_IP: u32 = 20;
+const BASE_LOCKOUT: Duration = Duration::from_secs(1);
+const MAX_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Clone, Debug)]
+struct LoginAttempts {
+    failures: u32,
+    locked_until: Option<Instant>,
+    last_failed_at: Instant,
+}
+
+// ユーザー名・IP アドレスごとのログイン失敗回数を全ワーカーで共有し、総当たり攻撃を遅延・ロックする
+#[derive(Debug)]
+pub struct LoginThrottle {
+    attempts: Mutex<LruCache<String, LoginAttempts>>,
+}
+
+fn username_key(username: &str) -> String {
+    format!("user:{}", username)
+}
+
+fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+impl LoginThrottle {
+    pub fn new(capacity: usize) -> Self {
+        LoginThrottle {
+            attempts: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    // ロック中の場合は解除までの残り時間を返す
+    pub fn check(&self, username: &str, ip: Option<&str>) -> Option<Duration> {
+        self.check_at(username, ip, Instant::now())
+    }
+
+    pub fn record_failure(&self, username: &str, ip: Option<&str>) {
+        self.record_failure_at(username, ip, Instant::now());
+    }
+
+    fn check_at(&self, username: &str, ip: Option<&str>, now: Instant) -> Option<Duration> {
+        let mut attempts = self.attempts.lock().unwrap();
+
+        let mut keys = vec![username_key(username)];
+        if let Some(ip) = ip {
+            keys.push(ip_key(ip));
+        }
+
+        keys.iter()
+            .filter_map(|key| attempts.get(key)?.locked_until)
+            .filter(|locked_until| *locked_until > now)
+            .map(|locked_until| locked_until - now)
+            .max()
+    }
+
+    fn record_failure_at(&self, username: &str, ip: Option<&str>, now: Instant) {
+        let mut attempts = self.attempts.lock().unwrap();
+
+        Self::increment(
+            &mut attempts,
+            username_key(username),
+            MAX_FREE_FAILURES_PER_USERNAME,
+            now,
+        );
+        if let Some(ip) = ip {
+            Self::increment(&mut attempts, ip_key(ip), MAX_FREE_FAILURES_PER_IP, now);
+        }
+    }
+
+    // ログイン成功時と管理者による解除時に呼ぶ。
+    // 同じ IP から複数アカウントを試す攻撃を防ぐため、IP アドレスのカウンタはリセットしない
+    pub fn unlock(&self, username: &str) {
+        self.attempts.lock().unwrap().pop(&username_key(username));
+    }
+
+    fn increment(
+        attempts: &mut LruCache<String, LoginAttempts>,
+        key: String,
+        max_free_failures: u32,
+        now: Instant,
+    ) {
+        let failures = match attempts.get(&key) {
+            // 最後の失敗から最大ロック時間が経過していればカウンタをリセットする
+            Some(attempt) if now - attempt.last_failed_at < MAX_LOCKOUT => attempt.failures + 1,
+            _ => 1,
+        };
+
+        let locked_until = if failures > max_free_failures {
+            let exponent = (failures - max_free_failures - 1).min(20);
+            let lockout = BASE_LOCKOUT
+                .checked_mul(1 << exponent)
+                .unwrap_or(MAX_LOCKOUT)
+                .min(MAX_LOCKOUT);
+            Some(now + lockout)
+        } else {
+            None
+        };
+
+        attempts.put(
+            key,
+            LoginAttempts {
+                failures,
+                locked_until,
+                last_failed_at: now,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IP: Option<&str> = Some("192.0.2.1");
+
+    fn fail(throttle: &LoginThrottle, username: &str, ip: Option<&str>, times: u32, now: Instant) {
+        for _ in 0..times {
+            throttle.record_failure_at(username, ip, now);
+        }
+    }
+
+    #[test]
+    fn username_is_locked_after_five_failures() {
+        let throttle = LoginThrottle::new(100);
+        let now = Instant::now();
+
+        fail(&throttle, "client1", None, 5, now);
+        assert_eq!(throttle.check_at("client1", None, now), None);
+
+        fail(&throttle, "client1", None, 1, now);
+        assert_eq!(throttle.check_at("client1", None, now), Some(BASE_LOCKOUT));
+        // 他のユーザー名には影響しない
+        assert_eq!(throttle.check_at("client2", None, now), None);
+    }
+
+    #[test]
+    fn ip_is_locked_after_twenty_failures() {
+        let throttle = LoginThrottle::new(100);
+        let now = Instant::now();
+
+        // ユーザー名ごとのロックにかからないよう、毎回別のユーザー名で失敗する
+        for i in 0..MAX_FREE_FAILURES_PER_IP {
+            fail(&throttle, &format!("user{}", i), IP, 1, now);
+        }
+        assert_eq!(throttle.check_at("other", IP, now), None);
+
+        fail(&throttle, "user20", IP, 1, now);
+        assert_eq!(throttle.check_at("other", IP, now), Some(BASE_LOCKOUT));
+        assert_eq!(throttle.check_at("other", Some("192.0.2.2"), now), None);
+        assert_eq!(throttle.check_at("other", None, now), None);
+    }
+
+    #[test]
+    fn lockout_doubles_and_is_capped_at_fifteen_minutes() {
+        let throttle = LoginThrottle::new(100);
+        let now = Instant::now();
+        fail(
+            &throttle,
+            "client1",
+            None,
+            MAX_FREE_FAILURES_PER_USERNAME,
+            now,
+        );
+
+        for expected_secs in [1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 900, 900] {
+            fail(&throttle, "client1", None, 1, now);
+            assert_eq!(
+                throttle.check_at("client1", None, now),
+                Some(Duration::from_secs(expected_secs))
+            );
+        }
+
+        // 指数が大きくなってもオーバーフローせず上限のまま
+        fail(&throttle, "client1", None, 100, now);
+        assert_eq!(throttle.check_at("client1", None, now), Some(MAX_LOCKOUT));
+    }
+
+    #[test]
+    fn lock_expires_and_counter_resets_after_max_lockout() {
+        let throttle = LoginThrottle::new(100);
+        let now = Instant::now();
+        fail(&throttle, "client1", None, 6, now);
+
+        let later = now + BASE_LOCKOUT;
+        assert_eq!(throttle.check_at("client1", None, later), None);
+
+        // 最後の失敗から MAX_LOCKOUT が経過すると 1 回目の失敗として数え直す
+        let much_later = now + MAX_LOCKOUT;
+        fail(&throttle, "client1", None, 5, much_later);
+        assert_eq!(throttle.check_at("client1", None, much_later), None);
+    }
+
+    #[test]
+    fn unlock_resets_username_but_not_ip() {
+        let throttle = LoginThrottle::new(100);
+        let now = Instant::now();
+        fail(&throttle, "client1", IP, MAX_FREE_FAILURES_PER_IP + 1, now);
+        assert!(throttle.check_at("client1", None, now).is_some());
+
+        throttle.unlock("client1");
+
+        assert_eq!(throttle.check_at("client1", None, now), None);
+        assert!(throttle.check_at("client1", IP, now).is_some());
+        // ユーザー名のカウンタは 0 から数え直す
+        fail(&throttle, "client1", None, 5, now);
+        assert_eq!(throttle.check_at("client1", None, now), None);
+    }
+}
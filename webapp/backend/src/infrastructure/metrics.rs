@@ -0,0 +1,167 @@
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use log::error;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+pub use prometheus::TEXT_FORMAT;
+
+// レイテンシのバケット（秒）。Dijkstra はリクエストより短い時間で終わるため細かく刻む
+const HTTP_DURATION_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+const DIJKSTRA_DURATION_BUCKETS: [f64; 10] = [
+    0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5,
+];
+
+// プロセス全体で共有するメトリクス。/metrics で Prometheus のテキスト形式で出力する
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests_total: IntCounterVec,
+    pub http_request_duration_seconds: HistogramVec,
+    pub db_pool_connections: IntGaugeVec,
+    pub db_pool_max_connections: IntGauge,
+    pub dijkstra_runs_total: IntCounter,
+    pub dijkstra_duration_seconds: Histogram,
+    pub image_cache_bytes: IntGauge,
+    pub image_cache_max_bytes: IntGauge,
+    pub image_cache_entries: IntGauge,
+    pub pending_orders: IntGaugeVec,
+    pub available_tow_trucks: IntGaugeVec,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let http_requests_total = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP リクエスト数"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP リクエストの処理時間（秒）",
+            )
+            .buckets(HTTP_DURATION_BUCKETS.to_vec()),
+            &["method", "route"],
+        )
+        .unwrap();
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "DB コネクションプールの接続数"),
+            &["state"],
+        )
+        .unwrap();
+        let db_pool_max_connections = IntGauge::new(
+            "db_pool_max_connections",
+            "DB コネクションプールの最大接続数",
+        )
+        .unwrap();
+        let dijkstra_runs_total =
+            IntCounter::new("dijkstra_runs_total", "最短経路探索の実行回数").unwrap();
+        let dijkstra_duration_seconds = Histogram::with_opts(
+            HistogramOpts::new("dijkstra_duration_seconds", "最短経路探索の処理時間（秒）")
+                .buckets(DIJKSTRA_DURATION_BUCKETS.to_vec()),
+        )
+        .unwrap();
+        let image_cache_bytes = IntGauge::new(
+            "image_cache_bytes",
+            "画像キャッシュのメモリ上の合計バイト数",
+        )
+        .unwrap();
+        let image_cache_max_bytes = IntGauge::new(
+            "image_cache_max_bytes",
+            "画像キャッシュのメモリ上の上限バイト数",
+        )
+        .unwrap();
+        let image_cache_entries = IntGauge::new(
+            "image_cache_entries",
+            "画像キャッシュのメモリ上のエントリ数",
+        )
+        .unwrap();
+        let pending_orders = IntGaugeVec::new(
+            Opts::new("pending_orders", "エリアごとの未配車の依頼数"),
+            &["area_id"],
+        )
+        .unwrap();
+        let available_tow_trucks = IntGaugeVec::new(
+            Opts::new("available_tow_trucks", "エリアごとの待機中のレッカー車数"),
+            &["area_id"],
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(http_requests_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(http_request_duration_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_pool_connections.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_pool_max_connections.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(dijkstra_runs_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(dijkstra_duration_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(image_cache_bytes.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(image_cache_max_bytes.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(image_cache_entries.clone()))
+            .unwrap();
+        registry.register(Box::new(pending_orders.clone())).unwrap();
+        registry
+            .register(Box::new(available_tow_trucks.clone()))
+            .unwrap();
+
+        Metrics {
+            registry,
+            http_requests_total,
+            http_request_duration_seconds,
+            db_pool_connections,
+            db_pool_max_connections,
+            dijkstra_runs_total,
+            dijkstra_duration_seconds,
+            image_cache_bytes,
+            image_cache_max_bytes,
+            image_cache_entries,
+            pending_orders,
+            available_tow_trucks,
+        }
+    }
+
+    pub fn observe_dijkstra<R>(&self, run: impl FnOnce() -> R) -> R {
+        let started_at = Instant::now();
+        let result = run();
+        self.dijkstra_runs_total.inc();
+        self.dijkstra_duration_seconds
+            .observe(started_at.elapsed().as_secs_f64());
+        result
+    }
+
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            error!("メトリクスの出力に失敗しました: {:?}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use log::info;
+use sqlx::mysql::{MySqlConnection, MySqlPool};
+use sqlx::{Executor, FromRow};
+use thiserror::Error;
+
+use crate::utils::hash_bytes;
+
+// 適用履歴のない既存の DB で、マイグレーションが適用済みかを判定するためのテーブル・カラム
+#[derive(Clone, Copy, Debug)]
+pub enum SchemaObject {
+    Table(&'static str),
+    Column(&'static str, &'static str),
+}
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+    // 適用履歴のない既存の DB では、これが存在すれば実行せずに適用済みとして記録する
+    pub adopt_if_exists: SchemaObject,
+}
+
+impl Migration {
+    fn checksum(&self) -> String {
+        hash_bytes(self.sql.as_bytes())
+    }
+}
+
+// バイナリに埋め込むマイグレーション。migrations/ にファイルを追加したらここにも登録する。
+// 0002 以降は mysql/migration の 1〜6 と同じ内容で、スキーマの変更ごとに1つのマイグレーションにする
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "baseline",
+        sql: include_str!("../../migrations/0001_baseline.sql"),
+        adopt_if_exists: SchemaObject::Table("users"),
+    },
+    Migration {
+        version: 2,
+        name: "order_offers",
+        sql: include_str!("../../migrations/0002_order_offers.sql"),
+        adopt_if_exists: SchemaObject::Table("order_offers"),
+    },
+    Migration {
+        version: 3,
+        name: "session_expiry",
+        sql: include_str!("../../migrations/0003_session_expiry.sql"),
+        adopt_if_exists: SchemaObject::Column("sessions", "created_at"),
+    },
+    Migration {
+        version: 4,
+        name: "password_reset_tokens",
+        sql: include_str!("../../migrations/0004_password_reset_tokens.sql"),
+        adopt_if_exists: SchemaObject::Table("password_reset_tokens"),
+    },
+    Migration {
+        version: 5,
+        name: "api_keys",
+        sql: include_str!("../../migrations/0005_api_keys.sql"),
+        adopt_if_exists: SchemaObject::Table("api_keys"),
+    },
+    Migration {
+        version: 6,
+        name: "user_disabled",
+        sql: include_str!("../../migrations/0006_user_disabled.sql"),
+        adopt_if_exists: SchemaObject::Column("users", "is_disabled"),
+    },
+    Migration {
+        version: 7,
+        name: "session_client_info",
+        sql: include_str!("../../migrations/0007_session_client_info.sql"),
+        adopt_if_exists: SchemaObject::Column("sessions", "ip_address"),
+    },
+];
+
+// 複数のインスタンスが同時に起動しても、マイグレーションは1つのプロセスだけが実行する
+const LOCK_NAME: &str = "schema_migrations";
+const LOCK_TIMEOUT_SECS: i64 = 60;
+
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+    #[error("他のプロセスがマイグレーション中のため、ロックを取得できませんでした")]
+    Locked,
+    #[error("適用済みのマイグレーション {version}_{name} の内容が変更されています")]
+    Modified { version: i64, name: &'static str },
+    #[error("マイグレーション {version}_{name} の適用に失敗しました: {source}")]
+    Failed {
+        version: i64,
+        name: &'static str,
+        source: sqlx::Error,
+    },
+}
+
+#[derive(Debug, FromRow)]
+struct AppliedMigration {
+    version: i64,
+    name: String,
+    checksum: String,
+    applied_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    // 適用後にファイルの内容が変更された
+    Modified,
+    // DB には記録があるが、バイナリに含まれていない
+    Unknown,
+}
+
+impl MigrationState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MigrationState::Applied => "applied",
+            MigrationState::Pending => "pending",
+            MigrationState::Modified => "modified",
+            MigrationState::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: String,
+    pub state: MigrationState,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+async fn is_tracked(conn: &mut MySqlConnection) -> Result<bool, sqlx::Error> {
+    let count = sqlx::query_scalar::<_, i64>(
+        "SELECT
+            COUNT(*)
+        FROM
+            information_schema.tables
+        WHERE
+            table_schema = DATABASE() AND table_name = 'schema_migrations'",
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(count > 0)
+}
+
+async fn schema_object_exists(
+    conn: &mut MySqlConnection,
+    object: SchemaObject,
+) -> Result<bool, sqlx::Error> {
+    let count = match object {
+        SchemaObject::Table(table) => {
+            sqlx::query_scalar::<_, i64>(
+                "SELECT
+                    COUNT(*)
+                FROM
+                    information_schema.tables
+                WHERE
+                    table_schema = DATABASE() AND table_name = ?",
+            )
+            .bind(table)
+            .fetch_one(&mut *conn)
+            .await?
+        }
+        SchemaObject::Column(table, column) => {
+            sqlx::query_scalar::<_, i64>(
+                "SELECT
+                    COUNT(*)
+                FROM
+                    information_schema.columns
+                WHERE
+                    table_schema = DATABASE() AND table_name = ? AND column_name = ?",
+            )
+            .bind(table)
+            .bind(column)
+            .fetch_one(&mut *conn)
+            .await?
+        }
+    };
+
+    Ok(count > 0)
+}
+
+async fn get_applied_migrations(
+    conn: &mut MySqlConnection,
+) -> Result<HashMap<i64, AppliedMigration>, sqlx::Error> {
+    let applied = sqlx::query_as::<_, AppliedMigration>(
+        "SELECT
+            version, name, checksum, applied_at
+        FROM
+            schema_migrations
+        ORDER BY
+            version",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(applied
+        .into_iter()
+        .map(|migration| (migration.version, migration))
+        .collect())
+}
+
+async fn record_migration(
+    conn: &mut MySqlConnection,
+    migration: &Migration,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO schema_migrations (version, name, checksum) VALUES (?, ?, ?)")
+        .bind(migration.version)
+        .bind(migration.name)
+        .bind(migration.checksum())
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+// 適用履歴を管理していない DB の場合は None を返す
+pub async fn status(pool: &MySqlPool) -> Result<Option<Vec<MigrationStatus>>, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    if !is_tracked(&mut conn).await? {
+        return Ok(None);
+    }
+
+    let mut applied = get_applied_migrations(&mut conn).await?;
+    let mut statuses: Vec<MigrationStatus> = MIGRATIONS
+        .iter()
+        .map(|migration| match applied.remove(&migration.version) {
+            Some(applied_migration) => MigrationStatus {
+                version: migration.version,
+                name: migration.name.to_string(),
+                state: if applied_migration.checksum == migration.checksum() {
+                    MigrationState::Applied
+                } else {
+                    MigrationState::Modified
+                },
+                applied_at: Some(applied_migration.applied_at),
+            },
+            None => MigrationStatus {
+                version: migration.version,
+                name: migration.name.to_string(),
+                state: MigrationState::Pending,
+                applied_at: None,
+            },
+        })
+        .collect();
+
+    statuses.extend(
+        applied
+            .into_values()
+            .map(|applied_migration| MigrationStatus {
+                version: applied_migration.version,
+                name: applied_migration.name,
+                state: MigrationState::Unknown,
+                applied_at: Some(applied_migration.applied_at),
+            }),
+    );
+    statuses.sort_by_key(|status| status.version);
+
+    Ok(Some(statuses))
+}
+
+// 未適用のマイグレーションを順に適用し、適用した件数を返す。
+// MySQL の DDL はトランザクションで巻き戻せないため、1件ずつ適用して記録する
+pub async fn run(pool: &MySqlPool) -> Result<usize, MigrationError> {
+    let mut conn = pool.acquire().await?;
+
+    let locked = sqlx::query_scalar::<_, Option<i64>>("SELECT GET_LOCK(?, ?)")
+        .bind(LOCK_NAME)
+        .bind(LOCK_TIMEOUT_SECS)
+        .fetch_one(&mut *conn)
+        .await?;
+    if locked != Some(1) {
+        return Err(MigrationError::Locked);
+    }
+
+    let result = run_locked(&mut conn).await;
+
+    sqlx::query("SELECT RELEASE_LOCK(?)")
+        .bind(LOCK_NAME)
+        .execute(&mut *conn)
+        .await?;
+
+    result
+}
+
+async fn run_locked(conn: &mut MySqlConnection) -> Result<usize, MigrationError> {
+    if !is_tracked(conn).await? {
+        (&mut *conn)
+            .execute(
+                "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version BIGINT PRIMARY KEY,
+                name VARCHAR(255) NOT NULL,
+                checksum VARCHAR(64) NOT NULL,
+                applied_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )",
+            )
+            .await?;
+
+        // init.sql と mysql/migration で構築済みの DB は、適用済みの変更を実行せずに記録する。
+        // 途中までしか mysql/migration を適用していない DB では、残りを以降で適用する
+        if schema_object_exists(conn, MIGRATIONS[0].adopt_if_exists).await? {
+            for migration in MIGRATIONS {
+                if schema_object_exists(conn, migration.adopt_if_exists).await? {
+                    record_migration(conn, migration).await?;
+                    info!(
+                        "既存のスキーマを {}_{} の適用済みとして記録しました",
+                        migration.version, migration.name
+                    );
+                }
+            }
+        }
+    }
+
+    let applied = get_applied_migrations(conn).await?;
+    let mut applied_count = 0;
+    for migration in MIGRATIONS {
+        match applied.get(&migration.version) {
+            Some(applied_migration) if applied_migration.checksum != migration.checksum() => {
+                return Err(MigrationError::Modified {
+                    version: migration.version,
+                    name: migration.name,
+                });
+            }
+            Some(_) => continue,
+            None => {}
+        }
+
+        (&mut *conn)
+            .execute(migration.sql)
+            .await
+            .map_err(|source| MigrationError::Failed {
+                version: migration.version,
+                name: migration.name,
+                source,
+            })?;
+        record_migration(conn, migration).await?;
+        applied_count += 1;
+        info!(
+            "マイグレーション {}_{} を適用しました",
+            migration.version, migration.name
+        );
+    }
+
+    Ok(applied_count)
+}
@@ -1 +1,11 @@
+pub mod access_token;
 pub mod db;
+pub mod image_cache;
+pub mod image_resizer;
+pub mod logging;
+pub mod login_throttle;
+pub mod metrics;
+pub mod migrations;
+pub mod sqlite;
+pub mod token_cache;
+pub mod trusted_proxies;
@@ -0,0 +1,130 @@
+use std::path::{Path, PathBuf};
+
+use chrono::DateTime;
+use log::info;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Executor;
+use thiserror::Error;
+
+const SCHEMA: &str = include_str!("../../sqlite/schema.sql");
+
+// 外部キーの参照先から順に読み込む。CSV の列名をそのまま INSERT の列名として使う
+const SEED_TABLES: [&str; 9] = [
+    "areas",
+    "users",
+    "dispatchers",
+    "tow_trucks",
+    "nodes",
+    "edges",
+    "locations",
+    "orders",
+    "completed_orders",
+];
+
+#[derive(Debug, Error)]
+pub enum SeedError {
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+    #[error("CSV ファイル {} を読み込めません: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("CSV ファイル {} の {line} 行目の列数がヘッダーと一致しません", path.display())]
+    Malformed { path: PathBuf, line: usize },
+}
+
+// スキーマを作成し、DB が空で seed_dir が指定されていれば CSV からデータを読み込む
+pub async fn initialize(pool: &SqlitePool, seed_dir: Option<&Path>) -> Result<(), SeedError> {
+    pool.execute(SCHEMA).await?;
+
+    let seed_dir = match seed_dir {
+        Some(seed_dir) => seed_dir,
+        None => return Ok(()),
+    };
+    let area_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM areas")
+        .fetch_one(pool)
+        .await?;
+    if area_count > 0 {
+        return Ok(());
+    }
+
+    let mut tx = pool.begin().await?;
+    for table in SEED_TABLES {
+        let path = seed_dir.join(format!("{}.csv", table));
+        let content = std::fs::read_to_string(&path).map_err(|source| SeedError::Read {
+            path: path.clone(),
+            source,
+        })?;
+
+        let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+        let columns = match lines.next() {
+            Some(header) => parse_csv_line(header),
+            None => continue,
+        };
+        let sql = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            table,
+            columns.join(", "),
+            vec!["?"; columns.len()].join(", ")
+        );
+
+        let mut row_count = 0;
+        for (index, line) in lines.enumerate() {
+            let fields = parse_csv_line(line);
+            if fields.len() != columns.len() {
+                return Err(SeedError::Malformed {
+                    path,
+                    line: index + 2,
+                });
+            }
+
+            let mut query = sqlx::query(&sql);
+            for field in fields {
+                query = query.bind(normalize_field(field));
+            }
+            query.execute(&mut tx).await?;
+            row_count += 1;
+        }
+        info!("{} に {} 件のデータを読み込みました", table, row_count);
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// ダブルクォートで囲まれた値を考慮して1行を分割する
+fn parse_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+// 空の値は NULL とし、日時は sqlx が SQLite に書き込む形式に揃えて文字列として比較できるようにする
+fn normalize_field(field: String) -> Option<String> {
+    if field.is_empty() {
+        return None;
+    }
+
+    match DateTime::parse_from_rfc3339(&field) {
+        Ok(datetime) => Some(datetime.naive_utc().format("%F %T%.f").to_string()),
+        Err(_) => Some(field),
+    }
+}
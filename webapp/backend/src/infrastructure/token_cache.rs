@@ -0,0 +1,84 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+
+use crate::models::user::AuthenticatedUser;
+
+// 全ワーカー・全スコープで共有するセッショントークンのキャッシュ。
+// 無効なトークンも None として短時間キャッシュし、DB への問い合わせを抑える
+#[derive(Clone, Debug)]
+pub struct CachedToken {
+    pub authenticated_user: Option<AuthenticatedUser>,
+    pub cached_until: Instant,
+}
+
+#[derive(Debug)]
+pub struct TokenCache {
+    entries: Mutex<LruCache<String, CachedToken>>,
+    ttl: Duration,
+}
+
+impl TokenCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        TokenCache {
+            entries: Mutex::new(LruCache::new(capacity)),
+            ttl,
+        }
+    }
+
+    pub fn get(&self, token: &str) -> Option<CachedToken> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(token) {
+            Some(cached) if cached.cached_until > Instant::now() => Some(cached.clone()),
+            Some(_) => {
+                entries.pop(token);
+                None
+            }
+            None => None,
+        }
+    }
+
+    // expires_until はセッション自体の失効時刻。キャッシュの TTL と早い方まで保持する
+    pub fn put(
+        &self,
+        token: String,
+        authenticated_user: Option<AuthenticatedUser>,
+        expires_until: Option<Instant>,
+    ) {
+        let mut cached_until = Instant::now() + self.ttl;
+        if let Some(expires_until) = expires_until {
+            cached_until = cached_until.min(expires_until);
+        }
+
+        self.entries.lock().unwrap().put(
+            token,
+            CachedToken {
+                authenticated_user,
+                cached_until,
+            },
+        );
+    }
+
+    pub fn evict(&self, token: &str) {
+        self.entries.lock().unwrap().pop(token);
+    }
+
+    pub fn evict_user(&self, user_id: i32) {
+        let mut entries = self.entries.lock().unwrap();
+        let tokens: Vec<String> = entries
+            .iter()
+            .filter(|(_, cached)| {
+                cached
+                    .authenticated_user
+                    .as_ref()
+                    .is_some_and(|user| user.user_id == user_id)
+            })
+            .map(|(token, _)| token.clone())
+            .collect();
+
+        for token in tokens {
+            entries.pop(&token);
+        }
+    }
+}
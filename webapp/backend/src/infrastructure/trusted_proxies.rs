@@ -0,0 +1,93 @@
+use std::net::IpAddr;
+
+// X-Real-IP を信頼するリバースプロキシのアドレス。IP アドレスまたは CIDR 表記（172.16.0.0/12 など）で指定する
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    ranges: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    pub fn from_list(items: &[String]) -> Option<Self> {
+        let ranges = items
+            .iter()
+            .map(|item| parse_range(item))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(TrustedProxies { ranges })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 射影アドレスで接続された場合も IPv4 として比較する
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            IpAddr::V4(_) => ip,
+        };
+
+        self.ranges
+            .iter()
+            .any(|(network, prefix_len)| match (network, ip) {
+                (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                    let mask = u32::MAX.checked_shl(32 - *prefix_len as u32).unwrap_or(0);
+                    u32::from(*network) & mask == u32::from(ip) & mask
+                }
+                (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                    let mask = u128::MAX.checked_shl(128 - *prefix_len as u32).unwrap_or(0);
+                    u128::from(*network) & mask == u128::from(ip) & mask
+                }
+                _ => false,
+            })
+    }
+}
+
+fn parse_range(item: &str) -> Option<(IpAddr, u8)> {
+    let (address, prefix_len) = match item.split_once('/') {
+        Some((address, prefix_len)) => (address, Some(prefix_len.parse::<u8>().ok()?)),
+        None => (item, None),
+    };
+    let address = address.parse::<IpAddr>().ok()?;
+    let max_prefix_len = if address.is_ipv4() { 32 } else { 128 };
+
+    match prefix_len {
+        Some(prefix_len) if prefix_len > max_prefix_len => None,
+        Some(prefix_len) => Some((address, prefix_len)),
+        None => Some((address, max_prefix_len)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxies(items: &[&str]) -> Option<TrustedProxies> {
+        let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+        TrustedProxies::from_list(&items)
+    }
+
+    #[test]
+    fn matches_addresses_and_cidr_ranges() {
+        let proxies = proxies(&["10.0.0.5", "172.16.0.0/12", "fd00::/8"]).unwrap();
+
+        assert!(proxies.contains("10.0.0.5".parse().unwrap()));
+        assert!(!proxies.contains("10.0.0.6".parse().unwrap()));
+        assert!(proxies.contains("172.18.0.3".parse().unwrap()));
+        assert!(!proxies.contains("172.32.0.1".parse().unwrap()));
+        assert!(proxies.contains("::ffff:172.18.0.3".parse().unwrap()));
+        assert!(proxies.contains("fd12::1".parse().unwrap()));
+        assert!(!proxies.contains("2001:db8::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn empty_list_trusts_nothing() {
+        let proxies = proxies(&[]).unwrap();
+
+        assert!(!proxies.contains("127.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn invalid_entries_are_rejected() {
+        for item in ["nginx", "10.0.0.0/33", "::/129", "10.0.0.0/"] {
+            assert!(proxies(&[item]).is_none(), "{}", item);
+        }
+        assert!(proxies(&["0.0.0.0/0"]).is_some());
+    }
+}
@@ -1,22 +1,15 @@
-use std::sync::Arc;
+use std::env;
+use std::time::Duration;
 
-use actix_cors::Cors;
-use actix_web::{web, App, HttpServer};
-use api::{
-    auth_handler, health_check_handler, map_handler, order_handler, result_handler,
-    tow_truck_handler,
-};
-use domains::map_service::MapService;
-use domains::{
-    auth_service::AuthService, order_service::OrderService, tow_truck_service::TowTruckService,
-};
-use middlewares::auth_middleware::AuthMiddleware;
-use repositories::auth_repository::AuthRepositoryImpl;
-use repositories::map_repository::MapRepositoryImpl;
-use repositories::order_repository::OrderRepositoryImpl;
-use repositories::tow_truck_repository::TowTruckRepositoryImpl;
+use actix_web::HttpServer;
+use app::{create_app, AppState};
+use config::{CommandLine, MigrateCommand, Settings};
+use infrastructure::db::DbPool;
+use infrastructure::migrations::{self, MigrationState};
 
 mod api;
+mod app;
+mod config;
 mod domains;
 mod errors;
 mod infrastructure;
@@ -27,132 +20,140 @@ mod utils;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let pool = infrastructure::db::create_pool().await;
-    let mut port = 8080;
+    let command_line = match CommandLine::parse(env::args().skip(1)) {
+        Ok(command_line) => command_line,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    let settings = match Settings::load(command_line.config_path.as_deref()) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("設定の読み込みに失敗しました: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if command_line.print_config {
+        print!("{}", settings.to_redacted_toml());
+        return Ok(());
+    }
+
+    infrastructure::logging::init(&settings.log);
+
+    let pool = infrastructure::db::create_pool(&settings.database).await;
+
+    match (&pool, command_line.migrate) {
+        (DbPool::MySql(pool), Some(MigrateCommand::Up)) => {
+            return match migrations::run(pool).await {
+                Ok(applied) => {
+                    println!("{} 件のマイグレーションを適用しました", applied);
+                    Ok(())
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+        }
+        (DbPool::MySql(pool), Some(MigrateCommand::Status)) => {
+            return match migrations::status(pool).await {
+                Ok(Some(statuses)) => {
+                    let has_modified = statuses
+                        .iter()
+                        .any(|status| status.state == MigrationState::Modified);
+                    for status in statuses {
+                        println!(
+                            "{:>4}  {:<32}  {:<8}  {}",
+                            status.version,
+                            status.name,
+                            status.state.as_str(),
+                            status
+                                .applied_at
+                                .map(|applied_at| applied_at.to_rfc3339())
+                                .unwrap_or_default()
+                        );
+                    }
+                    // migrate up は変更された適用済みのマイグレーションがあると進まないため、異常終了にする
+                    if has_modified {
+                        eprintln!("適用後に内容が変更されたマイグレーションがあります。ファイルを適用時の内容に戻してください");
+                        std::process::exit(1);
+                    }
+                    Ok(())
+                }
+                Ok(None) => {
+                    println!("schema_migrations テーブルがないため、適用履歴を確認できません");
+                    Ok(())
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+        }
+        (DbPool::Sqlite(_), Some(_)) => {
+            eprintln!("SQLite の場合はマイグレーションを使わず、起動時に sqlite/schema.sql からスキーマを作成します");
+            std::process::exit(2);
+        }
+        (_, None) => {}
+    }
 
-    if cfg!(debug_assertions) {
-        port = 18080;
+    match &pool {
+        DbPool::MySql(pool) if settings.database.migrate_on_startup => {
+            if let Err(e) = migrations::run(pool).await {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        DbPool::Sqlite(pool) => {
+            if let Err(e) =
+                infrastructure::sqlite::initialize(pool, settings.database.seed_dir.as_deref())
+                    .await
+            {
+                eprintln!("SQLite の初期化に失敗しました: {}", e);
+                std::process::exit(1);
+            }
+        }
+        _ => {}
     }
 
-    let auth_service = web::Data::new(AuthService::new(AuthRepositoryImpl::new(pool.clone())));
-    let auth_service_for_middleware =
-        Arc::new(AuthService::new(AuthRepositoryImpl::new(pool.clone())));
-    let tow_truck_service = web::Data::new(TowTruckService::new(
-        TowTruckRepositoryImpl::new(pool.clone()),
-        OrderRepositoryImpl::new(pool.clone()),
-        MapRepositoryImpl::new(pool.clone()),
-    ));
-    let order_service = web::Data::new(OrderService::new(
-        OrderRepositoryImpl::new(pool.clone()),
-        TowTruckRepositoryImpl::new(pool.clone()),
-        AuthRepositoryImpl::new(pool.clone()),
-        MapRepositoryImpl::new(pool.clone()),
-    ));
-    let map_service = web::Data::new(MapService::new(MapRepositoryImpl::new(pool.clone())));
+    let state = AppState::new(&pool, &settings);
 
-    HttpServer::new(move || {
-        let mut cors = Cors::default();
+    // 応答期限切れのオファーを定期的に次のレッカー車へ回す
+    let order_service_for_offer_expiry = state.order_service.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(Duration::from_secs(5));
+        loop {
+            interval.tick().await;
+            if let Err(e) = order_service_for_offer_expiry.expire_order_offers().await {
+                log::error!("オファーの期限切れ処理に失敗しました: {:?}", e);
+            }
+        }
+    });
 
-        cors = cors
-            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
-            .allowed_headers(vec![
-                actix_web::http::header::AUTHORIZATION,
-                actix_web::http::header::ACCEPT,
-            ])
-            .allowed_header(actix_web::http::header::CONTENT_TYPE)
-            .supports_credentials()
-            .max_age(3600);
+    // 失効・無効化されたセッションを定期的に削除する
+    let auth_service_for_session_purge = state.auth_service.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(Duration::from_secs(10 * 60));
+        loop {
+            interval.tick().await;
+            match auth_service_for_session_purge
+                .purge_expired_sessions()
+                .await
+            {
+                Ok(purged) if purged > 0 => {
+                    log::info!("失効したセッションを {} 件削除しました", purged)
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("セッションの削除に失敗しました: {:?}", e),
+            }
+        }
+    });
 
-        App::new()
-            .app_data(tow_truck_service.clone())
-            .app_data(auth_service.clone())
-            .app_data(order_service.clone())
-            .app_data(map_service.clone())
-            .wrap(cors)
-            .service(
-                web::scope("/api")
-                    .service(
-                        web::resource("/health_check")
-                            .route(web::get().to(health_check_handler::health_check_handler)),
-                    )
-                    .service(
-                        web::resource("/result")
-                            .route(web::get().to(result_handler::result_handler)),
-                    )
-                    .service(
-                        web::resource("/register")
-                            .route(web::post().to(auth_handler::register_handler)),
-                    )
-                    .service(
-                        web::resource("/login").route(web::post().to(auth_handler::login_handler)),
-                    )
-                    .service(
-                        web::resource("/logout")
-                            .route(web::post().to(auth_handler::logout_handler)),
-                    )
-                    .service(
-                        web::resource("/user_image/{user_id}")
-                            .route(web::get().to(auth_handler::user_profile_image_handler)),
-                    )
-                    .service(
-                        web::scope("/tow_truck")
-                            .wrap(AuthMiddleware::new(auth_service_for_middleware.clone()))
-                            .service(web::resource("/list").route(
-                                web::get().to(tow_truck_handler::get_paginated_tow_trucks_handler),
-                            ))
-                            .service(
-                                web::resource("/location").route(
-                                    web::post().to(tow_truck_handler::update_location_handler),
-                                ),
-                            )
-                            .service(web::resource("/nearest").route(
-                                web::get().to(
-                                    tow_truck_handler::get_nearest_available_tow_trucks_handler,
-                                ),
-                            ))
-                            .service(
-                                web::resource("/{id}")
-                                    .route(web::get().to(tow_truck_handler::get_tow_truck_handler)),
-                            ),
-                    )
-                    .service(
-                        web::scope("/order")
-                            .wrap(AuthMiddleware::new(auth_service_for_middleware.clone()))
-                            .service(
-                                web::resource("/list").route(
-                                    web::get().to(order_handler::get_paginated_orders_handler),
-                                ),
-                            )
-                            .service(
-                                web::resource("/status").route(
-                                    web::post().to(order_handler::update_order_status_handler),
-                                ),
-                            )
-                            .service(
-                                web::resource("/client").route(
-                                    web::post().to(order_handler::create_client_order_handler),
-                                ),
-                            )
-                            .service(web::resource("/dispatcher").route(
-                                web::post().to(order_handler::create_dispatcher_order_handler),
-                            ))
-                            .service(
-                                web::resource("/{id}")
-                                    .route(web::get().to(order_handler::get_order_handler)),
-                            ),
-                    )
-                    .service(
-                        web::scope("/map")
-                            .wrap(AuthMiddleware::new(auth_service_for_middleware.clone()))
-                            .service(
-                                web::resource("/update_edge")
-                                    .route(web::put().to(map_handler::update_edge_handler)),
-                            ),
-                    ),
-            )
-    })
-    .bind(format!("0.0.0.0:{port}"))?
-    .workers(6)
-    .run()
-    .await
+    let cors_settings = settings.cors.clone();
+    HttpServer::new(move || create_app(&state, &cors_settings))
+        .bind(("0.0.0.0", settings.server.port))?
+        .workers(settings.server.workers)
+        .run()
+        .await
 }
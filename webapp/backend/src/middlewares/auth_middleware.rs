@@ -1,28 +1,23 @@
-use std::sync::{Arc, RwLock};
 use actix_web::{
-    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    Error,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    Error, FromRequest, HttpMessage, HttpRequest,
 };
 use futures_util::future::{ready, LocalBoxFuture, Ready};
-use lru::LruCache;
-use std::num::NonZeroUsize;
+use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::{
-    domains::auth_service::AuthService, repositories::auth_repository::AuthRepositoryImpl,
+    domains::auth_service::AuthService, errors::AppError, models::user::AuthenticatedUser,
+    repositories::auth_repository::AuthRepositoryImpl,
 };
 
 pub struct AuthMiddleware {
     auth_service: Arc<AuthService<AuthRepositoryImpl>>,
-    token_cache: Arc<RwLock<LruCache<String, bool>>>,
 }
 
 impl AuthMiddleware {
     pub fn new(auth_service: Arc<AuthService<AuthRepositoryImpl>>) -> Self {
-        let cache_size = NonZeroUsize::new(1024).unwrap().get(); // ここで usize に変換
-        AuthMiddleware {
-            auth_service,
-            token_cache: Arc::new(RwLock::new(LruCache::new(cache_size))),
-        }
+        AuthMiddleware { auth_service }
     }
 }
 
@@ -40,17 +35,15 @@ where
 
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(AuthMiddlewareMiddleware {
-            service,
+            service: Rc::new(service),
             auth_service: self.auth_service.clone(),
-            token_cache: self.token_cache.clone(),
         }))
     }
 }
 
 pub struct AuthMiddlewareMiddleware<S> {
-    service: S,
+    service: Rc<S>,
     auth_service: Arc<AuthService<AuthRepositoryImpl>>,
-    token_cache: Arc<RwLock<LruCache<String, bool>>>,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthMiddlewareMiddleware<S>
@@ -70,34 +63,46 @@ where
             .get("Authorization")
             .and_then(|h| h.to_str().ok())
             .map(|s| s.to_string());
-        let auth_header = Arc::new(auth_header);
+        // 外部システム連携用の API キーはセッショントークンと区別して X-API-Key ヘッダーで受け取る
+        let api_key_header = req
+            .headers()
+            .get("X-API-Key")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.to_string());
 
         let auth_service = self.auth_service.clone();
-        let token_cache = self.token_cache.clone();
-        let fut = self.service.call(req);
+        let service = self.service.clone();
 
         Box::pin(async move {
-            let is_valid_token = match &*auth_header {
-                Some(token) => {
-                    let mut cache = token_cache.write().unwrap();
-                    if let Some(is_valid) = cache.get(token) {
-                        *is_valid
-                    } else {
-                        let is_valid = auth_service.validate_session(token).await.is_ok();
-                        cache.put(token.clone(), is_valid);
-                        is_valid
-                    }
-                }
-                None => false,
+            let authenticated_user = match (&api_key_header, &auth_header) {
+                (Some(api_key), _) => auth_service.authenticate_api_key(api_key).await,
+                (None, Some(token)) => auth_service.authenticate(token).await,
+                (None, None) => None,
             };
 
-            if is_valid_token {
-                fut.await
-            } else {
-                Err(actix_web::error::ErrorUnauthorized(
+            match authenticated_user {
+                Some(authenticated_user) => {
+                    req.extensions_mut().insert(authenticated_user);
+                    service.call(req).await
+                }
+                None => Err(actix_web::error::ErrorUnauthorized(
                     "Invalid or missing token",
-                ))
+                )),
             }
         })
     }
 }
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<AuthenticatedUser>()
+                .cloned()
+                .ok_or(AppError::Unauthorized),
+        )
+    }
+}
@@ -1 +1,4 @@
 pub mod auth_middleware;
+pub mod request_id;
+pub mod request_metrics;
+pub mod role_guard;
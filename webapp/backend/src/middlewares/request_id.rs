@@ -0,0 +1,111 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
+    http::header::{HeaderName, HeaderValue},
+    Error,
+};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use std::time::Instant;
+use tracing::{info, info_span, Instrument};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// 上流（nginx やクライアント）から受け取る X-Request-Id の最大長
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+// App 全体を包み、リクエストごとに X-Request-Id を採番または引き継いでレスポンスに付与する。
+// 処理全体をリクエスト ID を持つスパンで囲み、以降のログ（SQL を含む）を関連付ける
+pub struct RequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestIdMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdMiddleware { service }))
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request_id = req
+            .headers()
+            .get(&REQUEST_ID_HEADER)
+            .and_then(|h| h.to_str().ok())
+            .filter(|id| is_valid_request_id(id))
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let span = info_span!(
+            "request",
+            request_id = %request_id,
+            method = %req.method(),
+            path = %req.path(),
+        );
+        let started_at = Instant::now();
+        let future = self.service.call(req);
+
+        Box::pin(
+            async move {
+                let header_value = HeaderValue::from_str(&request_id).ok();
+                let result: Result<Self::Response, Error> = match future.await {
+                    Ok(mut res) => {
+                        if let Some(value) = header_value {
+                            res.headers_mut().insert(REQUEST_ID_HEADER, value);
+                        }
+                        Ok(res)
+                    }
+                    // 認証・認可のミドルウェアが返したエラーにも、レスポンスを作ってヘッダーを付与する
+                    Err(err) => {
+                        let mut response = err.error_response();
+                        if let Some(value) = header_value {
+                            response.headers_mut().insert(REQUEST_ID_HEADER, value);
+                        }
+                        Err(InternalError::from_response(err, response).into())
+                    }
+                };
+
+                let status = match &result {
+                    Ok(res) => res.status(),
+                    Err(err) => err.as_response_error().status_code(),
+                };
+                info!(
+                    status = status.as_u16(),
+                    elapsed_ms = started_at.elapsed().as_millis() as u64,
+                    "リクエストを処理しました"
+                );
+                result
+            }
+            .instrument(span),
+        )
+    }
+}
+
+// ログやヘッダーに埋め込むため、表示可能な ASCII のみを受け付ける
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH && id.bytes().all(|b| b.is_ascii_graphic())
+}
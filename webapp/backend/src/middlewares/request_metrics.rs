@@ -0,0 +1,76 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error,
+};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use std::time::Instant;
+
+use crate::infrastructure::metrics::metrics;
+
+// App 全体を包み、ルートごとのリクエスト数と処理時間を記録する。
+// ラベルにはパスそのものではなく /api/order/{id} のようなパターンを使い、系列数を抑える
+pub struct RequestMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestMetricsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware { service }))
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let started_at = Instant::now();
+        let method = req.method().to_string();
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_string());
+        let future = self.service.call(req);
+
+        Box::pin(async move {
+            let result = future.await;
+            // 認証・認可のミドルウェアはエラーとして返すため、そのステータスコードで記録する
+            let status = match &result {
+                Ok(res) => res.status(),
+                Err(err) => err.as_response_error().status_code(),
+            };
+
+            let metrics = metrics();
+            metrics
+                .http_requests_total
+                .with_label_values(&[&method, &route, status.as_str()])
+                .inc();
+            metrics
+                .http_request_duration_seconds
+                .with_label_values(&[&method, &route])
+                .observe(started_at.elapsed().as_secs_f64());
+
+            result
+        })
+    }
+}
@@ -0,0 +1,83 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage,
+};
+use futures_util::future::{ready, Either, Ready};
+
+use crate::models::user::AuthenticatedUser;
+
+// AuthMiddleware の内側で使用し、許可されたロール以外のリクエストを 403 で拒否する。
+// API キーはロールを持たず、scope で指定したスコープを持つ場合のみ許可する
+pub struct RoleGuard {
+    roles: &'static [&'static str],
+    scope: Option<&'static str>,
+}
+
+impl RoleGuard {
+    pub fn new(roles: &'static [&'static str]) -> Self {
+        RoleGuard { roles, scope: None }
+    }
+
+    pub fn scope(mut self, scope: &'static str) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RoleGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RoleGuardMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RoleGuardMiddleware {
+            service,
+            roles: self.roles,
+            scope: self.scope,
+        }))
+    }
+}
+
+pub struct RoleGuardMiddleware<S> {
+    service: S,
+    roles: &'static [&'static str],
+    scope: Option<&'static str>,
+}
+
+impl<S, B> Service<ServiceRequest> for RoleGuardMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let is_allowed = match req.extensions().get::<AuthenticatedUser>() {
+            Some(authenticated_user) if authenticated_user.is_api_key() => self
+                .scope
+                .is_some_and(|scope| authenticated_user.has_scope(scope)),
+            Some(authenticated_user) => authenticated_user.has_role(self.roles),
+            None => false,
+        };
+
+        if is_allowed {
+            Either::Left(self.service.call(req))
+        } else {
+            Either::Right(ready(Err(actix_web::error::ErrorForbidden(
+                "Insufficient role",
+            ))))
+        }
+    }
+}
@@ -77,6 +77,48 @@ impl Graph {
 
         i32::MAX // 経路が見つからなかった場合
     }
+
+    pub fn shortest_route(&self, from_node_id: i32, to_node_id: i32) -> Option<(i32, Vec<i32>)> {
+        let mut distances = HashMap::new();
+        let mut previous = HashMap::new();
+        let mut heap = BinaryHeap::new();
+
+        distances.insert(from_node_id, 0);
+        heap.push(State { cost: 0, position: from_node_id });
+
+        while let Some(State { cost, position }) = heap.pop() {
+            if position == to_node_id {
+                let mut route = vec![to_node_id];
+                let mut current = to_node_id;
+                while let Some(&prev) = previous.get(&current) {
+                    route.push(prev);
+                    current = prev;
+                }
+                route.reverse();
+                return Some((cost, route));
+            }
+
+            if cost > *distances.get(&position).unwrap_or(&i32::MAX) {
+                continue;
+            }
+
+            if let Some(edges) = self.edges.get(&position) {
+                for edge in edges {
+                    let next_cost = cost + edge.weight;
+                    if next_cost < *distances.get(&edge.node_b_id).unwrap_or(&i32::MAX) {
+                        distances.insert(edge.node_b_id, next_cost);
+                        previous.insert(edge.node_b_id, position);
+                        heap.push(State {
+                            cost: next_cost,
+                            position: edge.node_b_id,
+                        });
+                    }
+                }
+            }
+        }
+
+        None // 経路が見つからなかった場合
+    }
 }
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -23,3 +23,10 @@ pub struct CompletedOrder {
     pub completed_time: DateTime<Utc>,
     pub car_value: f64,
 }
+
+#[derive(FromRow, Clone, Debug)]
+pub struct OrderOffer {
+    pub id: i32,
+    pub order_id: i32,
+    pub tow_truck_id: i32,
+}
@@ -1,12 +1,18 @@
+use chrono::{DateTime, Utc};
 use sqlx::FromRow;
 
+use crate::errors::AppError;
+
 #[derive(FromRow, Clone, Debug)]
 pub struct User {
     pub id: i32,
     pub username: String,
     pub password: String,
+    // 画像名は find_profile_image_name_by_user_id で別に取得する
+    #[allow(dead_code)]
     pub profile_image: String,
     pub role: String,
+    pub is_disabled: bool,
 }
 
 #[derive(FromRow, Clone, Debug)]
@@ -15,19 +21,119 @@ pub struct Session {
     pub user_id: i32,
     pub session_token: String,
     pub is_valid: bool,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
 }
 
 #[derive(FromRow, Clone, Debug)]
-pub struct Driver {
+pub struct PasswordResetToken {
     pub id: i32,
     pub user_id: i32,
-    pub session_token: String,
-    pub is_valid: bool,
 }
 
+#[derive(FromRow, Clone, Debug)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    pub key_hash: String,
+    pub scopes: String,
+    pub created_by: i32,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    pub fn scope_list(&self) -> Vec<String> {
+        self.scopes
+            .split(',')
+            .filter(|scope| !scope.is_empty())
+            .map(|scope| scope.to_string())
+            .collect()
+    }
+}
+
+// API キーに付与できるスコープ
+pub const API_KEY_SCOPES: [&str; 5] = [
+    "tow_trucks:read",
+    "location:write",
+    "orders:read",
+    "orders:write",
+    "metrics:read",
+];
+
 #[derive(FromRow, Clone, Debug)]
 pub struct Dispatcher {
     pub id: i32,
     pub user_id: i32,
     pub area_id: i32,
 }
+
+#[derive(Clone, Debug)]
+pub struct AuthenticatedUser {
+    pub user_id: i32,
+    pub role: String,
+    pub dispatcher_id: Option<i32>,
+    pub area_id: Option<i32>,
+    // API キーで認証した場合のみ Some。ロールではなくスコープで許可を判定する
+    pub scopes: Option<Vec<String>>,
+}
+
+impl AuthenticatedUser {
+    pub fn is_admin(&self) -> bool {
+        self.role == "admin"
+    }
+
+    pub fn is_api_key(&self) -> bool {
+        self.scopes.is_some()
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match &self.scopes {
+            Some(scopes) => scopes.iter().any(|s| s == scope),
+            None => false,
+        }
+    }
+
+    pub fn has_role(&self, roles: &[&str]) -> bool {
+        roles.contains(&self.role.as_str())
+    }
+
+    // API キーはエリアに縛られず、スコープの範囲内で全エリアを操作できる
+    pub fn can_access_area(&self, area_id: i32) -> bool {
+        self.is_admin()
+            || self.is_api_key()
+            || (self.role == "dispatcher" && self.area_id == Some(area_id))
+    }
+
+    // ディスパッチャーは自エリア以外を指定できない。未指定なら自エリアに絞り込む
+    pub fn restrict_area(&self, area_id: Option<i32>) -> Result<Option<i32>, AppError> {
+        if self.role != "dispatcher" {
+            return Ok(area_id);
+        }
+        match area_id {
+            None => Ok(self.area_id),
+            Some(area_id) if self.area_id == Some(area_id) => Ok(Some(area_id)),
+            Some(_) => Err(AppError::Forbidden),
+        }
+    }
+
+    // リクエストボディで指定された ID ではなくセッションのユーザーを操作主体とする。
+    // 他人の ID を指定できるのは admin のみ
+    pub fn resolve_actor_id(
+        &self,
+        own_id: Option<i32>,
+        requested_id: Option<i32>,
+    ) -> Result<i32, AppError> {
+        if self.is_admin() {
+            return requested_id.or(own_id).ok_or(AppError::BadRequest);
+        }
+
+        let own_id = own_id.ok_or(AppError::Forbidden)?;
+        match requested_id {
+            Some(requested_id) if requested_id != own_id => Err(AppError::Forbidden),
+            _ => Ok(own_id),
+        }
+    }
+}
@@ -1,138 +1,684 @@
+use crate::domains::dto::auth::{ClientInfoDto, UserDto};
 use crate::errors::AppError;
-use crate::models::user::{Dispatcher, User};
+use crate::infrastructure::db::{with_pool, DbPool, InsertedId};
+use crate::models::user::{ApiKey, Dispatcher, PasswordResetToken, User};
 use crate::{domains::auth_service::AuthRepository, models::user::Session};
-use sqlx::mysql::MySqlPool;
+use chrono::{DateTime, Utc};
+use tracing::instrument;
 
 #[derive(Debug)]
 pub struct AuthRepositoryImpl {
-    pool: MySqlPool,
+    pool: DbPool,
 }
 
 impl AuthRepositoryImpl {
-    pub fn new(pool: MySqlPool) -> Self {
+    pub fn new(pool: DbPool) -> Self {
         AuthRepositoryImpl { pool }
     }
 }
 
 impl AuthRepository for AuthRepositoryImpl {
+    #[instrument(level = "debug", skip_all)]
     async fn find_user_by_id(&self, id: i32) -> Result<Option<User>, AppError> {
-        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
-            .bind(id)
-            .fetch_optional(&self.pool)
-            .await?;
+        with_pool!(&self.pool, |pool| {
+            let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
+                .bind(id)
+                .fetch_optional(pool)
+                .await?;
 
-        Ok(user)
+            Ok(user)
+        })
     }
 
+    #[instrument(level = "debug", skip_all)]
     async fn find_user_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
-        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = ?")
-            .bind(username)
-            .fetch_optional(&self.pool)
-            .await?;
+        with_pool!(&self.pool, |pool| {
+            let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = ?")
+                .bind(username)
+                .fetch_optional(pool)
+                .await?;
 
-        Ok(user)
+            Ok(user)
+        })
     }
 
+    #[instrument(level = "debug", skip_all)]
     async fn find_profile_image_name_by_user_id(
         &self,
         user_id: i32,
     ) -> Result<Option<String>, AppError> {
-        let profile_image_name = sqlx::query_scalar("SELECT profile_image FROM users WHERE id = ?")
-            .bind(user_id)
-            .fetch_optional(&self.pool)
-            .await?;
+        with_pool!(&self.pool, |pool| {
+            let profile_image_name =
+                sqlx::query_scalar("SELECT profile_image FROM users WHERE id = ?")
+                    .bind(user_id)
+                    .fetch_optional(pool)
+                    .await?;
 
-        Ok(profile_image_name)
+            Ok(profile_image_name)
+        })
     }
 
-    async fn authenticate_user(&self, username: &str, password: &str) -> Result<User, AppError> {
-        let user =
-            sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = ? AND password = ?")
+    #[instrument(level = "debug", skip_all)]
+    async fn create_user(
+        &self,
+        username: &str,
+        password: &str,
+        role: &str,
+    ) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("INSERT INTO users (username, password, role) VALUES (?, ?, ?)")
                 .bind(username)
                 .bind(password)
-                .fetch_one(&self.pool)
+                .bind(role)
+                .execute(pool)
                 .await?;
 
-        Ok(user)
+            Ok(())
+        })
     }
 
-    async fn create_user(
+    #[instrument(level = "debug", skip_all)]
+    async fn create_session(
         &self,
-        username: &str,
-        password: &str,
-        role: &str,
+        user_id: i32,
+        session_token: &str,
+        client: &ClientInfoDto,
     ) -> Result<(), AppError> {
-        sqlx::query("INSERT INTO users (username, password, role) VALUES (?, ?, ?)")
-            .bind(username)
-            .bind(password)
-            .bind(role)
-            .execute(&self.pool)
+        let now = Utc::now();
+        with_pool!(&self.pool, |pool| {
+            sqlx::query(
+                "INSERT INTO sessions (user_id, session_token, created_at, last_seen_at, user_agent, ip_address) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(user_id)
+            .bind(session_token)
+            .bind(now)
+            .bind(now)
+            .bind(&client.user_agent)
+            .bind(&client.ip_address)
+            .execute(pool)
             .await?;
 
-        Ok(())
+            Ok(())
+        })
     }
 
-    async fn create_session(&self, user_id: i32, session_token: &str) -> Result<(), AppError> {
-        sqlx::query("INSERT INTO sessions (user_id, session_token) VALUES (?, ?)")
-            .bind(user_id)
+    #[instrument(level = "debug", skip_all)]
+    async fn touch_session(
+        &self,
+        session_id: i32,
+        last_seen_at: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("UPDATE sessions SET last_seen_at = ? WHERE id = ?")
+                .bind(last_seen_at)
+                .bind(session_id)
+                .execute(pool)
+                .await?;
+
+            Ok(())
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn rotate_session_token(
+        &self,
+        session_token: &str,
+        new_session_token: &str,
+    ) -> Result<bool, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let result = sqlx::query(
+                "UPDATE sessions SET session_token = ? WHERE session_token = ? AND is_valid = TRUE",
+            )
+            .bind(new_session_token)
             .bind(session_token)
-            .execute(&self.pool)
+            .execute(pool)
             .await?;
 
-        Ok(())
+            Ok(result.rows_affected() > 0)
+        })
     }
 
-    async fn delete_session(&self, session_token: &str) -> Result<(), AppError> {
-        sqlx::query("DELETE FROM sessions WHERE session_token = ?")
-            .bind(session_token)
-            .execute(&self.pool)
+    #[instrument(level = "debug", skip_all)]
+    async fn delete_expired_sessions(
+        &self,
+        created_before: DateTime<Utc>,
+        last_seen_before: DateTime<Utc>,
+    ) -> Result<u64, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let result = sqlx::query(
+                "DELETE FROM sessions WHERE is_valid = FALSE OR created_at < ? OR last_seen_at < ?",
+            )
+            .bind(created_before)
+            .bind(last_seen_before)
+            .execute(pool)
             .await?;
 
-        Ok(())
+            Ok(result.rows_affected())
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn delete_session(&self, session_token: &str) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("DELETE FROM sessions WHERE session_token = ?")
+                .bind(session_token)
+                .execute(pool)
+                .await?;
+
+            Ok(())
+        })
     }
 
+    #[instrument(level = "debug", skip_all)]
     async fn find_session_by_session_token(
         &self,
         session_token: &str,
     ) -> Result<Session, AppError> {
-        let session =
-            sqlx::query_as::<_, Session>("SELECT * FROM sessions WHERE session_token = ?")
-                .bind(session_token)
-                .fetch_one(&self.pool)
-                .await?;
+        with_pool!(&self.pool, |pool| {
+            let session =
+                sqlx::query_as::<_, Session>("SELECT * FROM sessions WHERE session_token = ?")
+                    .bind(session_token)
+                    .fetch_one(pool)
+                    .await?;
 
-        Ok(session)
+            Ok(session)
+        })
     }
 
+    #[instrument(level = "debug", skip_all)]
     async fn find_dispatcher_by_id(&self, id: i32) -> Result<Option<Dispatcher>, AppError> {
-        let dispatcher = sqlx::query_as::<_, Dispatcher>("SELECT * FROM dispatchers WHERE id = ?")
-            .bind(id)
-            .fetch_optional(&self.pool)
-            .await?;
+        with_pool!(&self.pool, |pool| {
+            let dispatcher =
+                sqlx::query_as::<_, Dispatcher>("SELECT * FROM dispatchers WHERE id = ?")
+                    .bind(id)
+                    .fetch_optional(pool)
+                    .await?;
 
-        Ok(dispatcher)
+            Ok(dispatcher)
+        })
     }
 
+    #[instrument(level = "debug", skip_all)]
     async fn find_dispatcher_by_user_id(
         &self,
         user_id: i32,
     ) -> Result<Option<Dispatcher>, AppError> {
-        let dispatcher =
-            sqlx::query_as::<_, Dispatcher>("SELECT * FROM dispatchers WHERE user_id = ?")
+        with_pool!(&self.pool, |pool| {
+            let dispatcher =
+                sqlx::query_as::<_, Dispatcher>("SELECT * FROM dispatchers WHERE user_id = ?")
+                    .bind(user_id)
+                    .fetch_optional(pool)
+                    .await?;
+
+            Ok(dispatcher)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn create_dispatcher(&self, user_id: i32, area_id: i32) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("INSERT INTO dispatchers (user_id, area_id) VALUES (?, ?)")
                 .bind(user_id)
-                .fetch_optional(&self.pool)
+                .bind(area_id)
+                .execute(pool)
                 .await?;
 
-        Ok(dispatcher)
+            Ok(())
+        })
     }
 
-    async fn create_dispatcher(&self, user_id: i32, area_id: i32) -> Result<(), AppError> {
-        sqlx::query("INSERT INTO dispatchers (user_id, area_id) VALUES (?, ?)")
+    #[instrument(level = "debug", skip_all)]
+    async fn find_driver_area_id_by_user_id(&self, user_id: i32) -> Result<Option<i32>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let area_id = sqlx::query_scalar("SELECT area_id FROM tow_trucks WHERE driver_id = ?")
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?;
+
+            Ok(area_id)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn update_user_password(&self, user_id: i32, password: &str) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("UPDATE users SET password = ? WHERE id = ?")
+                .bind(password)
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+
+            Ok(())
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn invalidate_sessions_by_user_id(&self, user_id: i32) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("UPDATE sessions SET is_valid = FALSE WHERE user_id = ?")
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+
+            Ok(())
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn create_password_reset_token(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool| {
+            sqlx::query(
+                "INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) VALUES (?, ?, ?)",
+            )
+            .bind(user_id)
+            .bind(token_hash)
+            .bind(expires_at)
+            .execute(pool)
+            .await?;
+
+            Ok(())
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn find_valid_password_reset_token(
+        &self,
+        token_hash: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<PasswordResetToken>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let token = sqlx::query_as::<_, PasswordResetToken>(
+                "SELECT
+                    id, user_id
+                FROM
+                    password_reset_tokens
+                WHERE
+                    token_hash = ?
+                AND
+                    used_at IS NULL
+                AND
+                    expires_at > ?",
+            )
+            .bind(token_hash)
+            .bind(now)
+            .fetch_optional(pool)
+            .await?;
+
+            Ok(token)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn mark_password_reset_token_used(
+        &self,
+        id: i32,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let result = sqlx::query(
+                "UPDATE password_reset_tokens SET used_at = ? WHERE id = ? AND used_at IS NULL",
+            )
+            .bind(used_at)
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+            Ok(result.rows_affected() > 0)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn create_api_key(
+        &self,
+        name: &str,
+        key_hash: &str,
+        scopes: &str,
+        created_by: i32,
+    ) -> Result<i32, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let result = sqlx::query(
+                "INSERT INTO api_keys (name, key_hash, scopes, created_by) VALUES (?, ?, ?, ?)",
+            )
+            .bind(name)
+            .bind(key_hash)
+            .bind(scopes)
+            .bind(created_by)
+            .execute(pool)
+            .await?;
+
+            Ok(result.inserted_id() as i32)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn find_api_key_by_id(&self, id: i32) -> Result<Option<ApiKey>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let api_key = sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE id = ?")
+                .bind(id)
+                .fetch_optional(pool)
+                .await?;
+
+            Ok(api_key)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn find_active_api_key_by_hash(
+        &self,
+        key_hash: &str,
+    ) -> Result<Option<ApiKey>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let api_key = sqlx::query_as::<_, ApiKey>(
+                "SELECT * FROM api_keys WHERE key_hash = ? AND revoked_at IS NULL",
+            )
+            .bind(key_hash)
+            .fetch_optional(pool)
+            .await?;
+
+            Ok(api_key)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let api_keys = sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys ORDER BY id ASC")
+                .fetch_all(pool)
+                .await?;
+
+            Ok(api_keys)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn revoke_api_key(&self, id: i32, revoked_at: DateTime<Utc>) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("UPDATE api_keys SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL")
+                .bind(revoked_at)
+                .bind(id)
+                .execute(pool)
+                .await?;
+
+            Ok(())
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn get_paginated_users(
+        &self,
+        page: i32,
+        page_size: i32,
+        role: Option<String>,
+    ) -> Result<Vec<UserDto>, AppError> {
+        let where_clause = match role {
+            Some(_) => "WHERE u.role = ?",
+            None => "",
+        };
+        let query = format!(
+            "SELECT
+                u.id,
+                u.username,
+                u.role,
+                u.is_disabled,
+                d.id AS dispatcher_id,
+                tt.id AS tow_truck_id,
+                COALESCE(d.area_id, tt.area_id) AS area_id
+            FROM
+                users u
+            LEFT JOIN
+                dispatchers d
+            ON
+                d.user_id = u.id
+            LEFT JOIN
+                tow_trucks tt
+            ON
+                tt.driver_id = u.id
+            {}
+            ORDER BY
+                u.id ASC
+            LIMIT ?
+            OFFSET ?",
+            where_clause
+        );
+
+        with_pool!(&self.pool, |pool| {
+            let mut query = sqlx::query_as::<_, UserDto>(&query);
+            if let Some(role) = role {
+                query = query.bind(role);
+            }
+            let users = query
+                .bind(page_size)
+                .bind(page * page_size)
+                .fetch_all(pool)
+                .await?;
+
+            Ok(users)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn update_user_disabled(&self, user_id: i32, is_disabled: bool) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("UPDATE users SET is_disabled = ? WHERE id = ?")
+                .bind(is_disabled)
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+
+            Ok(())
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn find_node_area_id(&self, node_id: i32) -> Result<Option<i32>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let area_id = sqlx::query_scalar("SELECT area_id FROM nodes WHERE id = ?")
+                .bind(node_id)
+                .fetch_optional(pool)
+                .await?;
+
+            Ok(area_id)
+        })
+    }
+
+    // orders などは dispatchers・tow_trucks を ON DELETE CASCADE で参照しているため、
+    // 依頼の履歴がある行は削除せず Conflict を返す
+    #[instrument(level = "debug", skip_all)]
+    async fn change_user_role(
+        &self,
+        user_id: i32,
+        current_role: &str,
+        new_role: &str,
+        area_id: Option<i32>,
+        node_id: Option<i32>,
+    ) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool, dialect| {
+            let mut tx = pool.begin().await?;
+
+            match current_role {
+                "dispatcher" => {
+                    let order_count: i64 = sqlx::query_scalar(
+                        "SELECT
+                            COUNT(*)
+                        FROM
+                            orders o
+                        JOIN
+                            dispatchers d
+                        ON
+                            o.dispatcher_id = d.id
+                        WHERE
+                            d.user_id = ?",
+                    )
+                    .bind(user_id)
+                    .fetch_one(&mut tx)
+                    .await?;
+                    if order_count > 0 {
+                        return Err(AppError::Conflict);
+                    }
+
+                    sqlx::query("DELETE FROM dispatchers WHERE user_id = ?")
+                        .bind(user_id)
+                        .execute(&mut tx)
+                        .await?;
+                }
+                "driver" => {
+                    let order_count: i64 = sqlx::query_scalar(
+                        "SELECT
+                            (SELECT COUNT(*) FROM orders o WHERE o.tow_truck_id = tt.id)
+                            + (SELECT COUNT(*) FROM completed_orders co WHERE co.tow_truck_id = tt.id)
+                            + (SELECT COUNT(*) FROM order_offers oo WHERE oo.tow_truck_id = tt.id)
+                        FROM
+                            tow_trucks tt
+                        WHERE
+                            tt.driver_id = ?",
+                    )
+                    .bind(user_id)
+                    .fetch_optional(&mut tx)
+                    .await?
+                    .unwrap_or(0);
+                    if order_count > 0 {
+                        return Err(AppError::Conflict);
+                    }
+
+                    sqlx::query(dialect.delete_locations_by_driver_id)
+                        .bind(user_id)
+                        .execute(&mut tx)
+                        .await?;
+                    sqlx::query("DELETE FROM tow_trucks WHERE driver_id = ?")
+                        .bind(user_id)
+                        .execute(&mut tx)
+                        .await?;
+                }
+                _ => {}
+            }
+
+            sqlx::query("UPDATE users SET role = ? WHERE id = ?")
+                .bind(new_role)
+                .bind(user_id)
+                .execute(&mut tx)
+                .await?;
+
+            match (new_role, area_id, node_id) {
+                ("dispatcher", Some(area_id), _) => {
+                    sqlx::query("INSERT INTO dispatchers (user_id, area_id) VALUES (?, ?)")
+                        .bind(user_id)
+                        .bind(area_id)
+                        .execute(&mut tx)
+                        .await?;
+                }
+                ("driver", Some(area_id), Some(node_id)) => {
+                    let result =
+                        sqlx::query("INSERT INTO tow_trucks (driver_id, area_id) VALUES (?, ?)")
+                            .bind(user_id)
+                            .bind(area_id)
+                            .execute(&mut tx)
+                            .await?;
+                    sqlx::query("INSERT INTO locations (tow_truck_id, node_id) VALUES (?, ?)")
+                        .bind(result.inserted_id() as i32)
+                        .bind(node_id)
+                        .execute(&mut tx)
+                        .await?;
+                }
+                ("dispatcher", _, _) | ("driver", _, _) => return Err(AppError::BadRequest),
+                _ => {}
+            }
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn update_dispatcher_area(&self, user_id: i32, area_id: i32) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("UPDATE dispatchers SET area_id = ? WHERE user_id = ?")
+                .bind(area_id)
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+
+            Ok(())
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn update_profile_image_name(
+        &self,
+        user_id: i32,
+        profile_image_name: &str,
+    ) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("UPDATE users SET profile_image = ? WHERE id = ?")
+                .bind(profile_image_name)
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+
+            Ok(())
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn find_session_by_id(&self, id: i32) -> Result<Option<Session>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let session = sqlx::query_as::<_, Session>("SELECT * FROM sessions WHERE id = ?")
+                .bind(id)
+                .fetch_optional(pool)
+                .await?;
+
+            Ok(session)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn get_valid_sessions_by_user_id(
+        &self,
+        user_id: i32,
+        created_after: DateTime<Utc>,
+        last_seen_after: DateTime<Utc>,
+    ) -> Result<Vec<Session>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let sessions = sqlx::query_as::<_, Session>(
+                "SELECT
+                    *
+                FROM
+                    sessions
+                WHERE
+                    user_id = ?
+                AND
+                    is_valid = TRUE
+                AND
+                    created_at >= ?
+                AND
+                    last_seen_at >= ?
+                ORDER BY
+                    last_seen_at DESC",
+            )
             .bind(user_id)
-            .bind(area_id)
-            .execute(&self.pool)
+            .bind(created_after)
+            .bind(last_seen_after)
+            .fetch_all(pool)
             .await?;
 
-        Ok(())
+            Ok(sessions)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn invalidate_session(&self, id: i32) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("UPDATE sessions SET is_valid = FALSE WHERE id = ?")
+                .bind(id)
+                .execute(pool)
+                .await?;
+
+            Ok(())
+        })
     }
 }
@@ -0,0 +1,58 @@
+use crate::{
+    domains::health_service::{HealthRepository, MigrationSummary, PoolStatus},
+    errors::AppError,
+    infrastructure::{
+        db::{with_pool, DbPool},
+        migrations::{self, MigrationState},
+    },
+};
+use tracing::instrument;
+
+#[derive(Debug)]
+pub struct HealthRepositoryImpl {
+    pool: DbPool,
+}
+
+impl HealthRepositoryImpl {
+    pub fn new(pool: DbPool) -> Self {
+        HealthRepositoryImpl { pool }
+    }
+}
+
+impl HealthRepository for HealthRepositoryImpl {
+    #[instrument(level = "debug", skip_all)]
+    async fn ping(&self) -> Result<(), AppError> {
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("SELECT 1").execute(pool).await?;
+        });
+
+        Ok(())
+    }
+
+    fn pool_status(&self) -> PoolStatus {
+        with_pool!(&self.pool, |pool| PoolStatus {
+            size: pool.size(),
+            idle: pool.num_idle(),
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn get_migration_summary(&self) -> Result<Option<MigrationSummary>, AppError> {
+        match &self.pool {
+            DbPool::MySql(pool) => Ok(migrations::status(pool).await?.map(|statuses| {
+                let mut summary = MigrationSummary::default();
+                for status in statuses {
+                    let name = format!("{}_{}", status.version, status.name);
+                    match status.state {
+                        MigrationState::Pending => summary.pending.push(name),
+                        MigrationState::Modified => summary.modified.push(name),
+                        MigrationState::Applied | MigrationState::Unknown => {}
+                    }
+                }
+                summary
+            })),
+            // SQLite は起動時に sqlite/schema.sql から最新のスキーマを作成するため、未適用のものはない
+            DbPool::Sqlite(_) => Ok(Some(MigrationSummary::default())),
+        }
+    }
+}
@@ -1,22 +1,23 @@
-use sqlx::MySqlPool;
-
 use crate::{
     domains::map_service::MapRepository,
+    infrastructure::db::{with_pool, DbPool},
     models::graph::{Edge, Node},
 };
+use tracing::instrument;
 
 #[derive(Debug)]
 pub struct MapRepositoryImpl {
-    pool: MySqlPool,
+    pool: DbPool,
 }
 
 impl MapRepositoryImpl {
-    pub fn new(pool: MySqlPool) -> Self {
+    pub fn new(pool: DbPool) -> Self {
         MapRepositoryImpl { pool }
     }
 }
 
 impl MapRepository for MapRepositoryImpl {
+    #[instrument(level = "debug", skip_all)]
     async fn get_all_nodes(&self, area_id: Option<i32>) -> Result<Vec<Node>, sqlx::Error> {
         let where_clause = match area_id {
             Some(_) => "WHERE area_id = ?",
@@ -34,23 +35,22 @@ impl MapRepository for MapRepositoryImpl {
             where_clause
         );
 
-        let nodes = match area_id {
-            Some(area_id) => {
-                sqlx::query_as::<_, Node>(&sql)
-                    .bind(area_id)
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-            None => {
-                sqlx::query_as::<_, Node>(&sql)
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-        };
+        with_pool!(&self.pool, |pool| {
+            let nodes = match area_id {
+                Some(area_id) => {
+                    sqlx::query_as::<_, Node>(&sql)
+                        .bind(area_id)
+                        .fetch_all(pool)
+                        .await?
+                }
+                None => sqlx::query_as::<_, Node>(&sql).fetch_all(pool).await?,
+            };
 
-        Ok(nodes)
+            Ok(nodes)
+        })
     }
 
+    #[instrument(level = "debug", skip_all)]
     async fn get_all_edges(&self, area_id: Option<i32>) -> Result<Vec<Edge>, sqlx::Error> {
         let where_clause = match area_id {
             Some(_) => "JOIN nodes n ON e.node_a_id = n.id WHERE n.area_id = ?",
@@ -68,47 +68,51 @@ impl MapRepository for MapRepositoryImpl {
             where_clause
         );
 
-        let edges = match area_id {
-            Some(area_id) => {
-                sqlx::query_as::<_, Edge>(&sql)
-                    .bind(area_id)
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-            None => {
-                sqlx::query_as::<_, Edge>(&sql)
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-        };
+        with_pool!(&self.pool, |pool| {
+            let edges = match area_id {
+                Some(area_id) => {
+                    sqlx::query_as::<_, Edge>(&sql)
+                        .bind(area_id)
+                        .fetch_all(pool)
+                        .await?
+                }
+                None => sqlx::query_as::<_, Edge>(&sql).fetch_all(pool).await?,
+            };
 
-        Ok(edges)
+            Ok(edges)
+        })
     }
 
+    #[instrument(level = "debug", skip_all)]
     async fn get_area_id_by_node_id(&self, node_id: i32) -> Result<i32, sqlx::Error> {
-        let area_id = sqlx::query_scalar("SELECT area_id FROM nodes WHERE id = ?")
-            .bind(node_id)
-            .fetch_one(&self.pool)
-            .await?;
+        with_pool!(&self.pool, |pool| {
+            let area_id = sqlx::query_scalar("SELECT area_id FROM nodes WHERE id = ?")
+                .bind(node_id)
+                .fetch_one(pool)
+                .await?;
 
-        Ok(area_id)
+            Ok(area_id)
+        })
     }
 
+    #[instrument(level = "debug", skip_all)]
     async fn update_edge(
         &self,
         node_a_id: i32,
         node_b_id: i32,
         weight: i32,
     ) -> Result<(), sqlx::Error> {
-        sqlx::query("UPDATE edges SET weight = ? WHERE (node_a_id = ? AND node_b_id = ?) OR (node_a_id = ? AND node_b_id = ?)")
-            .bind(weight)
-            .bind(node_a_id)
-            .bind(node_b_id)
-            .bind(node_b_id)
-            .bind(node_a_id)
-            .execute(&self.pool)
-            .await?;
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("UPDATE edges SET weight = ? WHERE (node_a_id = ? AND node_b_id = ?) OR (node_a_id = ? AND node_b_id = ?)")
+                .bind(weight)
+                .bind(node_a_id)
+                .bind(node_b_id)
+                .bind(node_b_id)
+                .bind(node_a_id)
+                .execute(pool)
+                .await?;
 
-        Ok(())
+            Ok(())
+        })
     }
 }
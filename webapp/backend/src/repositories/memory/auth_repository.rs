@@ -0,0 +1,613 @@
+use crate::domains::dto::auth::{ClientInfoDto, UserDto};
+use crate::errors::AppError;
+use crate::models::user::{ApiKey, Dispatcher, PasswordResetToken, User};
+use crate::{domains::auth_service::AuthRepository, models::user::Session};
+use chrono::{DateTime, Utc};
+
+use super::{next_id, paginate, MemoryStore, PasswordResetTokenRow, TowTruckRow};
+
+#[derive(Debug)]
+pub struct MemoryAuthRepository {
+    store: MemoryStore,
+}
+
+impl MemoryAuthRepository {
+    pub fn new(store: MemoryStore) -> Self {
+        MemoryAuthRepository { store }
+    }
+}
+
+impl AuthRepository for MemoryAuthRepository {
+    async fn find_user_by_id(&self, id: i32) -> Result<Option<User>, AppError> {
+        Ok(self.store.lock().find_user(id).cloned())
+    }
+
+    async fn find_user_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
+        let user = self
+            .store
+            .lock()
+            .users
+            .iter()
+            .find(|user| user.username == username)
+            .cloned();
+
+        Ok(user)
+    }
+
+    async fn find_profile_image_name_by_user_id(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<String>, AppError> {
+        Ok(self
+            .store
+            .lock()
+            .find_user(user_id)
+            .map(|user| user.profile_image.clone()))
+    }
+
+    async fn create_user(
+        &self,
+        username: &str,
+        password: &str,
+        role: &str,
+    ) -> Result<(), AppError> {
+        let mut tables = self.store.lock();
+        // username の UNIQUE 制約の代わり
+        if tables.users.iter().any(|user| user.username == username) {
+            return Err(AppError::Conflict);
+        }
+
+        let id = next_id(tables.users.iter().map(|user| user.id));
+        tables.users.push(User {
+            id,
+            username: username.to_string(),
+            password: password.to_string(),
+            profile_image: "default.png".to_string(),
+            role: role.to_string(),
+            is_disabled: false,
+        });
+
+        Ok(())
+    }
+
+    async fn create_session(
+        &self,
+        user_id: i32,
+        session_token: &str,
+        client: &ClientInfoDto,
+    ) -> Result<(), AppError> {
+        let mut tables = self.store.lock();
+        let id = next_id(tables.sessions.iter().map(|session| session.id));
+        let now = Utc::now();
+        tables.sessions.push(Session {
+            id,
+            user_id,
+            session_token: session_token.to_string(),
+            is_valid: true,
+            created_at: now,
+            last_seen_at: now,
+            user_agent: client.user_agent.clone(),
+            ip_address: client.ip_address.clone(),
+        });
+
+        Ok(())
+    }
+
+    async fn touch_session(
+        &self,
+        session_id: i32,
+        last_seen_at: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        if let Some(session) = self
+            .store
+            .lock()
+            .sessions
+            .iter_mut()
+            .find(|session| session.id == session_id)
+        {
+            session.last_seen_at = last_seen_at;
+        }
+
+        Ok(())
+    }
+
+    async fn rotate_session_token(
+        &self,
+        session_token: &str,
+        new_session_token: &str,
+    ) -> Result<bool, AppError> {
+        match self
+            .store
+            .lock()
+            .sessions
+            .iter_mut()
+            .find(|session| session.session_token == session_token && session.is_valid)
+        {
+            Some(session) => {
+                session.session_token = new_session_token.to_string();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn delete_expired_sessions(
+        &self,
+        created_before: DateTime<Utc>,
+        last_seen_before: DateTime<Utc>,
+    ) -> Result<u64, AppError> {
+        let mut tables = self.store.lock();
+        let count = tables.sessions.len();
+        tables.sessions.retain(|session| {
+            session.is_valid
+                && session.created_at >= created_before
+                && session.last_seen_at >= last_seen_before
+        });
+
+        Ok((count - tables.sessions.len()) as u64)
+    }
+
+    async fn delete_session(&self, session_token: &str) -> Result<(), AppError> {
+        self.store
+            .lock()
+            .sessions
+            .retain(|session| session.session_token != session_token);
+
+        Ok(())
+    }
+
+    async fn find_session_by_session_token(
+        &self,
+        session_token: &str,
+    ) -> Result<Session, AppError> {
+        let session = self
+            .store
+            .lock()
+            .sessions
+            .iter()
+            .find(|session| session.session_token == session_token)
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)?;
+
+        Ok(session)
+    }
+
+    async fn find_dispatcher_by_id(&self, id: i32) -> Result<Option<Dispatcher>, AppError> {
+        let dispatcher = self
+            .store
+            .lock()
+            .dispatchers
+            .iter()
+            .find(|dispatcher| dispatcher.id == id)
+            .cloned();
+
+        Ok(dispatcher)
+    }
+
+    async fn find_dispatcher_by_user_id(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<Dispatcher>, AppError> {
+        let dispatcher = self
+            .store
+            .lock()
+            .dispatchers
+            .iter()
+            .find(|dispatcher| dispatcher.user_id == user_id)
+            .cloned();
+
+        Ok(dispatcher)
+    }
+
+    async fn create_dispatcher(&self, user_id: i32, area_id: i32) -> Result<(), AppError> {
+        self.store.insert_dispatcher(user_id, area_id);
+
+        Ok(())
+    }
+
+    async fn find_driver_area_id_by_user_id(&self, user_id: i32) -> Result<Option<i32>, AppError> {
+        let area_id = self
+            .store
+            .lock()
+            .tow_trucks
+            .iter()
+            .find(|row| row.driver_id == user_id)
+            .map(|row| row.area_id);
+
+        Ok(area_id)
+    }
+
+    async fn update_user_password(&self, user_id: i32, password: &str) -> Result<(), AppError> {
+        if let Some(user) = self
+            .store
+            .lock()
+            .users
+            .iter_mut()
+            .find(|user| user.id == user_id)
+        {
+            user.password = password.to_string();
+        }
+
+        Ok(())
+    }
+
+    async fn invalidate_sessions_by_user_id(&self, user_id: i32) -> Result<(), AppError> {
+        for session in self
+            .store
+            .lock()
+            .sessions
+            .iter_mut()
+            .filter(|session| session.user_id == user_id)
+        {
+            session.is_valid = false;
+        }
+
+        Ok(())
+    }
+
+    async fn create_password_reset_token(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        let mut tables = self.store.lock();
+        let id = next_id(tables.password_reset_tokens.iter().map(|token| token.id));
+        tables.password_reset_tokens.push(PasswordResetTokenRow {
+            id,
+            user_id,
+            token_hash: token_hash.to_string(),
+            expires_at,
+            used_at: None,
+        });
+
+        Ok(())
+    }
+
+    async fn find_valid_password_reset_token(
+        &self,
+        token_hash: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<PasswordResetToken>, AppError> {
+        let token = self
+            .store
+            .lock()
+            .password_reset_tokens
+            .iter()
+            .find(|token| {
+                token.token_hash == token_hash && token.used_at.is_none() && token.expires_at > now
+            })
+            .map(|token| PasswordResetToken {
+                id: token.id,
+                user_id: token.user_id,
+            });
+
+        Ok(token)
+    }
+
+    async fn mark_password_reset_token_used(
+        &self,
+        id: i32,
+        used_at: DateTime<Utc>,
+    ) -> Result<bool, AppError> {
+        match self
+            .store
+            .lock()
+            .password_reset_tokens
+            .iter_mut()
+            .find(|token| token.id == id && token.used_at.is_none())
+        {
+            Some(token) => {
+                token.used_at = Some(used_at);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn create_api_key(
+        &self,
+        name: &str,
+        key_hash: &str,
+        scopes: &str,
+        created_by: i32,
+    ) -> Result<i32, AppError> {
+        let mut tables = self.store.lock();
+        let id = next_id(tables.api_keys.iter().map(|api_key| api_key.id));
+        tables.api_keys.push(ApiKey {
+            id,
+            name: name.to_string(),
+            key_hash: key_hash.to_string(),
+            scopes: scopes.to_string(),
+            created_by,
+            created_at: Utc::now(),
+            revoked_at: None,
+        });
+
+        Ok(id)
+    }
+
+    async fn find_api_key_by_id(&self, id: i32) -> Result<Option<ApiKey>, AppError> {
+        let api_key = self
+            .store
+            .lock()
+            .api_keys
+            .iter()
+            .find(|api_key| api_key.id == id)
+            .cloned();
+
+        Ok(api_key)
+    }
+
+    async fn find_active_api_key_by_hash(
+        &self,
+        key_hash: &str,
+    ) -> Result<Option<ApiKey>, AppError> {
+        let api_key = self
+            .store
+            .lock()
+            .api_keys
+            .iter()
+            .find(|api_key| api_key.key_hash == key_hash && api_key.revoked_at.is_none())
+            .cloned();
+
+        Ok(api_key)
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, AppError> {
+        let mut api_keys = self.store.lock().api_keys.clone();
+        api_keys.sort_by_key(|api_key| api_key.id);
+
+        Ok(api_keys)
+    }
+
+    async fn revoke_api_key(&self, id: i32, revoked_at: DateTime<Utc>) -> Result<(), AppError> {
+        if let Some(api_key) = self
+            .store
+            .lock()
+            .api_keys
+            .iter_mut()
+            .find(|api_key| api_key.id == id && api_key.revoked_at.is_none())
+        {
+            api_key.revoked_at = Some(revoked_at);
+        }
+
+        Ok(())
+    }
+
+    async fn get_paginated_users(
+        &self,
+        page: i32,
+        page_size: i32,
+        role: Option<String>,
+    ) -> Result<Vec<UserDto>, AppError> {
+        let tables = self.store.lock();
+        let mut users: Vec<UserDto> = tables
+            .users
+            .iter()
+            .filter(|user| role.as_deref().is_none_or(|role| user.role == role))
+            .map(|user| {
+                let dispatcher = tables
+                    .dispatchers
+                    .iter()
+                    .find(|dispatcher| dispatcher.user_id == user.id);
+                let tow_truck = tables
+                    .tow_trucks
+                    .iter()
+                    .find(|row| row.driver_id == user.id);
+                UserDto {
+                    id: user.id,
+                    username: user.username.clone(),
+                    role: user.role.clone(),
+                    is_disabled: user.is_disabled,
+                    dispatcher_id: dispatcher.map(|dispatcher| dispatcher.id),
+                    tow_truck_id: tow_truck.map(|tow_truck| tow_truck.id),
+                    area_id: dispatcher
+                        .map(|dispatcher| dispatcher.area_id)
+                        .or(tow_truck.map(|tow_truck| tow_truck.area_id)),
+                }
+            })
+            .collect();
+        users.sort_by_key(|user| user.id);
+
+        Ok(paginate(users, page, page_size))
+    }
+
+    async fn update_user_disabled(&self, user_id: i32, is_disabled: bool) -> Result<(), AppError> {
+        if let Some(user) = self
+            .store
+            .lock()
+            .users
+            .iter_mut()
+            .find(|user| user.id == user_id)
+        {
+            user.is_disabled = is_disabled;
+        }
+
+        Ok(())
+    }
+
+    async fn find_node_area_id(&self, node_id: i32) -> Result<Option<i32>, AppError> {
+        Ok(self.store.lock().node_area_id(node_id))
+    }
+
+    // MySQL 実装と同じく、依頼の履歴がある場合は Conflict を返し、何も変更しない
+    async fn change_user_role(
+        &self,
+        user_id: i32,
+        current_role: &str,
+        new_role: &str,
+        area_id: Option<i32>,
+        node_id: Option<i32>,
+    ) -> Result<(), AppError> {
+        let mut tables = self.store.lock();
+
+        match (new_role, area_id, node_id) {
+            ("dispatcher", None, _) | ("driver", None, _) | ("driver", _, None) => {
+                return Err(AppError::BadRequest)
+            }
+            _ => {}
+        }
+
+        match current_role {
+            "dispatcher" => {
+                let dispatcher_ids: Vec<i32> = tables
+                    .dispatchers
+                    .iter()
+                    .filter(|dispatcher| dispatcher.user_id == user_id)
+                    .map(|dispatcher| dispatcher.id)
+                    .collect();
+                if tables.orders.iter().any(|order| {
+                    order
+                        .dispatcher_id
+                        .is_some_and(|id| dispatcher_ids.contains(&id))
+                }) {
+                    return Err(AppError::Conflict);
+                }
+
+                tables
+                    .dispatchers
+                    .retain(|dispatcher| dispatcher.user_id != user_id);
+            }
+            "driver" => {
+                let tow_truck_ids: Vec<i32> = tables
+                    .tow_trucks
+                    .iter()
+                    .filter(|row| row.driver_id == user_id)
+                    .map(|row| row.id)
+                    .collect();
+                let has_history = tables.orders.iter().any(|order| {
+                    order
+                        .tow_truck_id
+                        .is_some_and(|id| tow_truck_ids.contains(&id))
+                }) || tables
+                    .completed_orders
+                    .iter()
+                    .any(|order| tow_truck_ids.contains(&order.tow_truck_id))
+                    || tables
+                        .order_offers
+                        .iter()
+                        .any(|offer| tow_truck_ids.contains(&offer.tow_truck_id));
+                if has_history {
+                    return Err(AppError::Conflict);
+                }
+
+                tables
+                    .locations
+                    .retain(|(tow_truck_id, _)| !tow_truck_ids.contains(tow_truck_id));
+                tables.tow_trucks.retain(|row| row.driver_id != user_id);
+            }
+            _ => {}
+        }
+
+        if let Some(user) = tables.users.iter_mut().find(|user| user.id == user_id) {
+            user.role = new_role.to_string();
+        }
+
+        match (new_role, area_id, node_id) {
+            ("dispatcher", Some(area_id), _) => {
+                let id = next_id(tables.dispatchers.iter().map(|dispatcher| dispatcher.id));
+                tables.dispatchers.push(Dispatcher {
+                    id,
+                    user_id,
+                    area_id,
+                });
+            }
+            ("driver", Some(area_id), Some(node_id)) => {
+                let id = next_id(tables.tow_trucks.iter().map(|row| row.id));
+                tables.tow_trucks.push(TowTruckRow {
+                    id,
+                    driver_id: user_id,
+                    status: "available".to_string(),
+                    area_id,
+                });
+                tables.locations.push((id, node_id));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    async fn update_dispatcher_area(&self, user_id: i32, area_id: i32) -> Result<(), AppError> {
+        for dispatcher in self
+            .store
+            .lock()
+            .dispatchers
+            .iter_mut()
+            .filter(|dispatcher| dispatcher.user_id == user_id)
+        {
+            dispatcher.area_id = area_id;
+        }
+
+        Ok(())
+    }
+
+    async fn update_profile_image_name(
+        &self,
+        user_id: i32,
+        profile_image_name: &str,
+    ) -> Result<(), AppError> {
+        if let Some(user) = self
+            .store
+            .lock()
+            .users
+            .iter_mut()
+            .find(|user| user.id == user_id)
+        {
+            user.profile_image = profile_image_name.to_string();
+        }
+
+        Ok(())
+    }
+
+    async fn find_session_by_id(&self, id: i32) -> Result<Option<Session>, AppError> {
+        let session = self
+            .store
+            .lock()
+            .sessions
+            .iter()
+            .find(|session| session.id == id)
+            .cloned();
+
+        Ok(session)
+    }
+
+    async fn get_valid_sessions_by_user_id(
+        &self,
+        user_id: i32,
+        created_after: DateTime<Utc>,
+        last_seen_after: DateTime<Utc>,
+    ) -> Result<Vec<Session>, AppError> {
+        let mut sessions: Vec<Session> = self
+            .store
+            .lock()
+            .sessions
+            .iter()
+            .filter(|session| {
+                session.user_id == user_id
+                    && session.is_valid
+                    && session.created_at >= created_after
+                    && session.last_seen_at >= last_seen_after
+            })
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen_at));
+
+        Ok(sessions)
+    }
+
+    async fn invalidate_session(&self, id: i32) -> Result<(), AppError> {
+        if let Some(session) = self
+            .store
+            .lock()
+            .sessions
+            .iter_mut()
+            .find(|session| session.id == id)
+        {
+            session.is_valid = false;
+        }
+
+        Ok(())
+    }
+}
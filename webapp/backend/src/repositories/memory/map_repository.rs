@@ -0,0 +1,68 @@
+use crate::{
+    domains::map_service::MapRepository,
+    models::graph::{Edge, Node},
+};
+
+use super::MemoryStore;
+
+#[derive(Debug)]
+pub struct MemoryMapRepository {
+    store: MemoryStore,
+}
+
+impl MemoryMapRepository {
+    pub fn new(store: MemoryStore) -> Self {
+        MemoryMapRepository { store }
+    }
+}
+
+impl MapRepository for MemoryMapRepository {
+    async fn get_all_nodes(&self, area_id: Option<i32>) -> Result<Vec<Node>, sqlx::Error> {
+        let tables = self.store.lock();
+        let mut nodes: Vec<Node> = tables
+            .nodes
+            .iter()
+            .filter(|(_, node_area_id)| area_id.is_none() || area_id == Some(*node_area_id))
+            .map(|(node, _)| node.clone())
+            .collect();
+        nodes.sort_by_key(|node| node.id);
+
+        Ok(nodes)
+    }
+
+    async fn get_all_edges(&self, area_id: Option<i32>) -> Result<Vec<Edge>, sqlx::Error> {
+        let tables = self.store.lock();
+        let edges = tables
+            .edges
+            .iter()
+            .filter(|edge| area_id.is_none() || tables.node_area_id(edge.node_a_id) == area_id)
+            .cloned()
+            .collect();
+
+        Ok(edges)
+    }
+
+    async fn get_area_id_by_node_id(&self, node_id: i32) -> Result<i32, sqlx::Error> {
+        self.store
+            .lock()
+            .node_area_id(node_id)
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn update_edge(
+        &self,
+        node_a_id: i32,
+        node_b_id: i32,
+        weight: i32,
+    ) -> Result<(), sqlx::Error> {
+        for edge in self.store.lock().edges.iter_mut() {
+            if (edge.node_a_id == node_a_id && edge.node_b_id == node_b_id)
+                || (edge.node_a_id == node_b_id && edge.node_b_id == node_a_id)
+            {
+                edge.weight = weight;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+pub mod auth_repository;
+pub mod map_repository;
+pub mod order_repository;
+pub mod tow_truck_repository;
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+
+use crate::models::graph::{Edge, Node};
+use crate::models::order::{CompletedOrder, Order, OrderOffer};
+use crate::models::user::{ApiKey, Dispatcher, Session, User};
+
+#[derive(Clone, Debug)]
+pub struct TowTruckRow {
+    pub id: i32,
+    pub driver_id: i32,
+    pub status: String,
+    pub area_id: i32,
+}
+
+#[derive(Clone, Debug)]
+pub struct PasswordResetTokenRow {
+    pub id: i32,
+    pub user_id: i32,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OrderOfferRow {
+    pub id: i32,
+    pub order_id: i32,
+    pub tow_truck_id: i32,
+    pub status: String,
+    pub offered_at: DateTime<Utc>,
+    pub responded_at: Option<DateTime<Utc>>,
+}
+
+impl OrderOfferRow {
+    pub fn to_entity(&self) -> OrderOffer {
+        OrderOffer {
+            id: self.id,
+            order_id: self.order_id,
+            tow_truck_id: self.tow_truck_id,
+        }
+    }
+}
+
+// MySQL のテーブルに相当するデータ。ID は各 Vec の末尾の値に続けて採番する
+#[derive(Debug, Default)]
+pub struct Tables {
+    pub users: Vec<User>,
+    pub sessions: Vec<Session>,
+    pub dispatchers: Vec<Dispatcher>,
+    pub password_reset_tokens: Vec<PasswordResetTokenRow>,
+    pub api_keys: Vec<ApiKey>,
+    // (ノード, エリア ID)
+    pub nodes: Vec<(Node, i32)>,
+    pub edges: Vec<Edge>,
+    pub tow_trucks: Vec<TowTruckRow>,
+    // (レッカー車 ID, ノード ID)。後に追加したものが最新の位置
+    pub locations: Vec<(i32, i32)>,
+    pub orders: Vec<Order>,
+    pub completed_orders: Vec<CompletedOrder>,
+    pub order_offers: Vec<OrderOfferRow>,
+}
+
+impl Tables {
+    pub fn find_user(&self, id: i32) -> Option<&User> {
+        self.users.iter().find(|user| user.id == id)
+    }
+
+    pub fn node_area_id(&self, node_id: i32) -> Option<i32> {
+        self.nodes
+            .iter()
+            .find(|(node, _)| node.id == node_id)
+            .map(|(_, area_id)| *area_id)
+    }
+
+    pub fn tow_truck_node_id(&self, tow_truck_id: i32) -> Option<i32> {
+        self.locations
+            .iter()
+            .rev()
+            .find(|(id, _)| *id == tow_truck_id)
+            .map(|(_, node_id)| *node_id)
+    }
+}
+
+// 各リポジトリで共有するストア。コネクションプールと同じようにクローンして渡す
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    tables: Arc<Mutex<Tables>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap()
+    }
+
+    pub fn insert_user(&self, username: &str, role: &str) -> i32 {
+        let mut tables = self.lock();
+        let id = next_id(tables.users.iter().map(|user| user.id));
+        tables.users.push(User {
+            id,
+            username: username.to_string(),
+            password: String::new(),
+            profile_image: "default.png".to_string(),
+            role: role.to_string(),
+            is_disabled: false,
+        });
+        id
+    }
+
+    pub fn insert_dispatcher(&self, user_id: i32, area_id: i32) -> i32 {
+        let mut tables = self.lock();
+        let id = next_id(tables.dispatchers.iter().map(|dispatcher| dispatcher.id));
+        tables.dispatchers.push(Dispatcher {
+            id,
+            user_id,
+            area_id,
+        });
+        id
+    }
+
+    pub fn insert_node(&self, id: i32, area_id: i32) {
+        self.lock().nodes.push((Node { id, x: 0, y: 0 }, area_id));
+    }
+
+    pub fn insert_edge(&self, node_a_id: i32, node_b_id: i32, weight: i32) {
+        self.lock().edges.push(Edge {
+            node_a_id,
+            node_b_id,
+            weight,
+        });
+    }
+
+    pub fn insert_tow_truck(
+        &self,
+        driver_id: i32,
+        area_id: i32,
+        node_id: i32,
+        status: &str,
+    ) -> i32 {
+        let mut tables = self.lock();
+        let id = next_id(tables.tow_trucks.iter().map(|tow_truck| tow_truck.id));
+        tables.tow_trucks.push(TowTruckRow {
+            id,
+            driver_id,
+            status: status.to_string(),
+            area_id,
+        });
+        tables.locations.push((id, node_id));
+        id
+    }
+
+    pub fn insert_order(&self, client_id: i32, node_id: i32, car_value: f64) -> i32 {
+        let mut tables = self.lock();
+        let id = next_id(tables.orders.iter().map(|order| order.id));
+        tables.orders.push(Order {
+            id,
+            client_id,
+            dispatcher_id: None,
+            tow_truck_id: None,
+            status: "pending".to_string(),
+            node_id,
+            car_value,
+            order_time: Utc::now(),
+            completed_time: None,
+        });
+        id
+    }
+}
+
+pub fn next_id(ids: impl Iterator<Item = i32>) -> i32 {
+    ids.max().unwrap_or(0) + 1
+}
+
+// LIMIT / OFFSET に相当する。page_size が -1 の場合はすべて返す
+pub fn paginate<T>(items: Vec<T>, page: i32, page_size: i32) -> Vec<T> {
+    if page_size < 0 {
+        return items;
+    }
+    items
+        .into_iter()
+        .skip((page * page_size).max(0) as usize)
+        .take(page_size as usize)
+        .collect()
+}
@@ -0,0 +1,388 @@
+use crate::domains::dto::order::OrderDto;
+use crate::domains::order_service::OrderRepository;
+use crate::errors::AppError;
+use crate::models::order::{CompletedOrder, Order, OrderOffer};
+use chrono::{DateTime, Duration, Utc};
+
+use super::{next_id, paginate, MemoryStore, OrderOfferRow, Tables};
+
+#[derive(Debug)]
+pub struct MemoryOrderRepository {
+    store: MemoryStore,
+}
+
+impl MemoryOrderRepository {
+    pub fn new(store: MemoryStore) -> Self {
+        MemoryOrderRepository { store }
+    }
+}
+
+// MySQL 実装と同じく、ノードが存在しない依頼は結果に含めない
+fn to_order_dto(tables: &Tables, order: &Order) -> Option<OrderDto> {
+    let area_id = tables.node_area_id(order.node_id)?;
+    let dispatcher = order.dispatcher_id.and_then(|dispatcher_id| {
+        tables
+            .dispatchers
+            .iter()
+            .find(|dispatcher| dispatcher.id == dispatcher_id)
+    });
+    let tow_truck = order
+        .tow_truck_id
+        .and_then(|tow_truck_id| tables.tow_trucks.iter().find(|row| row.id == tow_truck_id));
+    let username = |user_id: i32| tables.find_user(user_id).map(|user| user.username.clone());
+
+    Some(OrderDto {
+        id: order.id,
+        client_id: order.client_id,
+        client_username: username(order.client_id),
+        dispatcher_id: order.dispatcher_id,
+        dispatcher_user_id: dispatcher.map(|dispatcher| dispatcher.user_id),
+        dispatcher_username: dispatcher.and_then(|dispatcher| username(dispatcher.user_id)),
+        tow_truck_id: order.tow_truck_id,
+        driver_user_id: tow_truck.map(|tow_truck| tow_truck.driver_id),
+        driver_username: tow_truck.and_then(|tow_truck| username(tow_truck.driver_id)),
+        status: order.status.clone(),
+        node_id: order.node_id,
+        area_id,
+        car_value: order.car_value,
+        order_time: order.order_time,
+        completed_time: order.completed_time,
+    })
+}
+
+fn is_offer_active(offer: &OrderOfferRow, timeout_secs: i64) -> bool {
+    offer.offered_at >= Utc::now() - Duration::seconds(timeout_secs)
+}
+
+fn push_order_offer(tables: &mut Tables, order_id: i32, tow_truck_id: i32) {
+    let id = next_id(tables.order_offers.iter().map(|offer| offer.id));
+    tables.order_offers.push(OrderOfferRow {
+        id,
+        order_id,
+        tow_truck_id,
+        status: "offered".to_string(),
+        offered_at: Utc::now(),
+        responded_at: None,
+    });
+}
+
+// 提示中のオファーにだけ応答できる。応答済みなら false
+fn respond_order_offer(tables: &mut Tables, offer_id: i32, status: &str) -> bool {
+    match tables
+        .order_offers
+        .iter_mut()
+        .find(|offer| offer.id == offer_id && offer.status == "offered")
+    {
+        Some(offer) => {
+            offer.status = status.to_string();
+            offer.responded_at = Some(Utc::now());
+            true
+        }
+        None => false,
+    }
+}
+
+fn set_tow_truck_status(tables: &mut Tables, tow_truck_id: i32, status: &str) {
+    if let Some(row) = tables
+        .tow_trucks
+        .iter_mut()
+        .find(|row| row.id == tow_truck_id)
+    {
+        row.status = status.to_string();
+    }
+}
+
+impl OrderRepository for MemoryOrderRepository {
+    async fn find_order_by_id(&self, id: i32) -> Result<Order, AppError> {
+        let order = self
+            .store
+            .lock()
+            .orders
+            .iter()
+            .find(|order| order.id == id)
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)?;
+
+        Ok(order)
+    }
+
+    async fn update_order_status(&self, order_id: i32, status: &str) -> Result<(), AppError> {
+        if let Some(order) = self
+            .store
+            .lock()
+            .orders
+            .iter_mut()
+            .find(|order| order.id == order_id)
+        {
+            order.status = status.to_string();
+        }
+
+        Ok(())
+    }
+
+    async fn get_paginated_orders(
+        &self,
+        page: i32,
+        page_size: i32,
+        sort_by: Option<String>,
+        sort_order: Option<String>,
+        status: Option<String>,
+        area: Option<i32>,
+    ) -> Result<Vec<OrderDto>, AppError> {
+        let tables = self.store.lock();
+        let mut orders: Vec<OrderDto> = tables
+            .orders
+            .iter()
+            .filter_map(|order| to_order_dto(&tables, order))
+            .filter(|order| {
+                status
+                    .as_deref()
+                    .is_none_or(|status| order.status == status)
+            })
+            .filter(|order| area.is_none_or(|area| order.area_id == area))
+            .collect();
+
+        match sort_by.as_deref() {
+            Some("car_value") => orders.sort_by(|a, b| a.car_value.total_cmp(&b.car_value)),
+            Some("status") => orders.sort_by(|a, b| a.status.cmp(&b.status)),
+            _ => orders.sort_by_key(|order| order.order_time),
+        }
+        if matches!(sort_order.as_deref(), Some("DESC") | Some("desc")) {
+            orders.reverse();
+        }
+
+        Ok(paginate(orders, page, page_size))
+    }
+
+    async fn create_order(
+        &self,
+        client_id: i32,
+        node_id: i32,
+        car_value: f64,
+    ) -> Result<(), AppError> {
+        let mut tables = self.store.lock();
+        // 外部キー制約の代わり
+        if tables.find_user(client_id).is_none() || tables.node_area_id(node_id).is_none() {
+            return Err(AppError::BadRequest);
+        }
+
+        let id = next_id(tables.orders.iter().map(|order| order.id));
+        tables.orders.push(Order {
+            id,
+            client_id,
+            dispatcher_id: None,
+            tow_truck_id: None,
+            status: "pending".to_string(),
+            node_id,
+            car_value,
+            order_time: Utc::now(),
+            completed_time: None,
+        });
+
+        Ok(())
+    }
+
+    async fn complete_order(
+        &self,
+        order_id: i32,
+        tow_truck_id: i32,
+        completed_time: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        let mut tables = self.store.lock();
+        if !tables.tow_trucks.iter().any(|row| row.id == tow_truck_id) {
+            return Err(AppError::BadRequest);
+        }
+        // order_id・tow_truck_id の UNIQUE 制約の代わり
+        if tables.completed_orders.iter().any(|completed_order| {
+            completed_order.order_id == order_id || completed_order.tow_truck_id == tow_truck_id
+        }) {
+            return Err(AppError::Conflict);
+        }
+        let order = tables
+            .orders
+            .iter_mut()
+            .find(|order| order.id == order_id)
+            .ok_or(AppError::BadRequest)?;
+        order.status = "completed".to_string();
+        order.completed_time = Some(completed_time);
+        let order = order.clone();
+
+        let id = next_id(tables.completed_orders.iter().map(|order| order.id));
+        tables.completed_orders.push(CompletedOrder {
+            id,
+            order_id,
+            tow_truck_id,
+            order_time: Some(order.order_time),
+            completed_time,
+            car_value: order.car_value,
+        });
+
+        Ok(())
+    }
+
+    async fn get_all_completed_orders(&self) -> Result<Vec<CompletedOrder>, AppError> {
+        Ok(self.store.lock().completed_orders.clone())
+    }
+
+    async fn offer_order(
+        &self,
+        order_id: i32,
+        dispatcher_id: i32,
+        tow_truck_id: i32,
+    ) -> Result<(), AppError> {
+        let mut tables = self.store.lock();
+        if let Some(order) = tables.orders.iter_mut().find(|order| order.id == order_id) {
+            order.dispatcher_id = Some(dispatcher_id);
+            order.tow_truck_id = Some(tow_truck_id);
+            order.status = "offered".to_string();
+        }
+        push_order_offer(&mut tables, order_id, tow_truck_id);
+        set_tow_truck_status(&mut tables, tow_truck_id, "busy");
+
+        Ok(())
+    }
+
+    async fn accept_order_offer(&self, offer: &OrderOffer) -> Result<bool, AppError> {
+        let mut tables = self.store.lock();
+        if !respond_order_offer(&mut tables, offer.id, "accepted") {
+            return Ok(false);
+        }
+        if let Some(order) = tables
+            .orders
+            .iter_mut()
+            .find(|order| order.id == offer.order_id)
+        {
+            order.status = "dispatched".to_string();
+        }
+
+        Ok(true)
+    }
+
+    async fn close_order_offer(
+        &self,
+        offer: &OrderOffer,
+        status: &str,
+        next_tow_truck_id: Option<i32>,
+    ) -> Result<bool, AppError> {
+        let mut tables = self.store.lock();
+        if !respond_order_offer(&mut tables, offer.id, status) {
+            return Ok(false);
+        }
+        set_tow_truck_status(&mut tables, offer.tow_truck_id, "available");
+
+        if let Some(order) = tables
+            .orders
+            .iter_mut()
+            .find(|order| order.id == offer.order_id)
+        {
+            order.tow_truck_id = next_tow_truck_id;
+            order.status = match next_tow_truck_id {
+                Some(_) => "offered".to_string(),
+                None => "pending".to_string(),
+            };
+        }
+        if let Some(tow_truck_id) = next_tow_truck_id {
+            push_order_offer(&mut tables, offer.order_id, tow_truck_id);
+            set_tow_truck_status(&mut tables, tow_truck_id, "busy");
+        }
+
+        Ok(true)
+    }
+
+    async fn find_active_order_offer(
+        &self,
+        order_id: i32,
+        tow_truck_id: i32,
+        timeout_secs: i64,
+    ) -> Result<Option<OrderOffer>, AppError> {
+        let offer = self
+            .store
+            .lock()
+            .order_offers
+            .iter()
+            .find(|offer| {
+                offer.order_id == order_id
+                    && offer.tow_truck_id == tow_truck_id
+                    && offer.status == "offered"
+                    && is_offer_active(offer, timeout_secs)
+            })
+            .map(OrderOfferRow::to_entity);
+
+        Ok(offer)
+    }
+
+    async fn find_expired_order_offers(
+        &self,
+        timeout_secs: i64,
+    ) -> Result<Vec<OrderOffer>, AppError> {
+        let tables = self.store.lock();
+        let mut offers: Vec<&OrderOfferRow> = tables
+            .order_offers
+            .iter()
+            .filter(|offer| offer.status == "offered" && !is_offer_active(offer, timeout_secs))
+            .collect();
+        offers.sort_by_key(|offer| offer.offered_at);
+
+        Ok(offers.into_iter().map(OrderOfferRow::to_entity).collect())
+    }
+
+    async fn find_active_order_by_tow_truck_id(
+        &self,
+        tow_truck_id: i32,
+    ) -> Result<Option<Order>, AppError> {
+        let order = self
+            .store
+            .lock()
+            .orders
+            .iter()
+            .filter(|order| {
+                order.tow_truck_id == Some(tow_truck_id)
+                    && ["offered", "dispatched"].contains(&order.status.as_str())
+            })
+            .max_by_key(|order| order.order_time)
+            .cloned();
+
+        Ok(order)
+    }
+
+    async fn get_completed_orders_by_tow_truck_id(
+        &self,
+        tow_truck_id: i32,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<Order>, AppError> {
+        let mut orders: Vec<Order> = self
+            .store
+            .lock()
+            .orders
+            .iter()
+            .filter(|order| order.tow_truck_id == Some(tow_truck_id) && order.status == "completed")
+            .cloned()
+            .collect();
+        orders.sort_by(|a, b| {
+            b.completed_time
+                .cmp(&a.completed_time)
+                .then(b.order_time.cmp(&a.order_time))
+        });
+
+        Ok(paginate(orders, page, page_size))
+    }
+
+    async fn find_offered_tow_truck_ids(&self, order_id: i32) -> Result<Vec<i32>, AppError> {
+        let mut tow_truck_ids: Vec<i32> = self
+            .store
+            .lock()
+            .order_offers
+            .iter()
+            .filter(|offer| {
+                offer.order_id == order_id
+                    && ["declined", "expired"].contains(&offer.status.as_str())
+            })
+            .map(|offer| offer.tow_truck_id)
+            .collect();
+        tow_truck_ids.sort();
+        tow_truck_ids.dedup();
+
+        Ok(tow_truck_ids)
+    }
+}
@@ -0,0 +1,84 @@
+use crate::domains::tow_truck_service::TowTruckRepository;
+use crate::errors::AppError;
+use crate::models::tow_truck::TowTruck;
+
+use super::{paginate, MemoryStore, Tables, TowTruckRow};
+
+#[derive(Debug)]
+pub struct MemoryTowTruckRepository {
+    store: MemoryStore,
+}
+
+impl MemoryTowTruckRepository {
+    pub fn new(store: MemoryStore) -> Self {
+        MemoryTowTruckRepository { store }
+    }
+}
+
+// MySQL 実装と同じく、位置情報のないレッカー車は結果に含めない
+fn to_tow_truck(tables: &Tables, row: &TowTruckRow) -> Option<TowTruck> {
+    let driver = tables.find_user(row.driver_id)?;
+    let node_id = tables.tow_truck_node_id(row.id)?;
+
+    Some(TowTruck {
+        id: row.id,
+        driver_id: row.driver_id,
+        driver_username: Some(driver.username.clone()),
+        status: row.status.clone(),
+        area_id: row.area_id,
+        node_id,
+    })
+}
+
+impl TowTruckRepository for MemoryTowTruckRepository {
+    async fn get_paginated_tow_trucks(
+        &self,
+        page: i32,
+        page_size: i32,
+        status: Option<String>,
+        area_id: Option<i32>,
+    ) -> Result<Vec<TowTruck>, AppError> {
+        let tables = self.store.lock();
+        let mut tow_trucks: Vec<TowTruck> = tables
+            .tow_trucks
+            .iter()
+            .filter(|row| status.as_deref().is_none_or(|status| row.status == status))
+            .filter(|row| area_id.is_none_or(|area_id| row.area_id == area_id))
+            .filter_map(|row| to_tow_truck(&tables, row))
+            .collect();
+        tow_trucks.sort_by_key(|tow_truck| tow_truck.id);
+
+        Ok(paginate(tow_trucks, page, page_size))
+    }
+
+    async fn update_location(&self, tow_truck_id: i32, node_id: i32) -> Result<(), AppError> {
+        self.store.lock().locations.push((tow_truck_id, node_id));
+
+        Ok(())
+    }
+
+    async fn find_tow_truck_by_id(&self, id: i32) -> Result<Option<TowTruck>, AppError> {
+        let tables = self.store.lock();
+        let tow_truck = tables
+            .tow_trucks
+            .iter()
+            .find(|row| row.id == id)
+            .and_then(|row| to_tow_truck(&tables, row));
+
+        Ok(tow_truck)
+    }
+
+    async fn find_tow_truck_by_driver_id(
+        &self,
+        driver_id: i32,
+    ) -> Result<Option<TowTruck>, AppError> {
+        let tables = self.store.lock();
+        let tow_truck = tables
+            .tow_trucks
+            .iter()
+            .find(|row| row.driver_id == driver_id)
+            .and_then(|row| to_tow_truck(&tables, row));
+
+        Ok(tow_truck)
+    }
+}
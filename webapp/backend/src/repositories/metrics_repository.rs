@@ -0,0 +1,55 @@
+use crate::{
+    domains::{health_service::PoolStatus, metrics_service::MetricsRepository},
+    errors::AppError,
+    infrastructure::db::{with_pool, DbPool},
+};
+use tracing::instrument;
+
+const PENDING_ORDERS_BY_AREA_QUERY: &str = "SELECT n.area_id, COUNT(*)
+    FROM orders o
+    JOIN nodes n ON o.node_id = n.id
+    WHERE o.status = 'pending'
+    GROUP BY n.area_id";
+const AVAILABLE_TOW_TRUCKS_BY_AREA_QUERY: &str = "SELECT area_id, COUNT(*)
+    FROM tow_trucks
+    WHERE status = 'available'
+    GROUP BY area_id";
+
+#[derive(Debug)]
+pub struct MetricsRepositoryImpl {
+    pool: DbPool,
+}
+
+impl MetricsRepositoryImpl {
+    pub fn new(pool: DbPool) -> Self {
+        MetricsRepositoryImpl { pool }
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn count_by_area(&self, query: &str) -> Result<Vec<(i32, i64)>, AppError> {
+        let counts = with_pool!(&self.pool, |pool| sqlx::query_as(query)
+            .fetch_all(pool)
+            .await?);
+
+        Ok(counts)
+    }
+}
+
+impl MetricsRepository for MetricsRepositoryImpl {
+    fn pool_status(&self) -> PoolStatus {
+        with_pool!(&self.pool, |pool| PoolStatus {
+            size: pool.size(),
+            idle: pool.num_idle(),
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn count_pending_orders_by_area(&self) -> Result<Vec<(i32, i64)>, AppError> {
+        self.count_by_area(PENDING_ORDERS_BY_AREA_QUERY).await
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn count_available_tow_trucks_by_area(&self) -> Result<Vec<(i32, i64)>, AppError> {
+        self.count_by_area(AVAILABLE_TOW_TRUCKS_BY_AREA_QUERY).await
+    }
+}
@@ -1,4 +1,8 @@
 pub mod auth_repository;
+pub mod health_repository;
 pub mod map_repository;
+#[cfg(test)]
+pub mod memory;
+pub mod metrics_repository;
 pub mod order_repository;
 pub mod tow_truck_repository;
@@ -1,48 +1,56 @@
 use crate::domains::dto::order::OrderDto;
 use crate::domains::order_service::OrderRepository;
 use crate::errors::AppError;
-use crate::models::order::{CompletedOrder, Order};
+use crate::infrastructure::db::{is_unique_violation, with_pool, DbPool};
+use crate::models::order::{CompletedOrder, Order, OrderOffer};
 use chrono::{DateTime, Utc};
-use sqlx::mysql::MySqlPool;
+use tracing::instrument;
 
 #[derive(Debug)]
 pub struct OrderRepositoryImpl {
-    pool: MySqlPool,
+    pool: DbPool,
 }
 
 impl OrderRepositoryImpl {
-    pub fn new(pool: MySqlPool) -> Self {
+    pub fn new(pool: DbPool) -> Self {
         OrderRepositoryImpl { pool }
     }
 }
 
 impl OrderRepository for OrderRepositoryImpl {
+    #[instrument(level = "debug", skip_all)]
     async fn find_order_by_id(&self, id: i32) -> Result<Order, AppError> {
-        let order = sqlx::query_as::<_, Order>(
-            "SELECT 
-                *
-            FROM
-                orders 
-            WHERE
-                id = ?",
-        )
-        .bind(id)
-        .fetch_one(&self.pool)
-        .await?;
-
-        Ok(order)
+        with_pool!(&self.pool, |pool| {
+            let order = sqlx::query_as::<_, Order>(
+                "SELECT 
+                    *
+                FROM
+                    orders 
+                WHERE
+                    id = ?",
+            )
+            .bind(id)
+            .fetch_one(pool)
+            .await?;
+
+            Ok(order)
+        })
     }
 
+    #[instrument(level = "debug", skip_all)]
     async fn update_order_status(&self, order_id: i32, status: &str) -> Result<(), AppError> {
-        sqlx::query("UPDATE orders SET status = ? WHERE id = ?")
-            .bind(status)
-            .bind(order_id)
-            .execute(&self.pool)
-            .await?;
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("UPDATE orders SET status = ? WHERE id = ?")
+                .bind(status)
+                .bind(order_id)
+                .execute(pool)
+                .await?;
 
-        Ok(())
+            Ok(())
+        })
     }
 
+    #[instrument(level = "debug", skip_all)]
     async fn get_paginated_orders(
         &self,
         page: i32,
@@ -106,103 +114,370 @@ impl OrderRepository for OrderRepositoryImpl {
             where_clause, order_clause
         );
 
-        let orders = match (status, area) {
-            (Some(status), Some(area)) => {
-                sqlx::query_as::<_, OrderDto>(&sql)
-                    .bind(status)
-                    .bind(area)
-                    .bind(page_size)
-                    .bind(offset)
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-            (None, Some(area)) => {
-                sqlx::query_as::<_, OrderDto>(&sql)
-                    .bind(area)
-                    .bind(page_size)
-                    .bind(offset)
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-            (Some(status), None) => {
-                sqlx::query_as::<_, OrderDto>(&sql)
-                    .bind(status)
-                    .bind(page_size)
-                    .bind(offset)
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-            _ => {
-                sqlx::query_as::<_, OrderDto>(&sql)
-                    .bind(page_size)
-                    .bind(offset)
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-        };
+        with_pool!(&self.pool, |pool| {
+            let orders = match (status, area) {
+                (Some(status), Some(area)) => {
+                    sqlx::query_as::<_, OrderDto>(&sql)
+                        .bind(status)
+                        .bind(area)
+                        .bind(page_size)
+                        .bind(offset)
+                        .fetch_all(pool)
+                        .await?
+                }
+                (None, Some(area)) => {
+                    sqlx::query_as::<_, OrderDto>(&sql)
+                        .bind(area)
+                        .bind(page_size)
+                        .bind(offset)
+                        .fetch_all(pool)
+                        .await?
+                }
+                (Some(status), None) => {
+                    sqlx::query_as::<_, OrderDto>(&sql)
+                        .bind(status)
+                        .bind(page_size)
+                        .bind(offset)
+                        .fetch_all(pool)
+                        .await?
+                }
+                _ => {
+                    sqlx::query_as::<_, OrderDto>(&sql)
+                        .bind(page_size)
+                        .bind(offset)
+                        .fetch_all(pool)
+                        .await?
+                }
+            };
 
-        Ok(orders)
+            Ok(orders)
+        })
     }
 
+    #[instrument(level = "debug", skip_all)]
     async fn create_order(
         &self,
         client_id: i32,
         node_id: i32,
         car_value: f64,
     ) -> Result<(), AppError> {
-        sqlx::query("INSERT INTO orders (client_id, node_id, status, car_value) VALUES (?, ?, 'pending', ?)")
-            .bind(client_id)
-            .bind(node_id)
-            .bind(car_value)
-            .execute(&self.pool)
-            .await?;
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("INSERT INTO orders (client_id, node_id, status, car_value) VALUES (?, ?, 'pending', ?)")
+                .bind(client_id)
+                .bind(node_id)
+                .bind(car_value)
+                .execute(pool)
+                .await?;
 
-        Ok(())
+            Ok(())
+        })
     }
 
-    async fn update_order_dispatched(
+    #[instrument(level = "debug", skip_all)]
+    async fn complete_order(
         &self,
-        id: i32,
-        dispatcher_id: i32,
+        order_id: i32,
         tow_truck_id: i32,
+        completed_time: DateTime<Utc>,
     ) -> Result<(), AppError> {
-        sqlx::query(
-            "UPDATE orders SET dispatcher_id = ?, tow_truck_id = ?, status = 'dispatched' WHERE id = ?",
-        )
-        .bind(dispatcher_id)
-        .bind(tow_truck_id)
-        .bind(id)
-        .execute(&self.pool)
-        .await?;
-
-        Ok(())
+        with_pool!(&self.pool, |pool| {
+            let mut tx = pool.begin().await?;
+
+            sqlx::query("UPDATE orders SET status = 'completed', completed_time = ? WHERE id = ?")
+                .bind(completed_time)
+                .bind(order_id)
+                .execute(&mut tx)
+                .await?;
+
+            // order_id・tow_truck_id は UNIQUE のため、記録済みの場合は 409 にする
+            match sqlx::query("INSERT INTO completed_orders (order_id, tow_truck_id, completed_time) VALUES (?, ?, ?)")
+                .bind(order_id)
+                .bind(tow_truck_id)
+                .bind(completed_time)
+                .execute(&mut tx)
+                .await
+            {
+                Ok(_) => {}
+                Err(err) if is_unique_violation(&err) => return Err(AppError::Conflict),
+                Err(err) => return Err(err.into()),
+            }
+
+            tx.commit().await?;
+
+            Ok(())
+        })
     }
 
-    async fn create_completed_order(
+    #[instrument(level = "debug", skip_all)]
+    async fn get_all_completed_orders(&self) -> Result<Vec<CompletedOrder>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let orders = sqlx::query_as::<_, CompletedOrder>(
+                "SELECT co.id, co.order_id, co.tow_truck_id, co.order_time, co.completed_time, o.car_value
+                        FROM completed_orders co
+                        JOIN orders o ON co.order_id = o.id"
+                )
+                .fetch_all(pool)
+                .await?;
+
+            Ok(orders)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn offer_order(
         &self,
         order_id: i32,
+        dispatcher_id: i32,
         tow_truck_id: i32,
-        completed_time: DateTime<Utc>,
     ) -> Result<(), AppError> {
-        sqlx::query("INSERT INTO completed_orders (order_id, tow_truck_id, completed_time) VALUES (?, ?, ?)")
+        with_pool!(&self.pool, |pool| {
+            let mut tx = pool.begin().await?;
+
+            sqlx::query(
+                "UPDATE orders SET dispatcher_id = ?, tow_truck_id = ?, status = 'offered' WHERE id = ?",
+            )
+            .bind(dispatcher_id)
+            .bind(tow_truck_id)
+            .bind(order_id)
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query("INSERT INTO order_offers (order_id, tow_truck_id) VALUES (?, ?)")
+                .bind(order_id)
+                .bind(tow_truck_id)
+                .execute(&mut tx)
+                .await?;
+
+            sqlx::query("UPDATE tow_trucks SET status = 'busy' WHERE id = ?")
+                .bind(tow_truck_id)
+                .execute(&mut tx)
+                .await?;
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn accept_order_offer(&self, offer: &OrderOffer) -> Result<bool, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let mut tx = pool.begin().await?;
+
+            let result = sqlx::query(
+                "UPDATE order_offers SET status = 'accepted', responded_at = CURRENT_TIMESTAMP WHERE id = ? AND status = 'offered'",
+            )
+            .bind(offer.id)
+            .execute(&mut tx)
+            .await?;
+            if result.rows_affected() == 0 {
+                return Ok(false);
+            }
+
+            sqlx::query("UPDATE orders SET status = 'dispatched' WHERE id = ?")
+                .bind(offer.order_id)
+                .execute(&mut tx)
+                .await?;
+
+            tx.commit().await?;
+
+            Ok(true)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn close_order_offer(
+        &self,
+        offer: &OrderOffer,
+        status: &str,
+        next_tow_truck_id: Option<i32>,
+    ) -> Result<bool, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let mut tx = pool.begin().await?;
+
+            let result = sqlx::query(
+                "UPDATE order_offers SET status = ?, responded_at = CURRENT_TIMESTAMP WHERE id = ? AND status = 'offered'",
+            )
+            .bind(status)
+            .bind(offer.id)
+            .execute(&mut tx)
+            .await?;
+            if result.rows_affected() == 0 {
+                return Ok(false);
+            }
+
+            sqlx::query("UPDATE tow_trucks SET status = 'available' WHERE id = ?")
+                .bind(offer.tow_truck_id)
+                .execute(&mut tx)
+                .await?;
+
+            match next_tow_truck_id {
+                Some(tow_truck_id) => {
+                    sqlx::query(
+                        "UPDATE orders SET tow_truck_id = ?, status = 'offered' WHERE id = ?",
+                    )
+                    .bind(tow_truck_id)
+                    .bind(offer.order_id)
+                    .execute(&mut tx)
+                    .await?;
+
+                    sqlx::query("INSERT INTO order_offers (order_id, tow_truck_id) VALUES (?, ?)")
+                        .bind(offer.order_id)
+                        .bind(tow_truck_id)
+                        .execute(&mut tx)
+                        .await?;
+
+                    sqlx::query("UPDATE tow_trucks SET status = 'busy' WHERE id = ?")
+                        .bind(tow_truck_id)
+                        .execute(&mut tx)
+                        .await?;
+                }
+                None => {
+                    sqlx::query(
+                        "UPDATE orders SET tow_truck_id = NULL, status = 'pending' WHERE id = ?",
+                    )
+                    .bind(offer.order_id)
+                    .execute(&mut tx)
+                    .await?;
+                }
+            }
+
+            tx.commit().await?;
+
+            Ok(true)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn find_active_order_offer(
+        &self,
+        order_id: i32,
+        tow_truck_id: i32,
+        timeout_secs: i64,
+    ) -> Result<Option<OrderOffer>, AppError> {
+        with_pool!(&self.pool, |pool, dialect| {
+            let offer = sqlx::query_as::<_, OrderOffer>(&format!(
+                "SELECT
+                    *
+                FROM
+                    order_offers
+                WHERE
+                    order_id = ?
+                AND
+                    tow_truck_id = ?
+                AND
+                    status = 'offered'
+                AND
+                    offered_at >= {}",
+                dialect.seconds_ago
+            ))
             .bind(order_id)
             .bind(tow_truck_id)
-            .bind(completed_time)
-            .execute(&self.pool)
+            .bind(timeout_secs)
+            .fetch_optional(pool)
             .await?;
 
-        Ok(())
+            Ok(offer)
+        })
     }
 
-    async fn get_all_completed_orders(&self) -> Result<Vec<CompletedOrder>, AppError> {
-        let orders = sqlx::query_as::<_, CompletedOrder>(
-            "SELECT co.id, co.order_id, co.tow_truck_id, co.order_time, co.completed_time, o.car_value
-                    FROM completed_orders co
-                    JOIN orders o ON co.order_id = o.id"
+    #[instrument(level = "debug", skip_all)]
+    async fn find_expired_order_offers(
+        &self,
+        timeout_secs: i64,
+    ) -> Result<Vec<OrderOffer>, AppError> {
+        with_pool!(&self.pool, |pool, dialect| {
+            let offers = sqlx::query_as::<_, OrderOffer>(&format!(
+                "SELECT
+                    *
+                FROM
+                    order_offers
+                WHERE
+                    status = 'offered'
+                AND
+                    offered_at < {}
+                ORDER BY
+                    offered_at",
+                dialect.seconds_ago
+            ))
+            .bind(timeout_secs)
+            .fetch_all(pool)
+            .await?;
+
+            Ok(offers)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn find_active_order_by_tow_truck_id(
+        &self,
+        tow_truck_id: i32,
+    ) -> Result<Option<Order>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let order = sqlx::query_as::<_, Order>(
+                "SELECT
+                    *
+                FROM
+                    orders
+                WHERE
+                    tow_truck_id = ?
+                AND
+                    status IN ('offered', 'dispatched')
+                ORDER BY
+                    order_time DESC
+                LIMIT 1",
+            )
+            .bind(tow_truck_id)
+            .fetch_optional(pool)
+            .await?;
+
+            Ok(order)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn get_completed_orders_by_tow_truck_id(
+        &self,
+        tow_truck_id: i32,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<Order>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let orders = sqlx::query_as::<_, Order>(
+                "SELECT
+                    *
+                FROM
+                    orders
+                WHERE
+                    tow_truck_id = ?
+                AND
+                    status = 'completed'
+                ORDER BY
+                    completed_time DESC, order_time DESC
+                LIMIT ?
+                OFFSET ?",
             )
-            .fetch_all(&self.pool)
+            .bind(tow_truck_id)
+            .bind(page_size)
+            .bind(page * page_size)
+            .fetch_all(pool)
+            .await?;
+
+            Ok(orders)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn find_offered_tow_truck_ids(&self, order_id: i32) -> Result<Vec<i32>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let tow_truck_ids = sqlx::query_scalar(
+                "SELECT DISTINCT tow_truck_id FROM order_offers WHERE order_id = ? AND status IN ('declined', 'expired')",
+            )
+            .bind(order_id)
+            .fetch_all(pool)
             .await?;
 
-        Ok(orders)
+            Ok(tow_truck_ids)
+        })
     }
 }
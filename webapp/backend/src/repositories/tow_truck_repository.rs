@@ -1,20 +1,22 @@
 use crate::domains::tow_truck_service::TowTruckRepository;
 use crate::errors::AppError;
+use crate::infrastructure::db::{with_pool, DbPool};
 use crate::models::tow_truck::TowTruck;
-use sqlx::mysql::MySqlPool;
+use tracing::instrument;
 
 #[derive(Debug)]
 pub struct TowTruckRepositoryImpl {
-    pool: MySqlPool,
+    pool: DbPool,
 }
 
 impl TowTruckRepositoryImpl {
-    pub fn new(pool: MySqlPool) -> Self {
+    pub fn new(pool: DbPool) -> Self {
         TowTruckRepositoryImpl { pool }
     }
 }
 
 impl TowTruckRepository for TowTruckRepositoryImpl {
+    #[instrument(level = "debug", skip_all)]
     async fn get_paginated_tow_trucks(
         &self,
         page: i32,
@@ -73,55 +75,85 @@ impl TowTruckRepository for TowTruckRepositoryImpl {
             where_clause, limit_clause, offset_clause
         );
 
-        let tow_trucks = sqlx::query_as::<_, TowTruck>(&query)
-            .fetch_all(&self.pool)
-            .await?;
+        with_pool!(&self.pool, |pool| {
+            let tow_trucks = sqlx::query_as::<_, TowTruck>(&query)
+                .fetch_all(pool)
+                .await?;
 
-        Ok(tow_trucks)
+            Ok(tow_trucks)
+        })
     }
 
+    #[instrument(level = "debug", skip_all)]
     async fn update_location(&self, tow_truck_id: i32, node_id: i32) -> Result<(), AppError> {
-        sqlx::query("INSERT INTO locations (tow_truck_id, node_id) VALUES (?, ?)")
-            .bind(tow_truck_id)
-            .bind(node_id)
-            .execute(&self.pool)
-            .await?;
-        Ok(())
+        with_pool!(&self.pool, |pool| {
+            sqlx::query("INSERT INTO locations (tow_truck_id, node_id) VALUES (?, ?)")
+                .bind(tow_truck_id)
+                .bind(node_id)
+                .execute(pool)
+                .await?;
+            Ok(())
+        })
     }
 
-    async fn update_status(&self, tow_truck_id: i32, status: &str) -> Result<(), AppError> {
-        sqlx::query("UPDATE tow_trucks SET status = ? WHERE id = ?")
-            .bind(status)
-            .bind(tow_truck_id)
-            .execute(&self.pool)
+    #[instrument(level = "debug", skip_all)]
+    async fn find_tow_truck_by_id(&self, id: i32) -> Result<Option<TowTruck>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let tow_truck = sqlx::query_as::<_, TowTruck>(
+                "SELECT
+                    tt.id, tt.driver_id, u.username AS driver_username, tt.status, l.node_id, tt.area_id
+                FROM
+                    tow_trucks tt
+                JOIN
+                    users u 
+                ON
+                    tt.driver_id = u.id
+                JOIN
+                    locations l
+                ON
+                    tt.id = l.tow_truck_id
+                WHERE
+                    tt.id = ?
+                AND
+                    l.timestamp = (SELECT MAX(timestamp) FROM locations WHERE tow_truck_id = tt.id)",
+            )
+            .bind(id)
+            .fetch_optional(pool)
             .await?;
 
-        Ok(())
+            Ok(tow_truck)
+        })
     }
 
-    async fn find_tow_truck_by_id(&self, id: i32) -> Result<Option<TowTruck>, AppError> {
-        let tow_truck = sqlx::query_as::<_, TowTruck>(
-            "SELECT
-                tt.id, tt.driver_id, u.username AS driver_username, tt.status, l.node_id, tt.area_id
-            FROM
-                tow_trucks tt
-            JOIN
-                users u 
-            ON
-                tt.driver_id = u.id
-            JOIN
-                locations l
-            ON
-                tt.id = l.tow_truck_id
-            WHERE
-                tt.id = ?
-            AND
-                l.timestamp = (SELECT MAX(timestamp) FROM locations WHERE tow_truck_id = tt.id)",
-        )
-        .bind(id)
-        .fetch_optional(&self.pool)
-        .await?;
+    #[instrument(level = "debug", skip_all)]
+    async fn find_tow_truck_by_driver_id(
+        &self,
+        driver_id: i32,
+    ) -> Result<Option<TowTruck>, AppError> {
+        with_pool!(&self.pool, |pool| {
+            let tow_truck = sqlx::query_as::<_, TowTruck>(
+                "SELECT
+                    tt.id, tt.driver_id, u.username AS driver_username, tt.status, l.node_id, tt.area_id
+                FROM
+                    tow_trucks tt
+                JOIN
+                    users u
+                ON
+                    tt.driver_id = u.id
+                JOIN
+                    locations l
+                ON
+                    tt.id = l.tow_truck_id
+                WHERE
+                    tt.driver_id = ?
+                AND
+                    l.timestamp = (SELECT MAX(timestamp) FROM locations WHERE tow_truck_id = tt.id)",
+            )
+            .bind(driver_id)
+            .fetch_optional(pool)
+            .await?;
 
-        Ok(tow_truck)
+            Ok(tow_truck)
+        })
     }
 }
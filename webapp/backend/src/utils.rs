@@ -3,6 +3,7 @@ use argon2::{
     Argon2, Params, Algorithm, Version,
 };
 use rand::Rng;
+use sha2::{Digest, Sha256};
 
 // use bcrypt::{hash, verify, DEFAULT_COST};
 
@@ -20,29 +21,58 @@ pub fn generate_session_token() -> String {
     token
 }
 
-// pub fn hash_password(password: &str) -> Result<String, AppError> {
-//     let password_bytes = password.as_bytes();
-//     let salt = SaltString::generate(&mut OsRng);
+// リセットトークンなど、推測困難なランダム値を保存するためのハッシュ
+pub fn hash_token(token: &str) -> String {
+    hash_bytes(token.as_bytes())
+}
 
-//     // Argon2 with default params (Argon2id v19)
-//     let argon2 = Argon2::default();
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
 
-//     // Hash password to PHC string ($argon2id$v=19$...)
-//     match argon2.hash_password(password_bytes, &salt) {
-//         Ok(hashed_password_bytes) => Ok(hashed_password_bytes.to_string()),
-//         Err(_) => Err(AppError::InternalServerError),
-//     }
-// }
+// パスワードハッシュに使用する Argon2id のパラメータ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordHashParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
 
-pub fn hash_password(password: &str) -> Result<String, AppError> {
+impl Default for PasswordHashParams {
+    // init.sql のシードデータと同じ m=19456, t=2, p=1
+    fn default() -> Self {
+        PasswordHashParams {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl PasswordHashParams {
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Option<Self> {
+        Params::new(memory_kib, iterations, parallelism, None).ok()?;
+        Some(PasswordHashParams {
+            memory_kib,
+            iterations,
+            parallelism,
+        })
+    }
+}
+
+pub fn hash_password(password: &str, hash_params: &PasswordHashParams) -> Result<String, AppError> {
     let password_bytes = password.as_bytes();
     let salt = SaltString::generate(&mut OsRng);
 
-    // カスタムパラメータを設定
-    let params = Params::new(1 * 1024, 1, 1, None)
-        .map_err(|_| AppError::InternalServerError)?;
+    let params = Params::new(
+        hash_params.memory_kib,
+        hash_params.iterations,
+        hash_params.parallelism,
+        None,
+    )
+    .map_err(|_| AppError::InternalServerError)?;
 
-    // Argon2 with custom params (Argon2id v19)
+    // Argon2 with configured params (Argon2id v19)
     let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
 
     // Hash password to PHC string ($argon2id$v=19$...)
@@ -58,13 +88,45 @@ pub fn verify_password(hashed_password: &str, input_password: &str) -> Result<bo
         Ok(hash) => hash,
         Err(_) => return Err(AppError::InternalServerError),
     };
-    let argon2 = Argon2::default();
+
+    // 保存されているハッシュごとに、PHC 文字列に記録されたアルゴリズム・バージョン・パラメータで検証する
+    let algorithm =
+        Algorithm::try_from(parsed_hash.algorithm).map_err(|_| AppError::InternalServerError)?;
+    let version = match parsed_hash.version {
+        Some(version) => Version::try_from(version).map_err(|_| AppError::InternalServerError)?,
+        None => Version::default(),
+    };
+    let params = Params::try_from(&parsed_hash).map_err(|_| AppError::InternalServerError)?;
+    let argon2 = Argon2::new(algorithm, version, params);
+
     match argon2.verify_password(input_password_bytes, &parsed_hash) {
         Ok(_) => Ok(true),
         Err(_) => Ok(false),
     }
 }
 
+// 現在の設定と異なるアルゴリズム・パラメータでハッシュ化されている場合は true
+pub fn needs_rehash(hashed_password: &str, hash_params: &PasswordHashParams) -> bool {
+    let parsed_hash = match PasswordHash::new(hashed_password) {
+        Ok(hash) => hash,
+        Err(_) => return true,
+    };
+    if Algorithm::try_from(parsed_hash.algorithm) != Ok(Algorithm::Argon2id)
+        || parsed_hash.version != Some(Version::V0x13 as u32)
+    {
+        return true;
+    }
+
+    match Params::try_from(&parsed_hash) {
+        Ok(params) => {
+            params.m_cost() != hash_params.memory_kib
+                || params.t_cost() != hash_params.iterations
+                || params.p_cost() != hash_params.parallelism
+        }
+        Err(_) => true,
+    }
+}
+
 // pub fn verify_password(hashed_password: &str, input_password: &str) -> Result<bool, AppError> {
 //     let input_password_bytes = input_password.as_bytes();
 //     let parsed_hash = match PasswordHash::new(hashed_password) {
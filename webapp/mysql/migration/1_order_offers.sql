@@ -0,0 +1,13 @@
+-- ドライバーへの依頼オファー（承諾・辞退・タイムアウトの履歴）
+CREATE TABLE IF NOT EXISTS order_offers (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    order_id INT NOT NULL,
+    tow_truck_id INT NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'offered',
+    offered_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    responded_at DATETIME,
+    FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE,
+    FOREIGN KEY (tow_truck_id) REFERENCES tow_trucks(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_order_offers_order_id ON order_offers (order_id);
+CREATE INDEX idx_order_offers_status_offered_at ON order_offers (status, offered_at);
@@ -0,0 +1,7 @@
+-- セッションの有効期限（絶対期限・アイドル期限）判定用
+ALTER TABLE sessions
+    ADD COLUMN created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    ADD COLUMN last_seen_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP;
+CREATE INDEX idx_sessions_created_at ON sessions (created_at);
+CREATE INDEX idx_sessions_last_seen_at ON sessions (last_seen_at);
+CREATE INDEX idx_sessions_user_id ON sessions (user_id);
@@ -0,0 +1,4 @@
+-- セッション一覧に表示する接続元の情報
+ALTER TABLE sessions
+    ADD COLUMN user_agent VARCHAR(512),
+    ADD COLUMN ip_address VARCHAR(45);